@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+use std::fmt;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::instruction::Instruction;
+
+// Where load_program_memory puts the program, and so where labels count from
+const ORIGIN: i64 = 0x200;
+
+// How deep constants can refer to other constants before giving up
+const MAX_CONSTANT_DEPTH: usize = 32;
+
+// Names that mean something as an operand and so can't be used as symbols
+const RESERVED: [&str; 9] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG"];
+
+// Something wrong with the source, at a 1-based line and column
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssembleError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl AssembleError {
+    fn new<S: Into<String>>(line: usize, column: usize, message: S)
+        -> AssembleError {
+        AssembleError { line, column, message: message.into() }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl AssembleError {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column,
+            self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+// Assemble Cowgod-style source into a ROM loaded at 0x200.
+//
+// Each line holds an optional label, then an instruction or directive, then
+// an optional comment:
+//
+//     loop:   LD V0, SPEED    ; comments run to the end of the line
+//             JP loop
+//     SPEED   EQU 0x04
+//     sprite: DB 0xF0, 0x90, 0xF0
+//             DW 0x1234
+//
+// Mnemonics, registers and directives are case insensitive, symbols are not.
+// Numbers can be decimal, hex (0x1F, #1F or $1F) or binary (0b101), and
+// anywhere a number goes can also be a sum like `sprite + 3`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut statements = Vec::new();
+    let mut symbols = HashMap::new();
+    let mut address = ORIGIN;
+
+    // First pass, parse everything and work out where the labels are
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut tokens = &tokenize(text, line)?[..];
+        if let [Spanned { token: Token::Ident(name), column },
+            Spanned { token: Token::Colon, .. }, rest @ ..] = tokens {
+            define(&mut symbols, name, Symbol::Label(address), line,
+                *column)?;
+            tokens = rest;
+        }
+        if tokens.is_empty() {
+            continue;
+        }
+        if let [Spanned { token: Token::Ident(name), column },
+            Spanned { token: Token::Ident(equ), .. }, rest @ ..] = tokens {
+            if equ.eq_ignore_ascii_case("EQU") {
+                let expression = parse_expression(rest, line, *column)?;
+                define(&mut symbols, name,
+                    Symbol::Constant(expression, line), line, *column)?;
+                continue;
+            }
+        }
+        let statement = parse_statement(tokens, line)?;
+        address += statement.size() as i64;
+        statements.push(statement);
+    }
+
+    // Second pass, now that every symbol is known
+    let mut rom = Vec::new();
+    for statement in &statements {
+        statement.encode(&symbols, &mut rom)?;
+    }
+    Ok(rom)
+}
+
+fn define(symbols: &mut HashMap<String, Symbol>, name: &str, symbol: Symbol,
+    line: usize, column: usize) -> Result<(), AssembleError> {
+    if is_reserved(name) || register(name).is_some() {
+        return Err(AssembleError::new(line, column,
+            format!("'{}' is a reserved name", name)));
+    }
+    if symbols.insert(name.to_string(), symbol).is_some() {
+        return Err(AssembleError::new(line, column,
+            format!("'{}' is already defined", name)));
+    }
+    Ok(())
+}
+
+fn is_reserved(name: &str) -> bool {
+    RESERVED.iter().any(|reserved| reserved.eq_ignore_ascii_case(name))
+}
+
+// The register index for names like V3 or vA
+fn register(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('V'), Some(digit), None) | (Some('v'), Some(digit), None) =>
+            digit.to_digit(16).map(|index| index as u8),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Comma,
+    Colon,
+    Plus,
+    Minus,
+    LeftBracket,
+    RightBracket,
+}
+
+#[derive(Clone, Debug)]
+struct Spanned {
+    token: Token,
+    column: usize,
+}
+
+fn tokenize(text: &str, line: usize) -> Result<Vec<Spanned>, AssembleError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let start = index;
+        let column = start + 1;
+        let c = chars[index];
+        index += 1;
+        let token = match c {
+            ';' => break,
+            c if c.is_whitespace() => continue,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '[' => Token::LeftBracket,
+            ']' => Token::RightBracket,
+            c if is_symbol_char(c) && !c.is_ascii_digit() => {
+                while index < chars.len() && is_symbol_char(chars[index]) {
+                    index += 1;
+                }
+                Token::Ident(chars[start..index].iter().collect())
+            }
+            c if c.is_ascii_digit() || c == '#' || c == '$' => {
+                while index < chars.len()
+                    && chars[index].is_ascii_alphanumeric() {
+                    index += 1;
+                }
+                let literal: String = chars[start..index].iter().collect();
+                match parse_number(&literal) {
+                    Some(value) => Token::Number(value),
+                    None => return Err(AssembleError::new(line, column,
+                        format!("invalid number '{}'", literal))),
+                }
+            }
+            c => return Err(AssembleError::new(line, column,
+                format!("unexpected character '{}'", c))),
+        };
+        tokens.push(Spanned { token, column });
+    }
+    Ok(tokens)
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn parse_number(literal: &str) -> Option<i64> {
+    let lower = literal.to_ascii_lowercase();
+    let (digits, radix) = if let Some(hex) = lower.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        (binary, 2)
+    } else if let Some(hex) = lower.strip_prefix('#') {
+        (hex, 16)
+    } else if let Some(hex) = lower.strip_prefix('$') {
+        (hex, 16)
+    } else {
+        (&lower[..], 10)
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
+
+// A sum of numbers and symbols, each with whether it's subtracted and its
+// column
+#[derive(Clone, Debug)]
+struct Expression {
+    terms: Vec<(bool, Term, usize)>,
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+#[derive(Clone, Debug)]
+enum Symbol {
+    Label(i64),
+    // With the line it's defined on, for errors in the expression
+    Constant(Expression, usize),
+}
+
+// Parse tokens that must make up exactly one expression. The column is used
+// when there are no tokens at all.
+fn parse_expression(tokens: &[Spanned], line: usize, column: usize)
+    -> Result<Expression, AssembleError> {
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut expect_term = true;
+    for spanned in tokens {
+        match (&spanned.token, expect_term) {
+            (Token::Minus, true) => negative = !negative,
+            (Token::Plus, true) => {}
+            (Token::Number(value), true) => {
+                terms.push((negative, Term::Number(*value), spanned.column));
+                expect_term = false;
+            }
+            (Token::Ident(name), true) if register(name).is_none()
+                && !is_reserved(name) => {
+                terms.push((negative, Term::Symbol(name.clone()),
+                    spanned.column));
+                expect_term = false;
+            }
+            (Token::Plus, false) | (Token::Minus, false) => {
+                negative = spanned.token == Token::Minus;
+                expect_term = true;
+            }
+            _ => return Err(AssembleError::new(line, spanned.column,
+                "expected a number or symbol")),
+        }
+    }
+    if expect_term {
+        let column = tokens.last().map_or(column, |spanned| spanned.column);
+        return Err(AssembleError::new(line, column,
+            "expected a number or symbol"));
+    }
+    Ok(Expression { terms })
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Register(u8),
+    // Vx-Vy
+    Range(u8, u8),
+    // [I]
+    Indirect,
+    // One of the RESERVED names, uppercased
+    Name(String),
+    // LONG addr
+    Long(Expression),
+    Value(Expression),
+}
+
+#[derive(Clone, Debug)]
+struct Statement {
+    line: usize,
+    column: usize,
+    mnemonic: String,
+    operands: Vec<(Operand, usize)>,
+}
+
+fn parse_statement(tokens: &[Spanned], line: usize)
+    -> Result<Statement, AssembleError> {
+    let (mnemonic, column) = match &tokens[0] {
+        Spanned { token: Token::Ident(name), column } =>
+            (name.to_ascii_uppercase(), *column),
+        Spanned { column, .. } => return Err(AssembleError::new(line, *column,
+            "expected an instruction")),
+    };
+    let mut operands = Vec::new();
+    let rest = &tokens[1..];
+    if !rest.is_empty() {
+        for operand in rest.split(|spanned| spanned.token == Token::Comma) {
+            operands.push(parse_operand(operand, line, column)?);
+        }
+    }
+    Ok(Statement { line, column, mnemonic, operands })
+}
+
+fn parse_operand(tokens: &[Spanned], line: usize, column: usize)
+    -> Result<(Operand, usize), AssembleError> {
+    let column = match tokens.first() {
+        Some(spanned) => spanned.column,
+        None => return Err(AssembleError::new(line, column,
+            "missing operand")),
+    };
+    let kinds: Vec<&Token> = tokens.iter().map(|spanned| &spanned.token)
+        .collect();
+    let operand = match &kinds[..] {
+        [Token::Ident(name)] if register(name).is_some() =>
+            Operand::Register(register(name).unwrap()),
+        [Token::Ident(from), Token::Minus, Token::Ident(to)]
+            if register(from).is_some() && register(to).is_some() =>
+            Operand::Range(register(from).unwrap(), register(to).unwrap()),
+        [Token::LeftBracket, Token::Ident(name), Token::RightBracket]
+            if name.eq_ignore_ascii_case("I") => Operand::Indirect,
+        [Token::Ident(name), ..] if name.eq_ignore_ascii_case("LONG") =>
+            Operand::Long(parse_expression(&tokens[1..], line, column)?),
+        [Token::Ident(name)] if is_reserved(name) =>
+            Operand::Name(name.to_ascii_uppercase()),
+        _ => Operand::Value(parse_expression(tokens, line, column)?),
+    };
+    Ok((operand, column))
+}
+
+impl Statement {
+    // Bytes the statement takes up in the ROM
+    fn size(&self) -> usize {
+        match &self.mnemonic[..] {
+            "DB" => self.operands.len(),
+            "DW" => self.operands.len() * 2,
+            _ if self.operands.iter().any(|(operand, _)|
+                matches!(operand, Operand::Long(_))) => 4,
+            _ => 2,
+        }
+    }
+
+    fn error<S: Into<String>>(&self, column: usize, message: S)
+        -> AssembleError {
+        AssembleError::new(self.line, column, message)
+    }
+
+    // Errors are reported on line, which is the constant's own line when
+    // evaluating one
+    fn evaluate(&self, expression: &Expression, line: usize,
+        symbols: &HashMap<String, Symbol>, depth: usize)
+        -> Result<i64, AssembleError> {
+        let mut total: i64 = 0;
+        for (negative, term, column) in &expression.terms {
+            let value = match term {
+                Term::Number(value) => *value,
+                Term::Symbol(name) => match symbols.get(name) {
+                    Some(Symbol::Label(address)) => *address,
+                    Some(Symbol::Constant(..)) if depth == MAX_CONSTANT_DEPTH =>
+                        return Err(AssembleError::new(line, *column,
+                            "constant refers to itself")),
+                    Some(Symbol::Constant(expression, defined)) =>
+                        self.evaluate(expression, *defined, symbols,
+                            depth + 1)?,
+                    None => return Err(AssembleError::new(line, *column,
+                        format!("undefined symbol '{}'", name))),
+                },
+            };
+            let sum = if *negative {
+                total.checked_sub(value)
+            } else {
+                total.checked_add(value)
+            };
+            total = sum.ok_or_else(|| AssembleError::new(line, *column,
+                "value is too big"))?;
+        }
+        Ok(total)
+    }
+
+    // Evaluate an expression that has to fit in the given number of bits
+    fn value(&self, expression: &Expression, column: usize, bits: u32,
+        symbols: &HashMap<String, Symbol>) -> Result<u16, AssembleError> {
+        let value = self.evaluate(expression, self.line, symbols, 0)?;
+        if value < 0 || value >= 1 << bits {
+            return Err(self.error(column,
+                format!("value {} doesn't fit in {} bits", value, bits)));
+        }
+        Ok(value as u16)
+    }
+
+    fn encode(&self, symbols: &HashMap<String, Symbol>, rom: &mut Vec<u8>)
+        -> Result<(), AssembleError> {
+        match &self.mnemonic[..] {
+            "DB" | "DW" => {
+                let bits = if self.mnemonic == "DB" { 8 } else { 16 };
+                for (operand, column) in &self.operands {
+                    let value = match operand {
+                        Operand::Value(expression) =>
+                            self.value(expression, *column, bits, symbols)?,
+                        _ => return Err(self.error(*column,
+                            "expected a number or symbol")),
+                    };
+                    if bits == 16 {
+                        rom.push((value >> 8) as u8);
+                    }
+                    rom.push(value as u8);
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        let (instruction, long) = self.instruction(symbols)?;
+        let opcode = instruction.opcode();
+        rom.push((opcode >> 8) as u8);
+        rom.push(opcode as u8);
+        if let Some(address) = long {
+            rom.push((address >> 8) as u8);
+            rom.push(address as u8);
+        }
+        Ok(())
+    }
+
+    // Match the mnemonic and operands up to an instruction, along with the
+    // address for LD I, LONG
+    fn instruction(&self, symbols: &HashMap<String, Symbol>)
+        -> Result<(Instruction, Option<u16>), AssembleError> {
+        use Operand::*;
+        let operands: Vec<&Operand> = self.operands.iter()
+            .map(|(operand, _)| operand).collect();
+        let column = |index: usize| self.operands[index].1;
+        let addr = |index: usize, expression: &Expression|
+            self.value(expression, column(index), 12, symbols);
+        let byte = |index: usize, expression: &Expression|
+            self.value(expression, column(index), 8, symbols)
+                .map(|value| value as u8);
+        let nibble = |index: usize, expression: &Expression|
+            self.value(expression, column(index), 4, symbols)
+                .map(|value| value as u8);
+        let name = |operand: &Operand, expected: &str| match operand {
+            Name(name) => name == expected,
+            _ => false,
+        };
+
+        let instruction = match (&self.mnemonic[..], &operands[..]) {
+            ("CLS", []) => Instruction::Cls,
+            ("RET", []) => Instruction::Ret,
+            ("SCR", []) => Instruction::Scr,
+            ("SCL", []) => Instruction::Scl,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::Low,
+            ("HIGH", []) => Instruction::High,
+            ("AUDIO", []) => Instruction::Audio,
+            ("SCD", [Value(n)]) => Instruction::Scd(nibble(0, n)?),
+            ("SCU", [Value(n)]) => Instruction::Scu(nibble(0, n)?),
+            ("PLANE", [Value(n)]) => Instruction::Plane(nibble(0, n)?),
+            ("JP", [Value(nnn)]) => Instruction::Jp(addr(0, nnn)?),
+            ("JP", [Register(0), Value(nnn)]) =>
+                Instruction::JpV0(addr(1, nnn)?),
+            ("CALL", [Value(nnn)]) => Instruction::Call(addr(0, nnn)?),
+            ("SE", [Register(x), Value(kk)]) =>
+                Instruction::SeByte { x: *x, kk: byte(1, kk)? },
+            ("SE", [Register(x), Register(y)]) =>
+                Instruction::SeReg { x: *x, y: *y },
+            ("SNE", [Register(x), Value(kk)]) =>
+                Instruction::SneByte { x: *x, kk: byte(1, kk)? },
+            ("SNE", [Register(x), Register(y)]) =>
+                Instruction::SneReg { x: *x, y: *y },
+            ("LD", [Register(x), Value(kk)]) =>
+                Instruction::LdByte { x: *x, kk: byte(1, kk)? },
+            ("LD", [Register(x), Register(y)]) =>
+                Instruction::LdReg { x: *x, y: *y },
+            ("LD", [i, Value(nnn)]) if name(i, "I") =>
+                Instruction::LdI(addr(1, nnn)?),
+            ("LD", [i, Long(nnnn)]) if name(i, "I") => {
+                let address = self.value(nnnn, column(1), 16, symbols)?;
+                return Ok((Instruction::LdILong, Some(address)));
+            }
+            ("LD", [Register(x), dt]) if name(dt, "DT") =>
+                Instruction::LdVxDt(*x),
+            ("LD", [Register(x), k]) if name(k, "K") => Instruction::LdVxK(*x),
+            ("LD", [dt, Register(x)]) if name(dt, "DT") =>
+                Instruction::LdDtVx(*x),
+            ("LD", [st, Register(x)]) if name(st, "ST") =>
+                Instruction::LdStVx(*x),
+            ("LD", [f, Register(x)]) if name(f, "F") => Instruction::LdF(*x),
+            ("LD", [hf, Register(x)]) if name(hf, "HF") =>
+                Instruction::LdHf(*x),
+            ("LD", [b, Register(x)]) if name(b, "B") => Instruction::LdB(*x),
+            ("LD", [r, Register(x)]) if name(r, "R") => Instruction::LdRVx(*x),
+            ("LD", [Register(x), r]) if name(r, "R") => Instruction::LdVxR(*x),
+            ("LD", [Indirect, Register(x)]) => Instruction::LdIVx(*x),
+            ("LD", [Register(x), Indirect]) => Instruction::LdVxI(*x),
+            ("LD", [Indirect, Range(x, y)]) =>
+                Instruction::StoreRange { x: *x, y: *y },
+            ("LD", [Range(x, y), Indirect]) =>
+                Instruction::LoadRange { x: *x, y: *y },
+            ("ADD", [Register(x), Value(kk)]) =>
+                Instruction::AddByte { x: *x, kk: byte(1, kk)? },
+            ("ADD", [Register(x), Register(y)]) =>
+                Instruction::AddReg { x: *x, y: *y },
+            ("ADD", [i, Register(x)]) if name(i, "I") =>
+                Instruction::AddIVx(*x),
+            ("OR", [Register(x), Register(y)]) =>
+                Instruction::Or { x: *x, y: *y },
+            ("AND", [Register(x), Register(y)]) =>
+                Instruction::And { x: *x, y: *y },
+            ("XOR", [Register(x), Register(y)]) =>
+                Instruction::Xor { x: *x, y: *y },
+            ("SUB", [Register(x), Register(y)]) =>
+                Instruction::Sub { x: *x, y: *y },
+            ("SUBN", [Register(x), Register(y)]) =>
+                Instruction::Subn { x: *x, y: *y },
+            // Shifting Vx into itself behaves the same whichever register
+            // the shift quirk reads from
+            ("SHR", [Register(x)]) => Instruction::Shr { x: *x, y: *x },
+            ("SHR", [Register(x), Register(y)]) =>
+                Instruction::Shr { x: *x, y: *y },
+            ("SHL", [Register(x)]) => Instruction::Shl { x: *x, y: *x },
+            ("SHL", [Register(x), Register(y)]) =>
+                Instruction::Shl { x: *x, y: *y },
+            ("RND", [Register(x), Value(kk)]) =>
+                Instruction::Rnd { x: *x, kk: byte(1, kk)? },
+            ("DRW", [Register(x), Register(y), Value(n)]) =>
+                Instruction::Drw { x: *x, y: *y, n: nibble(2, n)? },
+            ("SKP", [Register(x)]) => Instruction::Skp(*x),
+            ("SKNP", [Register(x)]) => Instruction::Sknp(*x),
+            ("PITCH", [Register(x)]) => Instruction::Pitch(*x),
+            _ if MNEMONICS.contains(&&self.mnemonic[..]) =>
+                return Err(self.error(self.column, format!(
+                    "invalid operands for {}", self.mnemonic))),
+            _ => return Err(self.error(self.column, format!(
+                "unknown instruction '{}'", self.mnemonic))),
+        };
+        Ok((instruction, None))
+    }
+}
+
+// Every instruction mnemonic, to tell bad operands apart from typos
+const MNEMONICS: [&str; 29] = [
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU",
+    "PLANE", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB",
+    "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "PITCH",
+];
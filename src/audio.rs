@@ -0,0 +1,151 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::platform::Platform;
+use crate::{AUDIO_PATTERN_SIZE, CPU};
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+const DEFAULT_FREQUENCY: f32 = 440.0;
+const DEFAULT_VOLUME: f32 = 0.25;
+const DEFAULT_ENVELOPE: f32 = 0.005;
+const PATTERN_BITS: usize = AUDIO_PATTERN_SIZE * 8;
+
+// Turns the sound timer into samples. The tone plays while ST is non-zero,
+// fading in and out over the envelope time so it doesn't click. CHIP-8 and
+// SUPER-CHIP play a square wave, XO-CHIP plays the audio pattern at the pitch
+// set by the ROM.
+pub struct Audio {
+    sample_rate: u32,
+    // Of the square wave, in Hz
+    frequency: f32,
+    // From 0 to 1
+    volume: f32,
+    // Seconds to fade in or out
+    envelope: f32,
+    // How far through the wave or pattern playback is, from 0 to 1
+    phase: f32,
+    // Current loudness from the envelope, from 0 to 1
+    level: f32,
+    // The last samples generated for JS
+    samples: Vec<f32>,
+    samples_i16: Vec<i16>,
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            frequency: DEFAULT_FREQUENCY,
+            volume: DEFAULT_VOLUME,
+            envelope: DEFAULT_ENVELOPE,
+            phase: 0.0,
+            level: 0.0,
+            samples: Vec::new(),
+            samples_i16: Vec::new(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    pub fn get_audio_sample_rate(&self) -> u32 {
+        self.audio.sample_rate
+    }
+
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.audio.sample_rate = sample_rate.max(1);
+    }
+
+    pub fn set_audio_frequency(&mut self, frequency: f32) {
+        self.audio.frequency = frequency.max(0.0);
+    }
+
+    // From 0 for silent to 1 for full scale
+    pub fn set_audio_volume(&mut self, volume: f32) {
+        self.audio.volume = volume.clamp(0.0, 1.0);
+    }
+
+    // Seconds the tone takes to fade in and out, 0 to switch instantly
+    pub fn set_audio_envelope(&mut self, seconds: f32) {
+        self.audio.envelope = seconds.max(0.0);
+    }
+
+    // Generate the next count samples for get_audio_pointer and
+    // get_audio_i16_pointer to point to. The pointers are only valid until
+    // the next call.
+    pub fn generate_audio(&mut self, count: usize) {
+        let mut samples = std::mem::take(&mut self.audio.samples);
+        samples.resize(count, 0.0);
+        self.fill_audio(&mut samples);
+        self.audio.samples_i16.clear();
+        self.audio.samples_i16
+            .extend(samples.iter().map(|&sample| to_i16(sample)));
+        self.audio.samples = samples;
+    }
+
+    pub fn get_audio_pointer(&self) -> *const f32 {
+        self.audio.samples.as_ptr()
+    }
+
+    pub fn get_audio_i16_pointer(&self) -> *const i16 {
+        self.audio.samples_i16.as_ptr()
+    }
+}
+
+impl CPU {
+    // Fill samples with the next stretch of sound, from -1 to 1
+    pub fn fill_audio(&mut self, samples: &mut [f32]) {
+        let playing = self.st > 0;
+        let rate = self.audio.sample_rate as f32;
+        // How much the level moves each sample
+        let fade = if self.audio.envelope > 0.0 {
+            1.0 / (self.audio.envelope * rate)
+        } else {
+            1.0
+        };
+        // How far the phase moves each sample
+        let step = match self.platform {
+            Platform::XoChip =>
+                self.pattern_rate() / PATTERN_BITS as f32 / rate,
+            _ => self.audio.frequency / rate,
+        };
+        for sample in samples.iter_mut() {
+            let audio = &mut self.audio;
+            audio.level = if playing {
+                (audio.level + fade).min(1.0)
+            } else {
+                (audio.level - fade).max(0.0)
+            };
+            let high = match self.platform {
+                Platform::XoChip => {
+                    let bit = (audio.phase * PATTERN_BITS as f32) as usize;
+                    let byte = self.audio_pattern[bit / 8];
+                    byte & 0x80 >> (bit % 8) != 0
+                }
+                _ => audio.phase < 0.5,
+            };
+            let wave = if high { 1.0 } else { -1.0 };
+            *sample = wave * audio.level * audio.volume;
+            audio.phase = (audio.phase + step).fract();
+        }
+    }
+
+    // fill_audio as 16 bit samples
+    pub fn fill_audio_i16(&mut self, samples: &mut [i16]) {
+        let mut buffer = vec![0.0; samples.len()];
+        self.fill_audio(&mut buffer);
+        for (sample, &value) in samples.iter_mut().zip(buffer.iter()) {
+            *sample = to_i16(value);
+        }
+    }
+
+    // XO-CHIP plays the pattern at 4000 * 2 ^ ((pitch - 64) / 48) bits a
+    // second
+    fn pattern_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample * i16::MAX as f32) as i16
+}
@@ -0,0 +1,508 @@
+// Plays a ROM in the terminal, drawing the display with half-block characters
+// next to the registers, so ROMs can be played and debugged over SSH.
+
+extern crate chip_8_emu;
+extern crate crossterm;
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::time::{Duration, Instant};
+
+use chip_8_emu::{
+    CpuState, Keymap, Platform, Quirks, QuirksPreset, RunResult, StopReason,
+    Timing, CPU,
+};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+    KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{
+    Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{
+    self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use crossterm::{execute, queue};
+
+const USAGE: &str = "\
+usage: chip8-tui [options] ROM
+
+Plays ROM in the terminal.
+
+options:
+  -p, --platform NAME   chip8, schip or xochip (default chip8)
+  -q, --quirks NAME     vip, chip48, schip or xochip (default: the
+                        platform's usual quirks)
+  -i, --ipf N           instructions per frame (default 10)
+  -v, --vip-timing      run instructions as fast as the COSMAC VIP did,
+                        rather than a fixed number a frame
+      --seed N          seed for RND (default a fixed seed)
+  -l, --layout KEYS     the 16 keyboard keys for CHIP-8 keys 0 to F, in order
+                        (default x123qweasdzc4rfv, the COSMAC VIP keypad on
+                        the left of a QWERTY keyboard)
+  -k, --keymap FILE     read the keys from a keymap file, with lines like
+                        'ArrowLeft = 4' and [rom CRC32] sections for
+                        bindings that only apply to one ROM
+  -m, --mono            draw with plain blocks rather than palette colours,
+                        for terminals without true colour
+  -h, --help            print this message
+
+keys:
+  space                 pause or carry on running
+  tab                   run one instruction while paused
+  backspace             reset and reload the ROM
+  esc, ctrl-c           quit
+";
+
+const PROGRAM_START: usize = 0x200;
+const FRAME_TIME: Duration = Duration::from_micros(1_000_000 / 60);
+// How far behind the loop can fall before it gives up catching up
+const MAX_LAG: Duration = Duration::from_millis(100);
+// Most terminals only report presses, so without release events a key is let
+// go this long after its last press or auto-repeat
+const KEY_HOLD: Duration = Duration::from_millis(200);
+
+struct Options {
+    rom: String,
+    platform: Platform,
+    quirks: Option<Quirks>,
+    ipf: u32,
+    timing: Timing,
+    seed: Option<u64>,
+    keymap: Keymap,
+    mono: bool,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("chip8-tui: {}", message);
+            eprintln!("try 'chip8-tui --help' for more information");
+            process::exit(2);
+        }
+    };
+    let rom = match fs::read(&options.rom) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("chip8-tui: can't read {}: {}", options.rom, error);
+            process::exit(2);
+        }
+    };
+    if let Err(message) = play(&options, rom) {
+        eprintln!("chip8-tui: {}", message);
+        process::exit(2);
+    }
+}
+
+// Returns None when the usage should be printed
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        rom: String::new(),
+        platform: Platform::Chip8,
+        quirks: None,
+        ipf: 10,
+        timing: Timing::Instructions,
+        seed: None,
+        keymap: Keymap::cosmac(),
+        mono: false,
+    };
+    let mut rom = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next()
+            .ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-p" | "--platform" => {
+                options.platform = match value(arg)?.as_str() {
+                    "chip8" => Platform::Chip8,
+                    "schip" => Platform::SuperChip,
+                    "xochip" => Platform::XoChip,
+                    other => return Err(format!("unknown platform '{}'",
+                        other)),
+                };
+            }
+            "-q" | "--quirks" => {
+                let preset = match value(arg)?.as_str() {
+                    "vip" => QuirksPreset::CosmacVip,
+                    "chip48" => QuirksPreset::Chip48,
+                    "schip" => QuirksPreset::SuperChip,
+                    "xochip" => QuirksPreset::XoChip,
+                    other => return Err(format!("unknown quirks '{}'",
+                        other)),
+                };
+                options.quirks = Some(Quirks::preset(preset));
+            }
+            "-i" | "--ipf" => {
+                let ipf = parse_number(value(arg)?)?;
+                if ipf == 0 || ipf > u32::MAX as u64 {
+                    return Err(format!("invalid IPF {}", ipf));
+                }
+                options.ipf = ipf as u32;
+            }
+            "-v" | "--vip-timing" => options.timing = Timing::CosmacVip,
+            "--seed" => options.seed = Some(parse_number(value(arg)?)?),
+            "-l" | "--layout" => options.keymap = parse_layout(value(arg)?)?,
+            "-k" | "--keymap" => {
+                let path = value(arg)?;
+                let text = fs::read_to_string(path)
+                    .map_err(|error| format!("can't read {}: {}", path,
+                        error))?;
+                options.keymap = Keymap::from_text(&text)
+                    .map_err(|error| format!("{}: {}", path, error))?;
+            }
+            "-m" | "--mono" => options.mono = true,
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option '{}'", arg));
+            }
+            _ if rom.is_some() => return Err("only one ROM can be run".into()),
+            _ => rom = Some(arg.clone()),
+        }
+    }
+    options.rom = rom.ok_or("no ROM given")?;
+    Ok(Some(options))
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    value.map_err(|_| format!("invalid number '{}'", text))
+}
+
+// 16 different keys, for CHIP-8 keys 0 to F
+fn parse_layout(text: &str) -> Result<Keymap, String> {
+    let invalid = |reason: String| format!("invalid layout '{}': {}", text,
+        reason);
+    let keys: Vec<String> = text.chars().map(|key| key.to_string()).collect();
+    if keys.len() != 16 {
+        return Err(invalid("needs 16 keys".into()));
+    }
+    let mut keymap = Keymap::new();
+    for (index, key) in keys.iter().enumerate() {
+        if keymap.get(key).is_some() {
+            return Err(invalid(format!("'{}' is used twice", key)));
+        }
+        keymap.bind(key, index as u8)
+            .map_err(|error| invalid(error.to_string()))?;
+    }
+    Ok(keymap)
+}
+
+fn play(options: &Options, rom: Vec<u8>) -> Result<(), String> {
+    let quirks = options.quirks
+        .unwrap_or_else(|| options.platform.default_quirks());
+    let mut cpu = CPU::new_with_platform_and_quirks(options.platform, quirks);
+    if rom.len() > cpu.get_memory_size() - PROGRAM_START {
+        return Err(format!("{} is too big to fit in memory", options.rom));
+    }
+    if let Some(seed) = options.seed {
+        cpu.set_rng_seed(seed);
+    }
+    cpu.set_instructions_per_frame(options.ipf);
+    cpu.set_timing(options.timing);
+    let mut tui = Tui {
+        cpu,
+        keymap: options.keymap.for_rom(&rom),
+        rom,
+        mono: options.mono,
+        releases: false,
+        held: [None; 16],
+        running: true,
+        status: String::new(),
+    };
+    tui.reset();
+
+    let mut stdout = io::stdout();
+    let terminal = Terminal::enter(&mut stdout)
+        .map_err(|error| format!("can't set up the terminal: {}", error))?;
+    tui.releases = terminal.releases;
+    let result = tui.run(&mut stdout);
+    drop(terminal);
+    result.map_err(|error| format!("terminal error: {}", error))
+}
+
+// The name of a key as the keymap knows it, as in a browser's
+// KeyboardEvent.key
+fn host_key(code: KeyCode) -> Option<String> {
+    let name = match code {
+        KeyCode::Char(key) => return Some(key.to_string()),
+        KeyCode::Left => "ArrowLeft",
+        KeyCode::Right => "ArrowRight",
+        KeyCode::Up => "ArrowUp",
+        KeyCode::Down => "ArrowDown",
+        KeyCode::Enter => "Enter",
+        KeyCode::Home => "Home",
+        KeyCode::End => "End",
+        KeyCode::PageUp => "PageUp",
+        KeyCode::PageDown => "PageDown",
+        KeyCode::Insert => "Insert",
+        KeyCode::Delete => "Delete",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+// Puts the terminal into raw mode on the alternate screen, putting it back
+// when dropped so a panic doesn't leave the shell unusable
+struct Terminal {
+    // Whether key releases are reported
+    releases: bool,
+}
+
+impl Terminal {
+    fn enter(stdout: &mut io::Stdout) -> io::Result<Terminal> {
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        let releases = terminal::supports_keyboard_enhancement()
+            .unwrap_or(false);
+        if releases {
+            execute!(stdout, PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+        Ok(Terminal { releases })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.releases {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Tui {
+    cpu: CPU,
+    rom: Vec<u8>,
+    keymap: Keymap,
+    mono: bool,
+    // Whether the terminal reports key releases
+    releases: bool,
+    // When each held key should be let go, without release events
+    held: [Option<Instant>; 16],
+    running: bool,
+    // Why the ROM stopped, if it did
+    status: String,
+}
+
+impl Tui {
+    // Run frames at 60Hz, handling keys in between, until quit
+    fn run(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
+        let mut next_frame = Instant::now();
+        loop {
+            let now = Instant::now();
+            if now < next_frame {
+                let quit = event::poll(next_frame - now)?
+                    && !self.handle(event::read()?);
+                if quit {
+                    return Ok(());
+                }
+                continue;
+            }
+            next_frame = if now - next_frame > MAX_LAG {
+                now + FRAME_TIME
+            } else {
+                next_frame + FRAME_TIME
+            };
+            self.release_keys(now);
+            if self.running {
+                let result = self.cpu.run_frame();
+                self.stopped(result);
+            }
+            self.draw(stdout)?;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.cpu.reset();
+        self.cpu.init_hex_sprites();
+        self.cpu.load_program_memory(self.rom.clone());
+        self.held = [None; 16];
+        self.status.clear();
+    }
+
+    // Returns false to quit
+    fn handle(&mut self, event: Event) -> bool {
+        let KeyEvent { code, modifiers, kind, .. } = match event {
+            Event::Key(key) => key,
+            _ => return true,
+        };
+        let pressed = kind != KeyEventKind::Release;
+        match code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) =>
+                return false,
+            KeyCode::Char(' ') if kind == KeyEventKind::Press => {
+                self.running = !self.running;
+                self.status.clear();
+            }
+            KeyCode::Tab if kind == KeyEventKind::Press => {
+                self.running = false;
+                let result = self.cpu.run(1);
+                self.status.clear();
+                self.stopped(result);
+            }
+            KeyCode::Backspace if kind == KeyEventKind::Press => self.reset(),
+            code => {
+                let key = host_key(code)
+                    .and_then(|host| self.keymap.get(&host));
+                if let Some(key) = key {
+                    self.set_key(key as usize, pressed);
+                }
+            }
+        }
+        true
+    }
+
+    fn set_key(&mut self, index: usize, pressed: bool) {
+        if pressed {
+            self.cpu.set_key_down(index as u32);
+            if !self.releases {
+                self.held[index] = Some(Instant::now() + KEY_HOLD);
+            }
+        } else {
+            self.cpu.set_key_up(index as u32);
+            self.held[index] = None;
+        }
+    }
+
+    // Let go of keys that haven't been pressed again for a while
+    fn release_keys(&mut self, now: Instant) {
+        for index in 0..self.held.len() {
+            if self.held[index].is_some_and(|release| release <= now) {
+                self.set_key(index, false);
+            }
+        }
+    }
+
+    // Pause and say why if the ROM stopped by itself
+    fn stopped(&mut self, result: RunResult) {
+        let status = match result.reason() {
+            StopReason::Error => match result.error() {
+                Some(error) => format!("error at {:04X}: {}", result.pc(),
+                    error),
+                None => format!("error at {:04X}", result.pc()),
+            },
+            StopReason::Exited => "exited".to_string(),
+            _ => return,
+        };
+        self.running = false;
+        self.status = status;
+    }
+
+    fn draw(&self, stdout: &mut io::Stdout) -> io::Result<()> {
+        let width = self.cpu.get_display_width();
+        let height = self.cpu.get_display_height();
+        let rows = height / 2;
+        for row in 0..rows {
+            queue!(stdout, MoveTo(0, row as u16))?;
+            self.draw_row(stdout, row * 2, width)?;
+        }
+        queue!(stdout, ResetColor)?;
+
+        let panel = self.panel();
+        for (row, line) in panel.iter().enumerate() {
+            queue!(stdout, MoveTo(width as u16 + 2, row as u16), Print(line),
+                Clear(ClearType::UntilNewLine))?;
+        }
+
+        // Status under everything else, clearing what a hires display left
+        let bottom = rows.max(panel.len()) as u16;
+        queue!(stdout, MoveTo(0, bottom), Clear(ClearType::FromCursorDown))?;
+        let state = match (self.running, self.cpu.get_state()) {
+            (true, CpuState::WaitingForKey) => "waiting for key",
+            (true, _) => "running",
+            (false, _) => "paused",
+        };
+        let speed = match self.cpu.get_timing() {
+            Timing::Instructions => format!("{} IPF",
+                self.cpu.get_instructions_per_frame()),
+            Timing::CosmacVip => "VIP timing".to_string(),
+        };
+        let line = match self.status.as_str() {
+            "" => format!("{} {:?} {}", state, self.cpu.get_platform(), speed),
+            status => format!("{}: {}", state, status),
+        };
+        queue!(stdout, MoveTo(0, bottom + 1), Print(line),
+            MoveTo(0, bottom + 2),
+            Print("space run/pause  tab step  backspace reset  esc quit"))?;
+        stdout.flush()
+    }
+
+    // Each character is two pixels stacked, the top one the foreground of ▀
+    // and the bottom one the background
+    fn draw_row(&self, stdout: &mut io::Stdout, y: usize, width: usize)
+        -> io::Result<()> {
+        let mut colours = None;
+        for x in 0..width {
+            let top = self.cpu.get_pixel_planes(x, y);
+            let bottom = self.cpu.get_pixel_planes(x, y + 1);
+            if self.mono {
+                let block = match (top != 0, bottom != 0) {
+                    (false, false) => ' ',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (true, true) => '█',
+                };
+                queue!(stdout, Print(block))?;
+                continue;
+            }
+            if colours != Some((top, bottom)) {
+                colours = Some((top, bottom));
+                queue!(stdout,
+                    SetForegroundColor(self.colour(top)),
+                    SetBackgroundColor(self.colour(bottom)))?;
+            }
+            queue!(stdout, Print('▀'))?;
+        }
+        Ok(())
+    }
+
+    fn colour(&self, planes: u8) -> Color {
+        let colour = self.cpu.get_palette_colour(planes);
+        Color::Rgb {
+            r: (colour >> 16) as u8,
+            g: (colour >> 8) as u8,
+            b: colour as u8,
+        }
+    }
+
+    // The registers and stack, laid out like the web frontend's tables
+    fn panel(&self) -> Vec<String> {
+        let cpu = &self.cpu;
+        let mut lines = vec![
+            format!("PC {:04X}  I  {:04X}", cpu.get_pc(), cpu.get_i()),
+            format!("SP {:02X}    DT {:02X}    ST {:02X}", cpu.get_sp(),
+                cpu.get_dt(), cpu.get_st()),
+            String::new(),
+        ];
+        let registers = cpu.get_registers();
+        for row in 0..8 {
+            lines.push(format!("V{:X} {:02X}    V{:X} {:02X}", row,
+                registers[row], row + 8, registers[row + 8]));
+        }
+        lines.push(String::new());
+        lines.push("Stack".to_string());
+        let stack = cpu.get_stack();
+        for row in 0..8 {
+            lines.push(format!("{:X}  {:04X}  {:X}  {:04X}", row, stack[row],
+                row + 8, stack[row + 8]));
+        }
+        lines
+    }
+}
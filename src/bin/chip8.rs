@@ -0,0 +1,435 @@
+// Runs a ROM without a browser and prints the state the machine ends up in,
+// for checking ROMs in CI and on servers.
+
+extern crate chip_8_emu;
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::process;
+
+use chip_8_emu::{
+    CpuError, Platform, Quirks, QuirksPreset, Replay, StopReason, CPU,
+};
+
+const USAGE: &str = "\
+usage: chip8 [options] ROM
+
+Runs ROM headless and prints the machine state at the end.
+
+options:
+  -p, --platform NAME   chip8, schip or xochip (default chip8)
+  -q, --quirks NAME     vip, chip48, schip or xochip (default: the
+                        platform's usual quirks)
+  -c, --cycles N        stop after N instructions
+  -f, --frames N        stop after N frames (default 60 without --cycles or
+                        --replay)
+  -i, --ipf N           instructions per frame (default 10)
+      --seed N          seed for RND, so runs with the same seed match
+                        (default a fixed seed)
+  -k, --key KEY@FROM[-TO]
+                        hold hex key KEY from frame FROM to frame TO
+                        inclusive, or for just frame FROM
+  -s, --script FILE     read --key entries from FILE, one per line, with #
+                        starting a comment
+  -r, --replay FILE     play back a replay recorded with the ROM, running to
+                        its end. Its platform and quirks are used.
+  -d, --dump LIST       what to print, a comma separated list of
+                        registers, memory and display (default
+                        registers,display)
+      --display FORMAT  print the display as ascii or pbm (default ascii)
+  -o, --output FILE     write the display to FILE rather than stdout
+  -h, --help            print this message
+
+Numbers can be decimal or hex with 0x. Each frame runs IPF instructions and
+then decrements the timers once. Exits with 1 if the ROM hits an error.
+";
+
+const PROGRAM_START: usize = 0x200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DisplayFormat {
+    Ascii,
+    Pbm,
+}
+
+// A key held down for a range of frames, inclusive
+#[derive(Clone, Copy, Debug)]
+struct KeyPress {
+    key: u8,
+    from: u64,
+    to: u64,
+}
+
+struct Options {
+    rom: String,
+    platform: Platform,
+    quirks: Option<Quirks>,
+    cycles: Option<u64>,
+    frames: Option<u64>,
+    ipf: u32,
+    seed: Option<u64>,
+    keys: Vec<KeyPress>,
+    replay: Option<String>,
+    dump_registers: bool,
+    dump_memory: bool,
+    dump_display: bool,
+    display_format: DisplayFormat,
+    output: Option<String>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("chip8: {}", message);
+            eprintln!("try 'chip8 --help' for more information");
+            process::exit(2);
+        }
+    };
+    match run(&options) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(message) => {
+            eprintln!("chip8: {}", message);
+            process::exit(2);
+        }
+    }
+}
+
+// Returns None when the usage should be printed
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        rom: String::new(),
+        platform: Platform::Chip8,
+        quirks: None,
+        cycles: None,
+        frames: None,
+        ipf: 10,
+        seed: None,
+        keys: Vec::new(),
+        replay: None,
+        dump_registers: true,
+        dump_memory: false,
+        dump_display: true,
+        display_format: DisplayFormat::Ascii,
+        output: None,
+    };
+    let mut rom = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next()
+            .ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-p" | "--platform" => {
+                options.platform = match value(arg)?.as_str() {
+                    "chip8" => Platform::Chip8,
+                    "schip" => Platform::SuperChip,
+                    "xochip" => Platform::XoChip,
+                    other => return Err(format!("unknown platform '{}'",
+                        other)),
+                };
+            }
+            "-q" | "--quirks" => {
+                let preset = match value(arg)?.as_str() {
+                    "vip" => QuirksPreset::CosmacVip,
+                    "chip48" => QuirksPreset::Chip48,
+                    "schip" => QuirksPreset::SuperChip,
+                    "xochip" => QuirksPreset::XoChip,
+                    other => return Err(format!("unknown quirks '{}'",
+                        other)),
+                };
+                options.quirks = Some(Quirks::preset(preset));
+            }
+            "-c" | "--cycles" => {
+                options.cycles = Some(parse_number(value(arg)?)?);
+            }
+            "-f" | "--frames" => {
+                options.frames = Some(parse_number(value(arg)?)?);
+            }
+            "-i" | "--ipf" => {
+                let ipf = parse_number(value(arg)?)?;
+                if ipf == 0 || ipf > u32::MAX as u64 {
+                    return Err(format!("invalid IPF {}", ipf));
+                }
+                options.ipf = ipf as u32;
+            }
+            "--seed" => options.seed = Some(parse_number(value(arg)?)?),
+            "-k" | "--key" => options.keys.push(parse_key(value(arg)?)?),
+            "-s" | "--script" => {
+                let path = value(arg)?;
+                let script = fs::read_to_string(path)
+                    .map_err(|error| format!("can't read {}: {}", path,
+                        error))?;
+                for (number, line) in script.lines().enumerate() {
+                    let line = line.split('#').next().unwrap().trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let key = parse_key(line).map_err(|message|
+                        format!("{} line {}: {}", path, number + 1,
+                            message))?;
+                    options.keys.push(key);
+                }
+            }
+            "-r" | "--replay" => options.replay = Some(value(arg)?.clone()),
+            "-d" | "--dump" => {
+                options.dump_registers = false;
+                options.dump_memory = false;
+                options.dump_display = false;
+                for item in value(arg)?.split(',') {
+                    match item.trim() {
+                        "registers" => options.dump_registers = true,
+                        "memory" => options.dump_memory = true,
+                        "display" => options.dump_display = true,
+                        other => return Err(format!("can't dump '{}'",
+                            other)),
+                    }
+                }
+            }
+            "--display" => {
+                options.display_format = match value(arg)?.as_str() {
+                    "ascii" => DisplayFormat::Ascii,
+                    "pbm" => DisplayFormat::Pbm,
+                    other => return Err(format!("unknown display format \
+                        '{}'", other)),
+                };
+            }
+            "-o" | "--output" => options.output = Some(value(arg)?.clone()),
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option '{}'", arg));
+            }
+            _ if rom.is_some() => return Err("only one ROM can be run".into()),
+            _ => rom = Some(arg.clone()),
+        }
+    }
+    options.rom = rom.ok_or("no ROM given")?;
+    Ok(Some(options))
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    value.map_err(|_| format!("invalid number '{}'", text))
+}
+
+// KEY@FROM or KEY@FROM-TO
+fn parse_key(text: &str) -> Result<KeyPress, String> {
+    let invalid = || format!("invalid key press '{}', expected KEY@FROM[-TO]",
+        text);
+    let (key, frames) = text.split_once('@').ok_or_else(invalid)?;
+    let key = u8::from_str_radix(key, 16).ok().filter(|&key| key < 16)
+        .ok_or_else(|| format!("invalid key '{}'", key))?;
+    let (from, to) = match frames.split_once('-') {
+        Some((from, to)) => (parse_number(from)?, parse_number(to)?),
+        None => {
+            let frame = parse_number(frames)?;
+            (frame, frame)
+        }
+    };
+    if to < from {
+        return Err(invalid());
+    }
+    Ok(KeyPress { key, from, to })
+}
+
+// Runs the ROM and prints the dumps. Returns whether the ROM ran without
+// errors, or a message if it couldn't be run at all.
+fn run(options: &Options) -> Result<bool, String> {
+    let rom = fs::read(&options.rom)
+        .map_err(|error| format!("can't read {}: {}", options.rom, error))?;
+    let replay = match &options.replay {
+        Some(path) => {
+            let bytes = fs::read(path)
+                .map_err(|error| format!("can't read {}: {}", path, error))?;
+            let replay = Replay::from_bytes(&bytes)
+                .map_err(|error| format!("{}: {}", path, error))?;
+            Some(replay)
+        }
+        None => None,
+    };
+    let platform = replay.as_ref()
+        .map_or(options.platform, |replay| replay.platform());
+    let quirks = options.quirks
+        .unwrap_or_else(|| platform.default_quirks());
+    let mut cpu = CPU::new_with_platform_and_quirks(platform, quirks);
+    if rom.len() > cpu.get_memory_size() - PROGRAM_START {
+        return Err(format!("{} is too big to fit in memory", options.rom));
+    }
+    match &replay {
+        Some(replay) => cpu.start_replay(replay, &rom)
+            .map_err(|error| format!("can't play replay: {}", error))?,
+        None => {
+            if let Some(seed) = options.seed {
+                cpu.set_rng_seed(seed);
+            }
+            cpu.init_hex_sprites();
+            cpu.load_program_memory(rom);
+        }
+    }
+
+    let replay_length = replay.map(|replay| replay.length());
+    let (frames, cycles, error) = run_frames(&mut cpu, options, replay_length);
+    let mut report = String::new();
+    if options.dump_registers {
+        write_registers(&mut report, &cpu, frames, cycles);
+    }
+    if options.dump_memory {
+        write_memory(&mut report, cpu.get_memory());
+    }
+    let display = if options.dump_display {
+        match options.display_format {
+            DisplayFormat::Ascii => display_ascii(&cpu),
+            DisplayFormat::Pbm => display_pbm(&cpu),
+        }
+    } else {
+        String::new()
+    };
+    match &options.output {
+        Some(path) => {
+            print!("{}", report);
+            fs::write(path, display)
+                .map_err(|error| format!("can't write {}: {}", path, error))?;
+        }
+        None => print!("{}{}", report, display),
+    }
+    if let Some(error) = error {
+        eprintln!("chip8: {}", error);
+    }
+    Ok(error.is_none())
+}
+
+// Run until the frame or cycle limit, the end of the replay, the ROM exits or
+// an error. Returns the number of frames and cycles run, and the error if
+// there was one.
+fn run_frames(cpu: &mut CPU, options: &Options, replay_length: Option<u64>)
+    -> (u64, u64, Option<CpuError>) {
+    // Without any other limit run for a second
+    let max_frames = match (options.frames, options.cycles, replay_length) {
+        (Some(frames), _, _) => frames,
+        (None, None, None) => 60,
+        _ => u64::MAX,
+    };
+    let max_cycles = options.cycles.unwrap_or(u64::MAX);
+    cpu.set_instructions_per_frame(options.ipf);
+    let ipf = options.ipf as u64;
+    let mut frame = 0;
+    let mut cycles = 0;
+    while frame < max_frames && cycles < max_cycles {
+        // Ignored while a replay is playing
+        let keyboard = options.keys.iter()
+            .filter(|press| press.from <= frame && frame <= press.to)
+            .fold(0, |keyboard, press| keyboard | 1 << press.key);
+        cpu.set_keyboard(keyboard);
+        let mut budget = ipf.min(max_cycles - cycles);
+        // Stop exactly where the recording did
+        if let Some(length) = replay_length {
+            budget = budget.min(length - cpu.get_instruction_count());
+            if budget == 0 {
+                break;
+            }
+        }
+        // Only whole frames get a timer tick
+        let result = if budget == ipf {
+            cpu.run_frame()
+        } else {
+            cpu.run(budget as u32)
+        };
+        // Waiting for a key or vblank idles away the rest of the frame, with
+        // only the instruction that waits counted
+        cycles += result.cycles() as u64;
+        match result.reason() {
+            StopReason::Error => return (frame, cycles, result.error()),
+            StopReason::Exited => return (frame, cycles, None),
+            _ => {}
+        }
+        if budget < ipf {
+            // A part frame that ends waiting has more cycles to give, so it
+            // idles to the end of the frame and the next one carries on. A
+            // replay has to stop where it does though.
+            let waiting = matches!(result.reason(),
+                StopReason::WaitingForKey | StopReason::WaitingForVblank);
+            if !waiting || cycles == max_cycles || replay_length.is_some() {
+                break;
+            }
+            cycles += cpu.run_frame().cycles() as u64;
+        }
+        frame += 1;
+    }
+    (frame, cycles, None)
+}
+
+fn write_registers(report: &mut String, cpu: &CPU, frames: u64, cycles: u64) {
+    let _ = writeln!(report, "frames {} cycles {}", frames, cycles);
+    let _ = writeln!(report, "PC={:04X} I={:04X} SP={:02X} DT={:02X} ST={:02X}",
+        cpu.get_pc(), cpu.get_i(), cpu.get_sp(), cpu.get_dt(), cpu.get_st());
+    let registers = cpu.get_registers();
+    for row in 0..2 {
+        let line: Vec<String> = (row * 8..row * 8 + 8)
+            .map(|x| format!("V{:X}={:02X}", x, registers[x]))
+            .collect();
+        let _ = writeln!(report, "{}", line.join(" "));
+    }
+    let stack = cpu.get_stack();
+    let stack: Vec<String> = stack[..cpu.get_sp() as usize].iter()
+        .map(|address| format!("{:04X}", address))
+        .collect();
+    let _ = writeln!(report, "stack [{}]", stack.join(" "));
+}
+
+// A hexdump, with runs of repeated lines collapsed into a *
+fn write_memory(report: &mut String, memory: &[u8]) {
+    let mut previous: Option<&[u8]> = None;
+    let mut skipping = false;
+    for (row, line) in memory.chunks(16).enumerate() {
+        if previous == Some(line) {
+            if !skipping {
+                let _ = writeln!(report, "*");
+                skipping = true;
+            }
+            continue;
+        }
+        previous = Some(line);
+        skipping = false;
+        let bytes: Vec<String> = line.iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let _ = writeln!(report, "{:04X}: {}", row * 16, bytes.join(" "));
+    }
+}
+
+// One character per pixel, with a different one for each combination of
+// XO-CHIP planes
+fn display_ascii(cpu: &CPU) -> String {
+    const PIXELS: [char; 4] = ['.', '#', '+', '@'];
+    let mut display = String::new();
+    for y in 0..cpu.get_display_height() {
+        for x in 0..cpu.get_display_width() {
+            display.push(PIXELS[cpu.get_pixel_planes(x, y) as usize & 3]);
+        }
+        display.push('\n');
+    }
+    display
+}
+
+// A plain PBM, where any pixel with a plane set is black
+fn display_pbm(cpu: &CPU) -> String {
+    let (width, height) = (cpu.get_display_width(), cpu.get_display_height());
+    let mut display = format!("P1\n{} {}\n", width, height);
+    for y in 0..height {
+        let row: Vec<&str> = (0..width)
+            .map(|x| if cpu.get_pixel_planes(x, y) != 0 { "1" } else { "0" })
+            .collect();
+        display.push_str(&row.join(" "));
+        display.push('\n');
+    }
+    display
+}
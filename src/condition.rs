@@ -0,0 +1,387 @@
+use std::fmt;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::CPU;
+
+// Something wrong with a condition, at a 1-based column
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConditionError {
+    column: usize,
+    message: String,
+}
+
+impl ConditionError {
+    fn new<S: Into<String>>(column: usize, message: S) -> ConditionError {
+        ConditionError { column, message: message.into() }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ConditionError {
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+}
+
+impl fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ConditionError {}
+
+// An expression over the CPU state that a conditional breakpoint stops on when
+// it's non-zero, like `pc == 0x2A4 && V3 > 10`.
+//
+// The variables are pc, i, sp, dt, st and the registers V0 to VF, and
+// mem[addr] reads a byte of memory (0 past the end of memory). Operators are
+// the C ones, from loosest to tightest binding:
+//
+//     ||  &&  |  ^  &  == !=  < <= > >=  + -  and unary ! -
+//
+// Comparisons and logic operators give 1 for true and 0 for false. Names are
+// case insensitive and numbers can be decimal or hex with 0x.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    root: Node,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, ConditionError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens: &tokens, position: 0,
+            end: source.chars().count() + 1, depth: 0 };
+        let root = parser.expression(0)?;
+        if let Some((_, column)) = parser.peek() {
+            return Err(ConditionError::new(column, "expected an operator"));
+        }
+        Ok(Condition { source: source.to_string(), root })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn evaluate(&self, cpu: &CPU) -> i64 {
+        self.root.evaluate(cpu)
+    }
+
+    pub fn is_true(&self, cpu: &CPU) -> bool {
+        self.evaluate(cpu) != 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Variable {
+    Pc,
+    I,
+    Sp,
+    Dt,
+    St,
+    Register(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Not,
+}
+
+impl Operator {
+    // How tightly a binary operator binds, higher first
+    fn precedence(self) -> Option<u8> {
+        match self {
+            Operator::Or => Some(1),
+            Operator::And => Some(2),
+            Operator::BitOr => Some(3),
+            Operator::BitXor => Some(4),
+            Operator::BitAnd => Some(5),
+            Operator::Equal | Operator::NotEqual => Some(6),
+            Operator::Less | Operator::LessEqual
+                | Operator::Greater | Operator::GreaterEqual => Some(7),
+            Operator::Add | Operator::Subtract => Some(8),
+            Operator::Not => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Variable(Variable),
+    Memory(Box<Node>),
+    Not(Box<Node>),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn evaluate(&self, cpu: &CPU) -> i64 {
+        match self {
+            Node::Number(value) => *value,
+            Node::Variable(Variable::Pc) => cpu.pc as i64,
+            Node::Variable(Variable::I) => cpu.i as i64,
+            Node::Variable(Variable::Sp) => cpu.sp as i64,
+            Node::Variable(Variable::Dt) => cpu.dt as i64,
+            Node::Variable(Variable::St) => cpu.st as i64,
+            Node::Variable(Variable::Register(x)) =>
+                cpu.gpr[*x as usize] as i64,
+            // Read memory directly so conditions don't trip watchpoints
+            Node::Memory(addr) => {
+                let addr = addr.evaluate(cpu);
+                let memory = cpu.get_memory();
+                if addr >= 0 && (addr as usize) < memory.len() {
+                    memory[addr as usize] as i64
+                } else {
+                    0
+                }
+            }
+            Node::Not(operand) => (operand.evaluate(cpu) == 0) as i64,
+            Node::Negate(operand) => operand.evaluate(cpu).wrapping_neg(),
+            Node::Binary(operator, left, right) => {
+                let left = left.evaluate(cpu);
+                // Short circuit the logic operators
+                match operator {
+                    Operator::Or if left != 0 => return 1,
+                    Operator::And if left == 0 => return 0,
+                    _ => {}
+                }
+                let right = right.evaluate(cpu);
+                match operator {
+                    Operator::Or | Operator::And => (right != 0) as i64,
+                    Operator::BitOr => left | right,
+                    Operator::BitXor => left ^ right,
+                    Operator::BitAnd => left & right,
+                    Operator::Equal => (left == right) as i64,
+                    Operator::NotEqual => (left != right) as i64,
+                    Operator::Less => (left < right) as i64,
+                    Operator::LessEqual => (left <= right) as i64,
+                    Operator::Greater => (left > right) as i64,
+                    Operator::GreaterEqual => (left >= right) as i64,
+                    Operator::Add => left.wrapping_add(right),
+                    Operator::Subtract => left.wrapping_sub(right),
+                    Operator::Not => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Variable(Variable),
+    Memory,
+    Operator(Operator),
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ConditionError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let start = index;
+        let column = start + 1;
+        let next = chars.get(index + 1).cloned();
+        let c = chars[index];
+        index += 1;
+        let token = match (c, next) {
+            (c, _) if c.is_whitespace() => continue,
+            ('(', _) => Token::LeftParen,
+            (')', _) => Token::RightParen,
+            ('[', _) => Token::LeftBracket,
+            (']', _) => Token::RightBracket,
+            ('+', _) => Token::Operator(Operator::Add),
+            ('-', _) => Token::Operator(Operator::Subtract),
+            ('^', _) => Token::Operator(Operator::BitXor),
+            ('|', Some('|')) => {
+                index += 1;
+                Token::Operator(Operator::Or)
+            }
+            ('|', _) => Token::Operator(Operator::BitOr),
+            ('&', Some('&')) => {
+                index += 1;
+                Token::Operator(Operator::And)
+            }
+            ('&', _) => Token::Operator(Operator::BitAnd),
+            ('=', Some('=')) => {
+                index += 1;
+                Token::Operator(Operator::Equal)
+            }
+            ('!', Some('=')) => {
+                index += 1;
+                Token::Operator(Operator::NotEqual)
+            }
+            ('!', _) => Token::Operator(Operator::Not),
+            ('<', Some('=')) => {
+                index += 1;
+                Token::Operator(Operator::LessEqual)
+            }
+            ('<', _) => Token::Operator(Operator::Less),
+            ('>', Some('=')) => {
+                index += 1;
+                Token::Operator(Operator::GreaterEqual)
+            }
+            ('>', _) => Token::Operator(Operator::Greater),
+            (c, _) if c.is_ascii_alphanumeric() => {
+                while index < chars.len()
+                    && chars[index].is_ascii_alphanumeric() {
+                    index += 1;
+                }
+                let word: String = chars[start..index].iter().collect();
+                word_token(&word).ok_or_else(|| ConditionError::new(column,
+                    format!("unknown name '{}'", word)))?
+            }
+            (c, _) => return Err(ConditionError::new(column,
+                format!("unexpected character '{}'", c))),
+        };
+        tokens.push((token, column));
+    }
+    Ok(tokens)
+}
+
+// A number, variable or mem
+fn word_token(word: &str) -> Option<Token> {
+    let lower = word.to_ascii_lowercase();
+    if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        let value = match lower.strip_prefix("0x") {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => lower.parse(),
+        };
+        return value.ok().map(Token::Number);
+    }
+    let variable = match &lower[..] {
+        "mem" => return Some(Token::Memory),
+        "pc" => Variable::Pc,
+        "i" => Variable::I,
+        "sp" => Variable::Sp,
+        "dt" => Variable::Dt,
+        "st" => Variable::St,
+        _ => {
+            let register = lower.strip_prefix('v')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| u8::from_str_radix(digit, 16).ok())?;
+            Variable::Register(register)
+        }
+    };
+    Some(Token::Variable(variable))
+}
+
+struct Parser<'a> {
+    tokens: &'a [(Token, usize)],
+    position: usize,
+    // Column just past the end, for errors about running out of input
+    end: usize,
+    // How deep in the tree the node being parsed is, counting each bracket,
+    // unary operator and binary operator above it. A chain like a + b + c
+    // nests each operator in the next.
+    depth: usize,
+}
+
+// Deepest a condition's tree can be, so parsing, evaluating and dropping it
+// can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<(&'a Token, usize)> {
+        self.tokens.get(self.position).map(|(token, column)| (token, *column))
+    }
+
+    fn next(&mut self) -> Result<(&'a Token, usize), ConditionError> {
+        let next = self.peek().ok_or_else(|| ConditionError::new(self.end,
+            "unexpected end of condition"))?;
+        self.position += 1;
+        Ok(next)
+    }
+
+    fn expect(&mut self, expected: Token, name: &str)
+        -> Result<(), ConditionError> {
+        match self.next()? {
+            (token, _) if *token == expected => Ok(()),
+            (_, column) => Err(ConditionError::new(column,
+                format!("expected '{}'", name))),
+        }
+    }
+
+    // Go a level deeper for the node at column
+    fn nest(&mut self, column: usize) -> Result<(), ConditionError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ConditionError::new(column,
+                "too long or nested too deeply"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // Parse binary operators that bind tighter than min_precedence
+    fn expression(&mut self, min_precedence: u8)
+        -> Result<Node, ConditionError> {
+        let depth = self.depth;
+        let mut left = self.unary()?;
+        while let Some((Token::Operator(operator), column)) = self.peek() {
+            let precedence = match operator.precedence() {
+                Some(precedence) if precedence > min_precedence => precedence,
+                _ => break,
+            };
+            self.nest(column)?;
+            self.position += 1;
+            let right = self.expression(precedence)?;
+            left = Node::Binary(*operator, Box::new(left), Box::new(right));
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Node, ConditionError> {
+        let (token, column) = self.next()?;
+        self.nest(column)?;
+        let node = match token {
+            Token::Number(value) => Node::Number(*value),
+            Token::Variable(variable) => Node::Variable(*variable),
+            Token::Memory => {
+                self.expect(Token::LeftBracket, "[")?;
+                let addr = self.expression(0)?;
+                self.expect(Token::RightBracket, "]")?;
+                Node::Memory(Box::new(addr))
+            }
+            Token::LeftParen => {
+                let inner = self.expression(0)?;
+                self.expect(Token::RightParen, ")")?;
+                inner
+            }
+            Token::Operator(Operator::Not) =>
+                Node::Not(Box::new(self.unary()?)),
+            Token::Operator(Operator::Subtract) =>
+                Node::Negate(Box::new(self.unary()?)),
+            _ => return Err(ConditionError::new(column,
+                "expected a number, variable or '('")),
+        };
+        self.depth -= 1;
+        Ok(node)
+    }
+}
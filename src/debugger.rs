@@ -0,0 +1,332 @@
+use std::collections::BTreeSet;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::condition::{Condition, ConditionError};
+use crate::error::{CpuError, ExecutionError, StepOutcome};
+use crate::CPU;
+
+// Debugging state that lives alongside the CPU but isn't part of the machine,
+// so it survives a reset
+#[derive(Clone, Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    // The first watched access made by the instruction being executed
+    access: Option<(u16, WatchKind, u8, u8)>,
+    // The watched access made by the last instruction executed
+    watchpoint_hit: Option<WatchpointHit>,
+    // Conditional breakpoints by id
+    conditions: Vec<(u32, Condition)>,
+    next_condition_id: u32,
+}
+
+// The kind of memory access a watchpoint triggers on
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // Either a read or a write
+    Access,
+}
+
+// Watches memory from start to end inclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Watchpoint {
+    start: u16,
+    end: u16,
+    kind: WatchKind,
+}
+
+// A memory access that triggered a watchpoint. Reads have the same old and new
+// value.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchpointHit {
+    addr: u16,
+    kind: WatchKind,
+    old_value: u8,
+    new_value: u8,
+    pc: u16,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl WatchpointHit {
+    pub fn addr(&self) -> u16 {
+        self.addr
+    }
+
+    // Either Read or Write
+    pub fn kind(&self) -> WatchKind {
+        self.kind
+    }
+
+    pub fn old_value(&self) -> u8 {
+        self.old_value
+    }
+
+    pub fn new_value(&self) -> u8 {
+        self.new_value
+    }
+
+    // Address of the instruction that made the access
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+}
+
+impl Debugger {
+    // Called by the CPU for every memory access the program makes
+    pub fn watch(&mut self, addr: usize, kind: WatchKind, old_value: u8,
+        new_value: u8) {
+        if self.watchpoints.is_empty() || self.access.is_some() {
+            return;
+        }
+        let addr = addr as u16;
+        let watched = self.watchpoints.iter().any(|watchpoint| {
+            watchpoint.start <= addr && addr <= watchpoint.end
+                && (watchpoint.kind == kind
+                    || watchpoint.kind == WatchKind::Access)
+        });
+        if watched {
+            self.access = Some((addr, kind, old_value, new_value));
+        }
+    }
+
+    pub fn start_instruction(&mut self) {
+        self.access = None;
+    }
+
+    // Turn any watched access into a hit for the instruction at pc. Failed
+    // instructions don't count, as they never really happened.
+    pub fn finish_instruction(&mut self, pc: u16, executed: bool) {
+        self.watchpoint_hit = match self.access.take() {
+            Some((addr, kind, old_value, new_value)) if executed =>
+                Some(WatchpointHit { addr, kind, old_value, new_value, pc }),
+            _ => None,
+        };
+    }
+}
+
+// Why a call to run came back
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    // Ran all the cycles it was given
+    CycleLimit,
+    // PC reached a breakpoint, which hasn't been executed yet
+    Breakpoint,
+    // The last instruction touched watched memory, see the watchpoint
+    Watchpoint,
+    // A conditional breakpoint became true, see the condition
+    Condition,
+    // An instruction failed, see the error
+    Error,
+    // Fx0A is blocking until a key is pressed
+    WaitingForKey,
+    // Dxyn is waiting for the next frame, with the display_wait quirk
+    WaitingForVblank,
+    // The SUPER-CHIP EXIT instruction was reached
+    Exited,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunResult {
+    reason: StopReason,
+    cycles: u32,
+    pc: u16,
+    error: Option<CpuError>,
+    watchpoint: Option<WatchpointHit>,
+    condition: Option<u32>,
+}
+
+impl RunResult {
+    pub fn error(&self) -> Option<CpuError> {
+        self.error
+    }
+
+    // The same result for a run that executed cycles more instructions before
+    // getting here
+    pub(crate) fn after(self, cycles: u32) -> RunResult {
+        RunResult { cycles: self.cycles + cycles, ..self }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RunResult {
+    pub fn reason(&self) -> StopReason {
+        self.reason
+    }
+
+    // Number of instructions executed
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
+    // Where the CPU stopped
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = error))]
+    pub fn execution_error(&self) -> Option<ExecutionError> {
+        self.error.map(|error| ExecutionError::new(error, self.pc))
+    }
+
+    pub fn watchpoint(&self) -> Option<WatchpointHit> {
+        self.watchpoint
+    }
+
+    // Id of the conditional breakpoint that stopped the run
+    pub fn condition(&self) -> Option<u32> {
+        self.condition
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.debugger.breakpoints.insert(addr);
+    }
+
+    // Returns whether there was a breakpoint to remove
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.debugger.breakpoints.remove(&addr)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.breakpoints.clear();
+    }
+
+    pub fn get_breakpoints(&self) -> Vec<u16> {
+        self.debugger.breakpoints.iter().cloned().collect()
+    }
+
+    // Watch the memory from start to end inclusive
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, kind: WatchKind) {
+        let watchpoint = Watchpoint { start, end, kind };
+        if !self.debugger.watchpoints.contains(&watchpoint) {
+            self.debugger.watchpoints.push(watchpoint);
+        }
+    }
+
+    // Returns whether there was a matching watchpoint to remove
+    pub fn remove_watchpoint(&mut self, start: u16, end: u16, kind: WatchKind)
+        -> bool {
+        let watchpoint = Watchpoint { start, end, kind };
+        let count = self.debugger.watchpoints.len();
+        self.debugger.watchpoints.retain(|other| *other != watchpoint);
+        self.debugger.watchpoints.len() != count
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.debugger.watchpoints.clear();
+    }
+
+    // The watched access made by the last instruction executed, if any
+    pub fn get_watchpoint_hit(&self) -> Option<WatchpointHit> {
+        self.debugger.watchpoint_hit
+    }
+
+    // Stop running once the condition is true after an instruction. See
+    // Condition for the syntax. Returns an id for removing it again.
+    pub fn add_conditional_breakpoint(&mut self, condition: &str)
+        -> Result<u32, ConditionError> {
+        let condition = Condition::parse(condition)?;
+        let id = self.debugger.next_condition_id;
+        self.debugger.next_condition_id += 1;
+        self.debugger.conditions.push((id, condition));
+        Ok(id)
+    }
+
+    // Returns whether there was a conditional breakpoint with the id
+    pub fn remove_conditional_breakpoint(&mut self, id: u32) -> bool {
+        let count = self.debugger.conditions.len();
+        self.debugger.conditions.retain(|(other, _)| *other != id);
+        self.debugger.conditions.len() != count
+    }
+
+    pub fn clear_conditional_breakpoints(&mut self) {
+        self.debugger.conditions.clear();
+    }
+
+    // Execute up to max_cycles instructions, stopping early on a breakpoint,
+    // a watchpoint, a condition, an error, EXIT or a wait for a key or vblank.
+    // The instruction at PC is always executed, so calling run again resumes
+    // from a breakpoint.
+    pub fn run(&mut self, max_cycles: u32) -> RunResult {
+        self.run_until(max_cycles, u64::MAX, false)
+    }
+}
+
+impl CPU {
+    // run, also stopping with CycleLimit once cycles_elapsed reaches end. In a
+    // frame, the instructions' cycles count towards it as they run, so history
+    // knows where in the frame each one started.
+    pub(crate) fn run_until(&mut self, max_cycles: u32, end: u64, frame: bool)
+        -> RunResult {
+        let mut cycles = 0;
+        let mut condition = None;
+        let reason = loop {
+            if cycles == max_cycles || self.cycles_elapsed >= end {
+                break StopReason::CycleLimit;
+            }
+            if cycles > 0 && self.debugger.breakpoints.contains(&self.pc) {
+                break StopReason::Breakpoint;
+            }
+            let start = self.cycles_elapsed;
+            let result = self.try_tick();
+            if frame {
+                self.frame_cycles += (self.cycles_elapsed - start) as u32;
+            }
+            let outcome = match result {
+                Ok(outcome) => outcome,
+                Err(error) => {
+                    return RunResult {
+                        reason: StopReason::Error,
+                        cycles,
+                        pc: self.pc,
+                        error: Some(error),
+                        watchpoint: None,
+                        condition: None,
+                    };
+                }
+            };
+            if outcome == StepOutcome::Exited {
+                break StopReason::Exited;
+            }
+            cycles += 1;
+            if self.debugger.watchpoint_hit.is_some() {
+                break StopReason::Watchpoint;
+            }
+            if let Some(id) = self.true_condition() {
+                condition = Some(id);
+                break StopReason::Condition;
+            }
+            match outcome {
+                StepOutcome::WaitingForKey => break StopReason::WaitingForKey,
+                StepOutcome::WaitingForVblank =>
+                    break StopReason::WaitingForVblank,
+                _ => {}
+            }
+        };
+        RunResult {
+            reason,
+            cycles,
+            pc: self.pc,
+            error: None,
+            watchpoint: self.debugger.watchpoint_hit,
+            condition,
+        }
+    }
+
+    // Id of the first conditional breakpoint that's currently true
+    fn true_condition(&self) -> Option<u32> {
+        self.debugger.conditions.iter()
+            .find(|(_, condition)| condition.is_true(self))
+            .map(|(id, _)| *id)
+    }
+}
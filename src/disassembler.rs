@@ -0,0 +1,251 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::instruction::{decode, Instruction};
+use crate::CPU;
+
+// The assembly language to print mnemonics in
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    // Cowgod's Chip-8 Technical Reference, e.g. LD V1, 0x20
+    Cowgod,
+    // Octo, e.g. v1 := 0x20
+    Octo,
+}
+
+// A single instruction read out of memory
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    address: u16,
+    opcode: u16,
+    // The address word following F000, the only instruction with an operand
+    operand: Option<u16>,
+    instruction: Instruction,
+}
+
+impl DisassembledInstruction {
+    pub fn instruction(&self) -> Instruction {
+        self.instruction
+    }
+
+    pub fn operand(&self) -> Option<u16> {
+        self.operand
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl DisassembledInstruction {
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    // Number of bytes the instruction takes up in memory
+    pub fn length(&self) -> u16 {
+        match self.operand {
+            Some(_) => 4,
+            None => 2,
+        }
+    }
+
+    pub fn text(&self, syntax: Syntax) -> String {
+        match syntax {
+            Syntax::Cowgod => cowgod(self.instruction, self.operand),
+            Syntax::Octo => octo(self.instruction, self.operand),
+        }
+    }
+}
+
+// Disassemble the instruction at address, or None if there isn't a whole
+// opcode left in memory there
+pub fn disassemble_one(memory: &[u8], address: usize)
+    -> Option<DisassembledInstruction> {
+    if address + 1 >= memory.len() {
+        return None;
+    }
+    let opcode = (memory[address] as u16) << 8 | memory[address + 1] as u16;
+    let mut instruction = decode(opcode);
+    let mut operand = None;
+    if instruction == Instruction::LdILong {
+        if address + 3 < memory.len() {
+            operand = Some((memory[address + 2] as u16) << 8
+                | memory[address + 3] as u16);
+        } else {
+            // The address got cut off, so it's just data
+            instruction = Instruction::Unknown(opcode);
+        }
+    }
+    Some(DisassembledInstruction {
+        address: address as u16,
+        opcode,
+        operand,
+        instruction,
+    })
+}
+
+// Disassemble the len bytes of memory starting at start. Anything that
+// doesn't decode to an instruction is treated as a data word.
+pub fn disassemble(memory: &[u8], start: usize, len: usize)
+    -> Vec<DisassembledInstruction> {
+    let end = memory.len().min(start.saturating_add(len));
+    let mut address = start;
+    let mut instructions = Vec::new();
+    while let Some(instruction) = disassemble_one(&memory[..end], address) {
+        address += instruction.length() as usize;
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+fn cowgod(instruction: Instruction, operand: Option<u16>) -> String {
+    match instruction {
+        Instruction::Scd(n) => format!("SCD {}", n),
+        Instruction::Scu(n) => format!("SCU {}", n),
+        Instruction::Cls => "CLS".to_string(),
+        Instruction::Ret => "RET".to_string(),
+        Instruction::Scr => "SCR".to_string(),
+        Instruction::Scl => "SCL".to_string(),
+        Instruction::Exit => "EXIT".to_string(),
+        Instruction::Low => "LOW".to_string(),
+        Instruction::High => "HIGH".to_string(),
+        Instruction::Jp(nnn) => format!("JP 0x{:03X}", nnn),
+        Instruction::Call(nnn) => format!("CALL 0x{:03X}", nnn),
+        Instruction::SeByte { x, kk } => format!("SE V{:X}, 0x{:02X}", x, kk),
+        Instruction::SneByte { x, kk } =>
+            format!("SNE V{:X}, 0x{:02X}", x, kk),
+        Instruction::SeReg { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::StoreRange { x, y } =>
+            format!("LD [I], V{:X}-V{:X}", x, y),
+        Instruction::LoadRange { x, y } =>
+            format!("LD V{:X}-V{:X}, [I]", x, y),
+        Instruction::LdByte { x, kk } => format!("LD V{:X}, 0x{:02X}", x, kk),
+        Instruction::AddByte { x, kk } =>
+            format!("ADD V{:X}, 0x{:02X}", x, kk),
+        Instruction::LdReg { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::AddReg { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::Shr { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::Subn { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::Shl { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::SneReg { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::LdI(nnn) => format!("LD I, 0x{:03X}", nnn),
+        Instruction::JpV0(nnn) => format!("JP V0, 0x{:03X}", nnn),
+        Instruction::Rnd { x, kk } => format!("RND V{:X}, 0x{:02X}", x, kk),
+        Instruction::Drw { x, y, n } =>
+            format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Instruction::Skp(x) => format!("SKP V{:X}", x),
+        Instruction::Sknp(x) => format!("SKNP V{:X}", x),
+        Instruction::LdILong =>
+            format!("LD I, LONG 0x{:04X}", operand.unwrap_or(0)),
+        Instruction::Plane(n) => format!("PLANE {}", n),
+        Instruction::Audio => "AUDIO".to_string(),
+        Instruction::LdVxDt(x) => format!("LD V{:X}, DT", x),
+        Instruction::LdVxK(x) => format!("LD V{:X}, K", x),
+        Instruction::LdDtVx(x) => format!("LD DT, V{:X}", x),
+        Instruction::LdStVx(x) => format!("LD ST, V{:X}", x),
+        Instruction::AddIVx(x) => format!("ADD I, V{:X}", x),
+        Instruction::LdF(x) => format!("LD F, V{:X}", x),
+        Instruction::LdHf(x) => format!("LD HF, V{:X}", x),
+        Instruction::LdB(x) => format!("LD B, V{:X}", x),
+        Instruction::Pitch(x) => format!("PITCH V{:X}", x),
+        Instruction::LdIVx(x) => format!("LD [I], V{:X}", x),
+        Instruction::LdVxI(x) => format!("LD V{:X}, [I]", x),
+        Instruction::LdRVx(x) => format!("LD R, V{:X}", x),
+        Instruction::LdVxR(x) => format!("LD V{:X}, R", x),
+        Instruction::Unknown(opcode) => format!("DW 0x{:04X}", opcode),
+    }
+}
+
+fn octo(instruction: Instruction, operand: Option<u16>) -> String {
+    match instruction {
+        Instruction::Scd(n) => format!("scroll-down {}", n),
+        Instruction::Scu(n) => format!("scroll-up {}", n),
+        Instruction::Cls => "clear".to_string(),
+        Instruction::Ret => "return".to_string(),
+        Instruction::Scr => "scroll-right".to_string(),
+        Instruction::Scl => "scroll-left".to_string(),
+        Instruction::Exit => "exit".to_string(),
+        Instruction::Low => "lores".to_string(),
+        Instruction::High => "hires".to_string(),
+        Instruction::Jp(nnn) => format!("jump 0x{:03X}", nnn),
+        Instruction::Call(nnn) => format!(":call 0x{:03X}", nnn),
+        // Octo conditions say when the next instruction runs, so they read
+        // as the opposite of the skip
+        Instruction::SeByte { x, kk } =>
+            format!("if v{:x} != 0x{:02X} then", x, kk),
+        Instruction::SneByte { x, kk } =>
+            format!("if v{:x} == 0x{:02X} then", x, kk),
+        Instruction::SeReg { x, y } => format!("if v{:x} != v{:x} then", x, y),
+        Instruction::StoreRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+        Instruction::LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+        Instruction::LdByte { x, kk } => format!("v{:x} := 0x{:02X}", x, kk),
+        Instruction::AddByte { x, kk } => format!("v{:x} += 0x{:02X}", x, kk),
+        Instruction::LdReg { x, y } => format!("v{:x} := v{:x}", x, y),
+        Instruction::Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+        Instruction::And { x, y } => format!("v{:x} &= v{:x}", x, y),
+        Instruction::Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+        Instruction::AddReg { x, y } => format!("v{:x} += v{:x}", x, y),
+        Instruction::Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+        Instruction::Shr { x, y } => format!("v{:x} >>= v{:x}", x, y),
+        Instruction::Subn { x, y } => format!("v{:x} =- v{:x}", x, y),
+        Instruction::Shl { x, y } => format!("v{:x} <<= v{:x}", x, y),
+        Instruction::SneReg { x, y } => format!("if v{:x} == v{:x} then", x, y),
+        Instruction::LdI(nnn) => format!("i := 0x{:03X}", nnn),
+        Instruction::JpV0(nnn) => format!("jump0 0x{:03X}", nnn),
+        Instruction::Rnd { x, kk } =>
+            format!("v{:x} := random 0x{:02X}", x, kk),
+        Instruction::Drw { x, y, n } =>
+            format!("sprite v{:x} v{:x} {}", x, y, n),
+        Instruction::Skp(x) => format!("if v{:x} -key then", x),
+        Instruction::Sknp(x) => format!("if v{:x} key then", x),
+        Instruction::LdILong =>
+            format!("i := long 0x{:04X}", operand.unwrap_or(0)),
+        Instruction::Plane(n) => format!("plane {}", n),
+        Instruction::Audio => "audio".to_string(),
+        Instruction::LdVxDt(x) => format!("v{:x} := delay", x),
+        Instruction::LdVxK(x) => format!("v{:x} := key", x),
+        Instruction::LdDtVx(x) => format!("delay := v{:x}", x),
+        Instruction::LdStVx(x) => format!("buzzer := v{:x}", x),
+        Instruction::AddIVx(x) => format!("i += v{:x}", x),
+        Instruction::LdF(x) => format!("i := hex v{:x}", x),
+        Instruction::LdHf(x) => format!("i := bighex v{:x}", x),
+        Instruction::LdB(x) => format!("bcd v{:x}", x),
+        Instruction::Pitch(x) => format!("pitch := v{:x}", x),
+        Instruction::LdIVx(x) => format!("save v{:x}", x),
+        Instruction::LdVxI(x) => format!("load v{:x}", x),
+        Instruction::LdRVx(x) => format!("saveflags v{:x}", x),
+        Instruction::LdVxR(x) => format!("loadflags v{:x}", x),
+        Instruction::Unknown(opcode) =>
+            format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF),
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    // Disassemble count instructions starting at addr, stopping early at the
+    // end of memory
+    pub fn disassemble_at(&self, addr: u16, count: usize)
+        -> Vec<DisassembledInstruction> {
+        let memory = self.get_memory();
+        let mut address = addr as usize;
+        let mut instructions = Vec::with_capacity(count);
+        while instructions.len() < count {
+            match disassemble_one(memory, address) {
+                Some(instruction) => {
+                    address += instruction.length() as usize;
+                    instructions.push(instruction);
+                }
+                None => break,
+            }
+        }
+        instructions
+    }
+}
@@ -0,0 +1,174 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{CPU, HIRES_HEIGHT, HIRES_WIDTH};
+
+pub const PLANE_SIZE: usize = HIRES_WIDTH * HIRES_HEIGHT / 8;
+const DISPLAY_SIZE: usize = HIRES_WIDTH * HIRES_HEIGHT * 3;
+const DISPLAY_RGBA_SIZE: usize = HIRES_WIDTH * HIRES_HEIGHT * 4;
+// Colours as 0xRRGGBB for each combination of the two bitplanes. Only the
+// first two are used unless an XO-CHIP ROM draws to the second plane.
+const DEFAULT_PALETTE: [u32; 4] = [0x000000, 0x66FF66, 0xFF6666, 0xFFFFFF];
+
+// The pixels, one bit each in two bitplanes. Pixels are numbered across rows
+// of the current resolution, so lores rows are 8 bytes and hires rows 16,
+// with the leftmost pixel in the most significant bit.
+pub struct Framebuffer {
+    planes: [[u8; PLANE_SIZE]; 2],
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer { planes: [[0u8; PLANE_SIZE]; 2] }
+    }
+}
+
+impl Framebuffer {
+    // The bitplanes set for the pixel at index
+    pub fn get(&self, index: usize) -> u8 {
+        let (byte, bit) = (index / 8, 0x80 >> (index % 8));
+        let plane = |plane: usize| (self.planes[plane][byte] & bit != 0) as u8;
+        plane(0) | plane(1) << 1
+    }
+
+    pub fn set(&mut self, index: usize, planes: u8) {
+        let (byte, bit) = (index / 8, 0x80 >> (index % 8));
+        for (plane, bits) in self.planes.iter_mut().enumerate() {
+            if planes & 1 << plane != 0 {
+                bits[byte] |= bit;
+            } else {
+                bits[byte] &= !bit;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.planes = [[0u8; PLANE_SIZE]; 2];
+    }
+
+    pub fn plane(&self, plane: usize) -> &[u8; PLANE_SIZE] {
+        &self.planes[plane]
+    }
+
+    pub fn plane_mut(&mut self, plane: usize) -> &mut [u8; PLANE_SIZE] {
+        &mut self.planes[plane]
+    }
+}
+
+// Turns the framebuffer into colours for the frontend. Only touched when the
+// frontend asks for a frame, so themes can't change how ROMs run.
+pub struct Renderer {
+    palette: [u32; 4],
+    rgb: [u8; DISPLAY_SIZE],
+    rgba: [u8; DISPLAY_RGBA_SIZE],
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer {
+            palette: DEFAULT_PALETTE,
+            rgb: [0u8; DISPLAY_SIZE],
+            rgba: [0u8; DISPLAY_RGBA_SIZE],
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    // Set the colours of lit and unlit pixels, as 0xRRGGBB
+    pub fn set_palette(&mut self, on: u32, off: u32) {
+        self.renderer.palette[0] = off & 0xFFFFFF;
+        self.renderer.palette[1] = on & 0xFFFFFF;
+    }
+
+    // Set the colour of pixels with the given XO-CHIP bitplanes set, from 0
+    // for unlit to 3 for both planes
+    pub fn set_palette_colour(&mut self, planes: u8, colour: u32) {
+        self.renderer.palette[planes as usize & 3] = colour & 0xFFFFFF;
+    }
+
+    pub fn get_palette_colour(&self, planes: u8) -> u32 {
+        self.renderer.palette[planes as usize & 3]
+    }
+
+    // Render the display as RGB bytes for get_display_pointer
+    pub fn render_display(&mut self) {
+        let pixels = self.pixel_count();
+        let renderer = &mut self.renderer;
+        render(&self.framebuffer, &renderer.palette, pixels,
+            &mut renderer.rgb, 3);
+    }
+
+    // Render the display as RGBA bytes for get_display_rgba_pointer
+    pub fn render_display_rgba(&mut self) {
+        let pixels = self.pixel_count();
+        let renderer = &mut self.renderer;
+        render(&self.framebuffer, &renderer.palette, pixels,
+            &mut renderer.rgba, 4);
+    }
+
+    // Width * height RGB bytes, as of the last render_display
+    pub fn get_display_pointer(&self) -> *const u8 {
+        self.renderer.rgb.as_ptr()
+    }
+
+    // Width * height RGBA bytes, as of the last render_display_rgba
+    pub fn get_display_rgba_pointer(&self) -> *const u8 {
+        self.renderer.rgba.as_ptr()
+    }
+
+    // The packed bits of bitplane 0 or 1, for drawing the display without
+    // going through the palette
+    pub fn get_plane_pointer(&self, plane: usize) -> *const u8 {
+        self.framebuffer.plane(plane & 1).as_ptr()
+    }
+}
+
+impl CPU {
+    // Render the display into rgb, 3 bytes a pixel. rgb must hold at least
+    // width * height pixels.
+    pub fn render_rgb(&self, rgb: &mut [u8]) {
+        render(&self.framebuffer, &self.renderer.palette, self.pixel_count(),
+            rgb, 3);
+    }
+
+    // Render the display into rgba, 4 bytes a pixel with full alpha. rgba must
+    // hold at least width * height pixels.
+    pub fn render_rgba(&self, rgba: &mut [u8]) {
+        render(&self.framebuffer, &self.renderer.palette, self.pixel_count(),
+            rgba, 4);
+    }
+
+    // The display rendered as RGB, width * height * 3 bytes
+    pub fn get_display(&self) -> Vec<u8> {
+        let mut rgb = vec![0u8; self.pixel_count() * 3];
+        self.render_rgb(&mut rgb);
+        rgb
+    }
+
+    // Set the bitplanes of the first pixels, as from get_pixel_planes
+    pub fn set_display(&mut self, planes: &[u8]) {
+        for (index, &planes) in planes.iter().enumerate() {
+            self.framebuffer.set(index, planes & 0b11);
+        }
+    }
+
+    fn pixel_count(&self) -> usize {
+        self.get_display_width() * self.get_display_height()
+    }
+}
+
+// Write the colour of the first pixels into output, taking bytes_per_pixel
+// bytes of 0xRRGGBBAA each
+fn render(framebuffer: &Framebuffer, palette: &[u32; 4], pixels: usize,
+    output: &mut [u8], bytes_per_pixel: usize) {
+    let colours = palette.iter()
+        .map(|colour| [(colour >> 16) as u8, (colour >> 8) as u8,
+            *colour as u8, 0xFF])
+        .collect::<Vec<_>>();
+    let chunks = output.chunks_exact_mut(bytes_per_pixel).take(pixels);
+    for (index, pixel) in chunks.enumerate() {
+        let colour = &colours[framebuffer.get(index) as usize];
+        pixel.copy_from_slice(&colour[..bytes_per_pixel]);
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// The result of successfully executing a single instruction
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    // The instruction ran to completion
+    Executed,
+    // Fx0A is blocking until a key is pressed
+    WaitingForKey,
+    // Dxyn drew with the display_wait quirk, and nothing more runs until the
+    // next frame
+    WaitingForVblank,
+    // The SUPER-CHIP EXIT instruction was reached
+    Exited,
+}
+
+// Everything that can stop the CPU from executing an instruction. The CPU is
+// left untouched when one of these is returned, with PC still pointing at the
+// offending instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuError {
+    // The opcode at pc doesn't decode to any known instruction
+    UnknownOpcode { pc: u16, opcode: u16 },
+    // CALL with all 16 stack slots in use
+    StackOverflow,
+    // RET with an empty stack
+    StackUnderflow,
+    // An instruction tried to read or write past the end of memory
+    MemoryOutOfBounds { addr: usize },
+    // PC points somewhere an instruction can't be fetched from
+    PcOutOfBounds { pc: u16 },
+}
+
+impl CpuError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            CpuError::UnknownOpcode { .. } => ErrorKind::UnknownOpcode,
+            CpuError::StackOverflow => ErrorKind::StackOverflow,
+            CpuError::StackUnderflow => ErrorKind::StackUnderflow,
+            CpuError::MemoryOutOfBounds { .. } => ErrorKind::MemoryOutOfBounds,
+            CpuError::PcOutOfBounds { .. } => ErrorKind::PcOutOfBounds,
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, pc)
+            }
+            CpuError::StackOverflow => write!(f, "stack overflow"),
+            CpuError::StackUnderflow => write!(f, "stack underflow"),
+            CpuError::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:X}", addr)
+            }
+            CpuError::PcOutOfBounds { pc } => {
+                write!(f, "program counter out of bounds at {:X}", pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+// JS-facing mirror of the CpuError variants
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownOpcode,
+    StackOverflow,
+    StackUnderflow,
+    MemoryOutOfBounds,
+    PcOutOfBounds,
+}
+
+// A CpuError flattened into something wasm-bindgen can hand to JS. Fields that
+// don't apply to the error kind are 0.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExecutionError {
+    error: CpuError,
+    pc: u16,
+}
+
+impl ExecutionError {
+    pub fn new(error: CpuError, pc: u16) -> ExecutionError {
+        ExecutionError { error, pc }
+    }
+
+    pub fn error(&self) -> CpuError {
+        self.error
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ExecutionError {
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+
+    // Address of the instruction that failed
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn opcode(&self) -> u16 {
+        match self.error {
+            CpuError::UnknownOpcode { opcode, .. } => opcode,
+            _ => 0,
+        }
+    }
+
+    pub fn addr(&self) -> u32 {
+        match self.error {
+            CpuError::MemoryOutOfBounds { addr } => addr as u32,
+            _ => 0,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        self.error.to_string()
+    }
+}
@@ -0,0 +1,110 @@
+use std::cmp;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::debugger::{RunResult, StopReason};
+use crate::replay::InputKind;
+use crate::CPU;
+
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+const FRAMES_PER_SECOND: u64 = 60;
+// How many frames run_realtime will run in one go to catch up
+const MAX_CATCH_UP_FRAMES: u64 = 4;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    pub fn get_instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    // At least 1
+    pub fn set_instructions_per_frame(&mut self, instructions: u32) {
+        self.instructions_per_frame = cmp::max(instructions, 1);
+    }
+
+    // Run the rest of the current frame, then decrement the timers once. A
+    // frame cut short by a breakpoint, watchpoint or error carries on where it
+    // stopped on the next call. Waiting for a key ends the frame early, as
+    // nothing else happens until a key is pressed, and so does a sprite drawn
+    // with the display_wait quirk. How long a frame is depends on the timing.
+    pub fn run_frame(&mut self) -> RunResult {
+        let remaining = self.frame_length().saturating_sub(self.frame_cycles);
+        self.run_cycles(remaining)
+    }
+
+    // Run for a number of cycles of the timing, decrementing the timers at
+    // each frame boundary passed. The instruction running when they're up
+    // still finishes, so cycles_elapsed can go a little past. Waiting for a
+    // key or vblank idles until the frame ends or the cycles are up. Stops
+    // early like run, and the result counts instructions like run's.
+    pub fn run_cycles(&mut self, cycles: u32) -> RunResult {
+        let end = self.cycles_elapsed + cycles as u64;
+        let mut instructions = 0;
+        loop {
+            let frame_end = self.cycles_elapsed
+                + self.frame_length().saturating_sub(self.frame_cycles) as u64;
+            let result = self.run_until(u32::MAX, cmp::min(end, frame_end),
+                true);
+            let waiting = matches!(result.reason(),
+                StopReason::WaitingForKey | StopReason::WaitingForVblank);
+            if waiting {
+                let idle = cmp::min(end, frame_end)
+                    .saturating_sub(self.cycles_elapsed);
+                self.cycles_elapsed += idle;
+                self.frame_cycles += idle as u32;
+            }
+            self.end_frames();
+            let finished = waiting
+                || result.reason() == StopReason::CycleLimit;
+            if !finished || self.cycles_elapsed >= end {
+                return result.after(instructions);
+            }
+            instructions += result.cycles();
+        }
+    }
+
+    // Run as many frames as have passed since the last call, going by time in
+    // milliseconds from the host's clock. Returns how the last frame run
+    // stopped, or None if no frame was due. After a long gap, like a tab in
+    // the background, only a few frames are caught up.
+    pub fn run_realtime(&mut self, time: u64) -> Option<RunResult> {
+        let frame = |time: u64| time * FRAMES_PER_SECOND / 1000;
+        let frames = frame(time).saturating_sub(frame(self.last_tick_time));
+        self.last_tick_time = time;
+        let mut result = None;
+        for _ in 0..cmp::min(frames, MAX_CATCH_UP_FRAMES) {
+            let frame = self.run_frame();
+            let finished = matches!(frame.reason(),
+                StopReason::CycleLimit | StopReason::WaitingForKey
+                    | StopReason::WaitingForVblank);
+            result = Some(frame);
+            if !finished {
+                break;
+            }
+        }
+        result
+    }
+}
+
+impl CPU {
+    // Start a new frame for each one the instructions run have filled. On the
+    // VIP an instruction can run into the next frame, or take several.
+    fn end_frames(&mut self) {
+        let length = self.frame_length();
+        while self.frame_cycles >= length {
+            self.frame_cycles -= length;
+            self.cycles_elapsed += self.interrupt_cycles() as u64;
+            self.elapse_frames(1);
+        }
+    }
+
+    // Decrement the timers for frames that have passed, unless a replay is
+    // providing them
+    pub(crate) fn elapse_frames(&mut self, frames: u64) {
+        let recorded = cmp::min(frames, 0xFF) as u8;
+        if frames > 0 && self.record_input(InputKind::Timers(recorded)) {
+            self.decrement_timers(frames);
+        }
+    }
+}
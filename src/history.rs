@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use std::mem;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::rng::XorShift;
+use crate::wait::KeyWait;
+use crate::{AUDIO_PATTERN_SIZE, CPU, RPL_FLAGS, STACK_SIZE};
+
+// Everything small enough to copy whole before every instruction
+#[derive(Clone, Debug)]
+struct Registers {
+    gpr: [u8; 16],
+    stack: [u16; STACK_SIZE],
+    i: u16,
+    pc: u16,
+    sp: u8,
+    dt: u8,
+    st: u8,
+    hires: bool,
+    rpl: [u8; RPL_FLAGS],
+    selected_planes: u8,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    // None if the random source can't be saved
+    rng: Option<u64>,
+    key_wait: Option<KeyWait>,
+    vblank_wait: bool,
+    cycles_elapsed: u64,
+    instructions: u64,
+    frame_cycles: u32,
+    // Only restored while a replay is playing, as live keys are still held
+    keyboard: u16,
+}
+
+// What it takes to undo a single instruction
+#[derive(Clone, Debug)]
+struct Delta {
+    registers: Registers,
+    // Old values of memory bytes written, in the order they were written
+    memory: Vec<(u16, u8)>,
+    // Old planes of pixels that changed, in the order they changed
+    pixels: Vec<(u16, u8)>,
+    // The instruction was the first of a frame
+    frame: bool,
+}
+
+impl Delta {
+    // Roughly how much memory the delta holds on to
+    fn size(&self) -> usize {
+        mem::size_of::<Delta>()
+            + (self.memory.capacity() + self.pixels.capacity())
+                * mem::size_of::<(u16, u8)>()
+    }
+}
+
+// Per-instruction undo history, oldest first. Recording is off while the
+// budget is 0, otherwise the oldest instructions are dropped to keep the
+// history within budget bytes.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    budget: usize,
+    used: usize,
+    deltas: VecDeque<Delta>,
+    // The delta for the instruction being executed
+    recording: Option<Delta>,
+    // The next instruction starts a frame
+    new_frame: bool,
+}
+
+impl History {
+    pub fn record_memory(&mut self, addr: usize, old_value: u8) {
+        if let Some(delta) = self.recording.as_mut() {
+            delta.memory.push((addr as u16, old_value));
+        }
+    }
+
+    pub fn record_pixel(&mut self, index: usize, old_planes: u8) {
+        if let Some(delta) = self.recording.as_mut() {
+            delta.pixels.push((index as u16, old_planes));
+        }
+    }
+
+    pub fn mark_frame(&mut self) {
+        self.new_frame = true;
+    }
+
+    // Forget everything recorded, but keep recording
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+        self.used = 0;
+        self.recording = None;
+        self.new_frame = false;
+    }
+
+    fn push(&mut self, mut delta: Delta) {
+        delta.memory.shrink_to_fit();
+        delta.pixels.shrink_to_fit();
+        self.used += delta.size();
+        self.deltas.push_back(delta);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used -= delta.size(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    // Start recording history, keeping it within budget_bytes. Changing the
+    // budget keeps whatever history still fits.
+    pub fn enable_history(&mut self, budget_bytes: usize) {
+        self.history.budget = budget_bytes;
+        self.history.trim();
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history.budget = 0;
+        self.history.clear();
+    }
+
+    // Number of instructions that can be stepped back through
+    pub fn get_history_length(&self) -> usize {
+        self.history.deltas.len()
+    }
+
+    // Undo the last instruction executed. Returns false if there's no history
+    // left to undo.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.deltas.pop_back() {
+            Some(delta) => {
+                self.history.used -= delta.size();
+                self.history.new_frame = delta.frame;
+                self.undo(delta);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Undo instructions back to the start of the nth frame, counting the
+    // current one as the first. Returns the number of frames rewound, which is
+    // less than n if the history runs out first.
+    pub fn rewind_frames(&mut self, n: u32) -> u32 {
+        let mut frames = 0;
+        while frames < n {
+            let frame = match self.history.deltas.back() {
+                Some(delta) => delta.frame,
+                None => break,
+            };
+            self.step_back();
+            if frame {
+                frames += 1;
+            }
+        }
+        frames
+    }
+}
+
+impl CPU {
+    // Called by try_tick before executing an instruction
+    pub(crate) fn begin_history(&mut self) {
+        if self.history.budget == 0 {
+            return;
+        }
+        let registers = Registers {
+            gpr: self.gpr,
+            stack: self.stack,
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+            hires: self.hires,
+            rpl: self.rpl,
+            selected_planes: self.selected_planes,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rng: self.rng.state(),
+            key_wait: self.key_wait,
+            vblank_wait: self.vblank_wait,
+            cycles_elapsed: self.cycles_elapsed,
+            instructions: self.instructions,
+            frame_cycles: self.frame_cycles,
+            keyboard: self.keyboard,
+        };
+        self.history.recording = Some(Delta {
+            registers,
+            memory: Vec::new(),
+            pixels: Vec::new(),
+            frame: self.history.new_frame,
+        });
+    }
+
+    // Called by try_tick once the instruction is done. Failed instructions
+    // don't change anything, so there's nothing to undo.
+    pub(crate) fn finish_history(&mut self, executed: bool) {
+        if let Some(delta) = self.history.recording.take() {
+            if executed {
+                self.history.new_frame = false;
+                self.history.push(delta);
+            }
+        }
+    }
+
+    fn undo(&mut self, delta: Delta) {
+        for &(addr, old_value) in delta.memory.iter().rev() {
+            self.memory[addr as usize] = old_value;
+        }
+        for &(index, old_planes) in delta.pixels.iter().rev() {
+            self.set_pixel_index_planes(index as usize, old_planes);
+        }
+        let registers = delta.registers;
+        self.gpr = registers.gpr;
+        self.stack = registers.stack;
+        self.i = registers.i;
+        self.pc = registers.pc;
+        self.sp = registers.sp;
+        self.dt = registers.dt;
+        self.st = registers.st;
+        self.hires = registers.hires;
+        self.rpl = registers.rpl;
+        self.selected_planes = registers.selected_planes;
+        self.audio_pattern = registers.audio_pattern;
+        self.pitch = registers.pitch;
+        self.key_wait = registers.key_wait;
+        self.vblank_wait = registers.vblank_wait;
+        self.cycles_elapsed = registers.cycles_elapsed;
+        self.instructions = registers.instructions;
+        self.frame_cycles = registers.frame_cycles;
+        self.rewind_inputs(registers.keyboard);
+        if let Some(state) = registers.rng {
+            self.rng = Box::new(XorShift::new(state));
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::platform::Platform;
+
+// A decoded instruction. Register operands are register indexes, x and y, not
+// register values. Names follow the Cowgod mnemonics where there is one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    // 00Cn - SCD nibble
+    Scd(u8),
+    // 00Dn - SCU nibble
+    Scu(u8),
+    // 00E0 - CLS
+    Cls,
+    // 00EE - RET
+    Ret,
+    // 00FB - SCR
+    Scr,
+    // 00FC - SCL
+    Scl,
+    // 00FD - EXIT
+    Exit,
+    // 00FE - LOW
+    Low,
+    // 00FF - HIGH
+    High,
+    // 1nnn - JP addr
+    Jp(u16),
+    // 2nnn - CALL addr
+    Call(u16),
+    // 3xkk - SE Vx, byte
+    SeByte { x: u8, kk: u8 },
+    // 4xkk - SNE Vx, byte
+    SneByte { x: u8, kk: u8 },
+    // 5xy0 - SE Vx, Vy
+    SeReg { x: u8, y: u8 },
+    // 5xy2 - LD [I], Vx-Vy
+    StoreRange { x: u8, y: u8 },
+    // 5xy3 - LD Vx-Vy, [I]
+    LoadRange { x: u8, y: u8 },
+    // 6xkk - LD Vx, byte
+    LdByte { x: u8, kk: u8 },
+    // 7xkk - ADD Vx, byte
+    AddByte { x: u8, kk: u8 },
+    // 8xy0 - LD Vx, Vy
+    LdReg { x: u8, y: u8 },
+    // 8xy1 - OR Vx, Vy
+    Or { x: u8, y: u8 },
+    // 8xy2 - AND Vx, Vy
+    And { x: u8, y: u8 },
+    // 8xy3 - XOR Vx, Vy
+    Xor { x: u8, y: u8 },
+    // 8xy4 - ADD Vx, Vy
+    AddReg { x: u8, y: u8 },
+    // 8xy5 - SUB Vx, Vy
+    Sub { x: u8, y: u8 },
+    // 8xy6 - SHR Vx {, Vy}
+    Shr { x: u8, y: u8 },
+    // 8xy7 - SUBN Vx, Vy
+    Subn { x: u8, y: u8 },
+    // 8xyE - SHL Vx {, Vy}
+    Shl { x: u8, y: u8 },
+    // 9xy0 - SNE Vx, Vy
+    SneReg { x: u8, y: u8 },
+    // Annn - LD I, addr
+    LdI(u16),
+    // Bnnn - JP V0, addr
+    JpV0(u16),
+    // Cxkk - RND Vx, byte
+    Rnd { x: u8, kk: u8 },
+    // Dxyn - DRW Vx, Vy, nibble
+    Drw { x: u8, y: u8, n: u8 },
+    // Ex9E - SKP Vx
+    Skp(u8),
+    // ExA1 - SKNP Vx
+    Sknp(u8),
+    // F000 nnnn - LD I, long addr. The address is read from the two bytes
+    // following the opcode when the instruction is executed.
+    LdILong,
+    // Fn01 - PLANE n
+    Plane(u8),
+    // F002 - AUDIO
+    Audio,
+    // Fx07 - LD Vx, DT
+    LdVxDt(u8),
+    // Fx0A - LD Vx, K
+    LdVxK(u8),
+    // Fx15 - LD DT, Vx
+    LdDtVx(u8),
+    // Fx18 - LD ST, Vx
+    LdStVx(u8),
+    // Fx1E - ADD I, Vx
+    AddIVx(u8),
+    // Fx29 - LD F, Vx
+    LdF(u8),
+    // Fx30 - LD HF, Vx
+    LdHf(u8),
+    // Fx33 - LD B, Vx
+    LdB(u8),
+    // Fx3A - PITCH Vx
+    Pitch(u8),
+    // Fx55 - LD [I], Vx
+    LdIVx(u8),
+    // Fx65 - LD Vx, [I]
+    LdVxI(u8),
+    // Fx75 - LD R, Vx
+    LdRVx(u8),
+    // Fx85 - LD Vx, R
+    LdVxR(u8),
+    // Anything else
+    Unknown(u16),
+}
+
+// Split an opcode into its instruction
+pub fn decode(opcode: u16) -> Instruction {
+    let nibbles = [
+        (opcode >> 12) as u8,
+        (opcode >> 8 & 0xF) as u8,
+        (opcode >> 4 & 0xF) as u8,
+        (opcode & 0xF) as u8,
+    ];
+    let nnn = opcode & 0x0FFF;
+    let kk = (opcode & 0x00FF) as u8;
+    match nibbles {
+        [0x0, 0x0, 0xC, n] => Instruction::Scd(n),
+        [0x0, 0x0, 0xD, n] => Instruction::Scu(n),
+        [0x0, 0x0, 0xE, 0x0] => Instruction::Cls,
+        [0x0, 0x0, 0xE, 0xE] => Instruction::Ret,
+        [0x0, 0x0, 0xF, 0xB] => Instruction::Scr,
+        [0x0, 0x0, 0xF, 0xC] => Instruction::Scl,
+        [0x0, 0x0, 0xF, 0xD] => Instruction::Exit,
+        [0x0, 0x0, 0xF, 0xE] => Instruction::Low,
+        [0x0, 0x0, 0xF, 0xF] => Instruction::High,
+        [0x1, _, _, _] => Instruction::Jp(nnn),
+        [0x2, _, _, _] => Instruction::Call(nnn),
+        [0x3, x, _, _] => Instruction::SeByte { x, kk },
+        [0x4, x, _, _] => Instruction::SneByte { x, kk },
+        [0x5, x, y, 0x0] => Instruction::SeReg { x, y },
+        [0x5, x, y, 0x2] => Instruction::StoreRange { x, y },
+        [0x5, x, y, 0x3] => Instruction::LoadRange { x, y },
+        [0x6, x, _, _] => Instruction::LdByte { x, kk },
+        [0x7, x, _, _] => Instruction::AddByte { x, kk },
+        [0x8, x, y, 0x0] => Instruction::LdReg { x, y },
+        [0x8, x, y, 0x1] => Instruction::Or { x, y },
+        [0x8, x, y, 0x2] => Instruction::And { x, y },
+        [0x8, x, y, 0x3] => Instruction::Xor { x, y },
+        [0x8, x, y, 0x4] => Instruction::AddReg { x, y },
+        [0x8, x, y, 0x5] => Instruction::Sub { x, y },
+        [0x8, x, y, 0x6] => Instruction::Shr { x, y },
+        [0x8, x, y, 0x7] => Instruction::Subn { x, y },
+        [0x8, x, y, 0xE] => Instruction::Shl { x, y },
+        [0x9, x, y, 0x0] => Instruction::SneReg { x, y },
+        [0xA, _, _, _] => Instruction::LdI(nnn),
+        [0xB, _, _, _] => Instruction::JpV0(nnn),
+        [0xC, x, _, _] => Instruction::Rnd { x, kk },
+        [0xD, x, y, n] => Instruction::Drw { x, y, n },
+        [0xE, x, 0x9, 0xE] => Instruction::Skp(x),
+        [0xE, x, 0xA, 0x1] => Instruction::Sknp(x),
+        [0xF, 0x0, 0x0, 0x0] => Instruction::LdILong,
+        [0xF, n, 0x0, 0x1] => Instruction::Plane(n),
+        [0xF, 0x0, 0x0, 0x2] => Instruction::Audio,
+        [0xF, x, 0x0, 0x7] => Instruction::LdVxDt(x),
+        [0xF, x, 0x0, 0xA] => Instruction::LdVxK(x),
+        [0xF, x, 0x1, 0x5] => Instruction::LdDtVx(x),
+        [0xF, x, 0x1, 0x8] => Instruction::LdStVx(x),
+        [0xF, x, 0x1, 0xE] => Instruction::AddIVx(x),
+        [0xF, x, 0x2, 0x9] => Instruction::LdF(x),
+        [0xF, x, 0x3, 0x0] => Instruction::LdHf(x),
+        [0xF, x, 0x3, 0x3] => Instruction::LdB(x),
+        [0xF, x, 0x3, 0xA] => Instruction::Pitch(x),
+        [0xF, x, 0x5, 0x5] => Instruction::LdIVx(x),
+        [0xF, x, 0x6, 0x5] => Instruction::LdVxI(x),
+        [0xF, x, 0x7, 0x5] => Instruction::LdRVx(x),
+        [0xF, x, 0x8, 0x5] => Instruction::LdVxR(x),
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+impl Instruction {
+    // Put the instruction back together into its opcode
+    pub fn opcode(&self) -> u16 {
+        fn xy(prefix: u16, x: u8, y: u8, suffix: u16) -> u16 {
+            prefix << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4
+                | suffix
+        }
+        fn xkk(prefix: u16, x: u8, kk: u8) -> u16 {
+            prefix << 12 | (x as u16 & 0xF) << 8 | kk as u16
+        }
+        fn fx(x: u8, suffix: u16) -> u16 {
+            0xF000 | (x as u16 & 0xF) << 8 | suffix
+        }
+        match *self {
+            Instruction::Scd(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::Scu(n) => 0x00D0 | (n as u16 & 0xF),
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::Scr => 0x00FB,
+            Instruction::Scl => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Low => 0x00FE,
+            Instruction::High => 0x00FF,
+            Instruction::Jp(nnn) => 0x1000 | (nnn & 0xFFF),
+            Instruction::Call(nnn) => 0x2000 | (nnn & 0xFFF),
+            Instruction::SeByte { x, kk } => xkk(0x3, x, kk),
+            Instruction::SneByte { x, kk } => xkk(0x4, x, kk),
+            Instruction::SeReg { x, y } => xy(0x5, x, y, 0x0),
+            Instruction::StoreRange { x, y } => xy(0x5, x, y, 0x2),
+            Instruction::LoadRange { x, y } => xy(0x5, x, y, 0x3),
+            Instruction::LdByte { x, kk } => xkk(0x6, x, kk),
+            Instruction::AddByte { x, kk } => xkk(0x7, x, kk),
+            Instruction::LdReg { x, y } => xy(0x8, x, y, 0x0),
+            Instruction::Or { x, y } => xy(0x8, x, y, 0x1),
+            Instruction::And { x, y } => xy(0x8, x, y, 0x2),
+            Instruction::Xor { x, y } => xy(0x8, x, y, 0x3),
+            Instruction::AddReg { x, y } => xy(0x8, x, y, 0x4),
+            Instruction::Sub { x, y } => xy(0x8, x, y, 0x5),
+            Instruction::Shr { x, y } => xy(0x8, x, y, 0x6),
+            Instruction::Subn { x, y } => xy(0x8, x, y, 0x7),
+            Instruction::Shl { x, y } => xy(0x8, x, y, 0xE),
+            Instruction::SneReg { x, y } => xy(0x9, x, y, 0x0),
+            Instruction::LdI(nnn) => 0xA000 | (nnn & 0xFFF),
+            Instruction::JpV0(nnn) => 0xB000 | (nnn & 0xFFF),
+            Instruction::Rnd { x, kk } => xkk(0xC, x, kk),
+            Instruction::Drw { x, y, n } => xy(0xD, x, y, n as u16 & 0xF),
+            Instruction::Skp(x) => xkk(0xE, x, 0x9E),
+            Instruction::Sknp(x) => xkk(0xE, x, 0xA1),
+            Instruction::LdILong => 0xF000,
+            Instruction::Plane(n) => fx(n, 0x01),
+            Instruction::Audio => 0xF002,
+            Instruction::LdVxDt(x) => fx(x, 0x07),
+            Instruction::LdVxK(x) => fx(x, 0x0A),
+            Instruction::LdDtVx(x) => fx(x, 0x15),
+            Instruction::LdStVx(x) => fx(x, 0x18),
+            Instruction::AddIVx(x) => fx(x, 0x1E),
+            Instruction::LdF(x) => fx(x, 0x29),
+            Instruction::LdHf(x) => fx(x, 0x30),
+            Instruction::LdB(x) => fx(x, 0x33),
+            Instruction::Pitch(x) => fx(x, 0x3A),
+            Instruction::LdIVx(x) => fx(x, 0x55),
+            Instruction::LdVxI(x) => fx(x, 0x65),
+            Instruction::LdRVx(x) => fx(x, 0x75),
+            Instruction::LdVxR(x) => fx(x, 0x85),
+            Instruction::Unknown(opcode) => opcode,
+        }
+    }
+
+    // The oldest platform that understands this instruction
+    pub fn platform(&self) -> Platform {
+        match self {
+            Instruction::Scd(_)
+            | Instruction::Scr
+            | Instruction::Scl
+            | Instruction::Exit
+            | Instruction::Low
+            | Instruction::High
+            | Instruction::LdHf(_)
+            | Instruction::LdRVx(_)
+            | Instruction::LdVxR(_) => Platform::SuperChip,
+            Instruction::Scu(_)
+            | Instruction::StoreRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::LdILong
+            | Instruction::Plane(_)
+            | Instruction::Audio
+            | Instruction::Pitch(_) => Platform::XoChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    // Size of the instruction in memory, in bytes
+    pub fn length(&self) -> u16 {
+        match self {
+            Instruction::LdILong => 4,
+            _ => 2,
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::state::crc32;
+use crate::CPU;
+
+// The COSMAC VIP keypad laid over the left of a QWERTY keyboard:
+//
+//     1 2 3 C        1 2 3 4
+//     4 5 6 D        q w e r
+//     7 8 9 E   ->   a s d f
+//     A 0 B F        z x c v
+const COSMAC_LAYOUT: [(&str, u8); 16] = [
+    ("1", 0x1), ("2", 0x2), ("3", 0x3), ("4", 0xC),
+    ("q", 0x4), ("w", 0x5), ("e", 0x6), ("r", 0xD),
+    ("a", 0x7), ("s", 0x8), ("d", 0x9), ("f", 0xE),
+    ("z", 0xA), ("x", 0x0), ("c", 0xB), ("v", 0xF),
+];
+
+// Why a binding or keymap text was rejected
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeymapError {
+    // CHIP-8 keys only go up to F
+    InvalidKey,
+    // Host keys can't be empty or hold control characters
+    InvalidHostKey,
+    // A line of keymap text that isn't a binding, ROM section or comment
+    InvalidLine,
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            KeymapError::InvalidKey => "CHIP-8 keys go from 0 to F",
+            KeymapError::InvalidHostKey => "invalid host key name",
+            KeymapError::InvalidLine => "invalid keymap line",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+// Maps host keys to the CHIP-8 keys they press, so every frontend handles
+// input the same way. Host keys are named as in a browser's KeyboardEvent.key,
+// ignoring case, like "x", "4" or "ArrowLeft".
+//
+// As text, each line binds a host key to a hex key, like "q = 4". Bindings
+// after a "[rom 1A2B3C4D]" line, with the ROM's CRC-32, only apply to that ROM
+// and replace the ones before for it. Lines starting with # are comments.
+// Host keys that would read as something else, like " " or "=", go in double
+// quotes, with \ before any " or \ in them: '" " = 5'.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: BTreeMap<String, u8>,
+    // By the CRC-32 of the ROM they're for
+    overrides: BTreeMap<u32, BTreeMap<String, u8>>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Keymap {
+    // A keymap with nothing bound
+    pub fn new() -> Keymap {
+        Keymap::default()
+    }
+
+    // The COSMAC VIP keypad on 1234, QWER, ASDF and ZXCV
+    pub fn cosmac() -> Keymap {
+        let mut keymap = Keymap::new();
+        for &(host, key) in COSMAC_LAYOUT.iter() {
+            keymap.bindings.insert(host.to_string(), key);
+        }
+        keymap
+    }
+
+    // Each hex digit presses the key it names
+    pub fn hex() -> Keymap {
+        let mut keymap = Keymap::new();
+        for key in 0..16u8 {
+            keymap.bindings.insert(format!("{:x}", key), key);
+        }
+        keymap
+    }
+
+    // Make host press key, replacing what it pressed before
+    pub fn bind(&mut self, host: &str, key: u8) -> Result<(), KeymapError> {
+        let host = validate(host, key)?;
+        self.bindings.insert(host, key);
+        Ok(())
+    }
+
+    // Returns whether host was bound
+    pub fn unbind(&mut self, host: &str) -> bool {
+        self.bindings.remove(&host.to_lowercase()).is_some()
+    }
+
+    // The CHIP-8 key host presses
+    pub fn get(&self, host: &str) -> Option<u8> {
+        self.bindings.get(&host.to_lowercase()).cloned()
+    }
+
+    // Make host press key while rom is running, see for_rom
+    pub fn bind_for_rom(&mut self, rom: &[u8], host: &str, key: u8)
+        -> Result<(), KeymapError> {
+        let host = validate(host, key)?;
+        self.overrides.entry(crc32(rom)).or_default().insert(host, key);
+        Ok(())
+    }
+
+    // The keymap to use while rom is running, with its overrides applied
+    pub fn for_rom(&self, rom: &[u8]) -> Keymap {
+        let mut bindings = self.bindings.clone();
+        if let Some(overrides) = self.overrides.get(&crc32(rom)) {
+            bindings.extend(overrides.clone());
+        }
+        Keymap { bindings, overrides: self.overrides.clone() }
+    }
+
+    // Press the key host is bound to, returning false if it isn't bound so the
+    // frontend can let the event through
+    pub fn press(&self, cpu: &mut CPU, host: &str) -> bool {
+        match self.get(host) {
+            Some(key) => {
+                cpu.set_key_down(key as u32);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn release(&self, cpu: &mut CPU, host: &str) -> bool {
+        match self.get(host) {
+            Some(key) => {
+                cpu.set_key_up(key as u32);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        write_bindings(&mut text, &self.bindings);
+        for (rom, overrides) in &self.overrides {
+            text.push_str(&format!("\n[rom {:08X}]\n", rom));
+            write_bindings(&mut text, overrides);
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Keymap, KeymapError> {
+        let mut keymap = Keymap::new();
+        let mut rom = None;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix("[rom ") {
+                let hash = section.strip_suffix(']')
+                    .filter(|hash| hash.len() == 8)
+                    .and_then(|hash| u32::from_str_radix(hash, 16).ok())
+                    .ok_or(KeymapError::InvalidLine)?;
+                keymap.overrides.entry(hash).or_default();
+                rom = Some(hash);
+                continue;
+            }
+            let (host, key) = match line.strip_prefix('"') {
+                Some(quoted) => unquote(quoted)?,
+                None => line.split_once('=')
+                    .map(|(host, key)| (host.trim().to_string(), key))
+                    .ok_or(KeymapError::InvalidLine)?,
+            };
+            let key = u8::from_str_radix(key.trim(), 16)
+                .map_err(|_| KeymapError::InvalidKey)?;
+            let host = validate(&host, key)?;
+            match rom {
+                Some(hash) => keymap.overrides.entry(hash).or_default()
+                    .insert(host, key),
+                None => keymap.bindings.insert(host, key),
+            };
+        }
+        Ok(keymap)
+    }
+}
+
+fn write_bindings(text: &mut String, bindings: &BTreeMap<String, u8>) {
+    for (host, key) in bindings {
+        text.push_str(&format!("{} = {:X}\n", quote(host), key));
+    }
+}
+
+// host as written in keymap text, quoted if it would read as something else
+fn quote(host: &str) -> String {
+    let plain = !host.starts_with(['"', '#', '['])
+        && !host.contains(|c: char| c.is_whitespace() || c == '=');
+    if plain {
+        return host.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in host.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Split the rest of a binding line after its opening quote into the host key
+// and what's after the '='
+fn unquote(line: &str) -> Result<(String, &str), KeymapError> {
+    let mut host = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let rest = chars.as_str().trim_start().strip_prefix('=')
+                    .ok_or(KeymapError::InvalidLine)?;
+                return Ok((host, rest));
+            }
+            '\\' => {
+                let escaped = chars.next().ok_or(KeymapError::InvalidLine)?;
+                host.push(escaped);
+            }
+            _ => host.push(c),
+        }
+    }
+    Err(KeymapError::InvalidLine)
+}
+
+// The host key name to store, if host and key can be bound
+fn validate(host: &str, key: u8) -> Result<String, KeymapError> {
+    if key >= 16 {
+        return Err(KeymapError::InvalidKey);
+    }
+    if host.is_empty() || host.contains(char::is_control) {
+        return Err(KeymapError::InvalidHostKey);
+    }
+    Ok(host.to_lowercase())
+}
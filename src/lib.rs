@@ -2,56 +2,198 @@ use std::cmp;
 
 #[macro_use]
 mod utils;
-
+mod assembler;
+mod audio;
+mod condition;
+mod debugger;
+mod disassembler;
+mod display;
+mod error;
+mod frame;
+mod history;
+mod instruction;
+mod keymap;
+mod platform;
+mod quirks;
+mod replay;
+mod rng;
+mod state;
+mod timing;
+mod trace;
+mod wait;
+
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
+use audio::Audio;
+use debugger::Debugger;
+use display::{Framebuffer, Renderer};
+use frame::DEFAULT_INSTRUCTIONS_PER_FRAME;
+use history::History;
+use replay::ReplayMode;
+use trace::Tracer;
+use wait::KeyWait;
+
+pub use assembler::{assemble, AssembleError};
+pub use condition::{Condition, ConditionError};
+pub use debugger::{RunResult, StopReason, WatchKind, WatchpointHit};
+pub use disassembler::{
+    disassemble, disassemble_one, DisassembledInstruction, Syntax,
+};
+pub use error::{CpuError, ErrorKind, ExecutionError, StepOutcome};
+pub use instruction::{decode, Instruction};
+pub use keymap::{Keymap, KeymapError};
+pub use platform::Platform;
+pub use quirks::{Quirks, QuirksPreset};
+pub use replay::{InputEvent, InputKind, Replay, ReplayError};
+pub use rng::{Rng, XorShift};
+pub use state::StateError;
+pub use timing::Timing;
+pub use trace::{RegisterChange, TraceEntry};
+pub use wait::CpuState;
+
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
-const PIXEL_ON: [u8; 3] = [102, 255, 102];
-const PIXEL_OFF: [u8; 3] = [0, 0, 0];
-
-#[wasm_bindgen]
+const MEMORY_SIZE: usize = 0x1000;
+const XO_MEMORY_SIZE: usize = 0x10000;
+const STACK_SIZE: usize = 16;
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+const FONT_ADDRESS: u16 = 0x000;
+const BIG_FONT_ADDRESS: u16 = 0x050;
+const RPL_FLAGS: usize = 16;
+const AUDIO_PATTERN_SIZE: usize = 16;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[repr(C)]
 pub struct CPU {
-    memory: [u8; 4096], // RAM
+    memory: [u8; XO_MEMORY_SIZE], // RAM, sized for XO-CHIP
     gpr: [u8; 16usize], // GP registers 0x0 through 0xF
-    stack: [u16; 16], // The stack
+    stack: [u16; STACK_SIZE], // The stack
     i: u16, // I register
     pc: u16, // Program Counter
     sp: u8, // Stack Pointer
     dt: u8, // Delay Timer
     st: u8, // Sound Timer
-    display: [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 3], // Display memory
     keyboard: u16, // Keyboard memory
+    key_wait: Option<KeyWait>, // Fx0A in progress
+    vblank_wait: bool, // Dxyn waiting for the next frame
     last_tick_time: u64, // Unix time of the last tick
+    instructions_per_frame: u32, // Instructions run_frame runs
+    frame_cycles: u32, // Cycles run so far in the current frame
+    timing: Timing, // How many cycles instructions take
+    cycles_elapsed: u64, // Cycles run, by the timing
+    quirks: Quirks, // Interpretation of ambiguous opcodes
+    platform: Platform, // Which opcodes are available
+    hires: bool, // SUPER-CHIP 128x64 mode
+    rpl: [u8; RPL_FLAGS], // SUPER-CHIP RPL user flags
+    framebuffer: Framebuffer, // Bitplanes, sized for hires
+    selected_planes: u8, // XO-CHIP bitplanes affected by drawing
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE], // XO-CHIP 1-bit audio samples
+    pitch: u8, // XO-CHIP audio playback rate
+    debugger: Debugger, // Breakpoints and watchpoints, kept across resets
+    history: History, // Undo information for rewinding
+    tracer: Tracer, // Execution trace recording
+    rng: Box<dyn Rng>, // Random source for RND
+    instructions: u64, // Instructions executed, which replays count time in
+    replay: ReplayMode, // Input recording and playback
+    audio: Audio, // Sound output settings and playback position
+    renderer: Renderer, // Palette and rendered frames for the frontend
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl CPU {
     pub fn new() -> CPU {
+        CPU::new_with_quirks(Quirks::default())
+    }
+
+    pub fn new_with_quirks(quirks: Quirks) -> CPU {
+        CPU::new_with_platform_and_quirks(Platform::Chip8, quirks)
+    }
+
+    pub fn new_with_platform(platform: Platform) -> CPU {
+        CPU::new_with_platform_and_quirks(platform, platform.default_quirks())
+    }
+
+    pub fn new_with_platform_and_quirks(platform: Platform, quirks: Quirks)
+        -> CPU {
         CPU {
-            memory: [0u8; 4096],
+            memory: [0u8; XO_MEMORY_SIZE],
             gpr: [0u8; 16],
-            stack: [0u16; 16],
+            stack: [0u16; STACK_SIZE],
             i: 0u16,
             pc: 0x200u16,
             sp: 0u8,
             dt: 0u8,
             st: 0u8,
-            display: [100u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 3],
             keyboard: 0u16,
+            key_wait: None,
+            vblank_wait: false,
             last_tick_time: 0u64,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            frame_cycles: 0,
+            timing: Timing::default(),
+            cycles_elapsed: 0,
+            quirks,
+            platform,
+            hires: false,
+            rpl: [0u8; RPL_FLAGS],
+            framebuffer: Framebuffer::default(),
+            selected_planes: 0b01,
+            audio_pattern: [0u8; AUDIO_PATTERN_SIZE],
+            pitch: 64,
+            debugger: Debugger::default(),
+            history: History::default(),
+            tracer: Tracer::default(),
+            rng: Box::new(XorShift::default()),
+            instructions: 0,
+            replay: ReplayMode::Idle,
+            audio: Audio::default(),
+            renderer: Renderer::default(),
+        }
+    }
+
+    pub fn get_platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn get_display_width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { LORES_WIDTH }
+    }
+
+    pub fn get_display_height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { LORES_HEIGHT }
+    }
+
+    // Addressable memory, 4K for everything but XO-CHIP's 64K
+    pub fn get_memory_size(&self) -> usize {
+        if self.platform >= Platform::XoChip {
+            XO_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
         }
     }
 
-    pub fn get_display_pointer(&self) -> *const u8 {
-        self.display.as_ptr()
+    pub fn get_selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    pub fn get_pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
 
     pub fn get_memory_pointer(&self) -> *const u8 {
@@ -85,8 +227,33 @@ impl CPU {
             0xF0,0x80,0xF0,0x80,0xF0, // E
             0xF0,0x80,0xF0,0x80,0x80, // F
         ];
-        let target_slice = &mut self.memory[0x000..hex_sprites.len()];
+        // SUPER-CHIP 8x10 digits, with Octo's A-F
+        let big_hex_sprites = [
+            0x3C,0x7E,0xE7,0xC3,0xC3,0xC3,0xC3,0xE7,0x7E,0x3C, // 0
+            0x18,0x38,0x58,0x18,0x18,0x18,0x18,0x18,0x18,0x3C, // 1
+            0x3E,0x7F,0xC3,0x06,0x0C,0x18,0x30,0x60,0xFF,0xFF, // 2
+            0x3C,0x7E,0xC3,0x03,0x0E,0x0E,0x03,0xC3,0x7E,0x3C, // 3
+            0x06,0x0E,0x1E,0x36,0x66,0xC6,0xFF,0xFF,0x06,0x06, // 4
+            0xFF,0xFF,0xC0,0xC0,0xFC,0xFE,0x03,0xC3,0x7E,0x3C, // 5
+            0x3E,0x7C,0xC0,0xC0,0xFC,0xFE,0xC3,0xC3,0x7E,0x3C, // 6
+            0xFF,0xFF,0x03,0x06,0x0C,0x18,0x30,0x60,0x60,0x60, // 7
+            0x3C,0x7E,0xC3,0xC3,0x7E,0x7E,0xC3,0xC3,0x7E,0x3C, // 8
+            0x3C,0x7E,0xC3,0xC3,0x7F,0x3F,0x03,0x03,0x3E,0x7C, // 9
+            0x7E,0xFF,0xC3,0xC3,0xC3,0xFF,0xFF,0xC3,0xC3,0xC3, // A
+            0xFC,0xFC,0xC3,0xC3,0xFC,0xFC,0xC3,0xC3,0xFC,0xFC, // B
+            0x3C,0xFF,0xC3,0xC0,0xC0,0xC0,0xC0,0xC3,0xFF,0x3C, // C
+            0xFC,0xFE,0xC3,0xC3,0xC3,0xC3,0xC3,0xC3,0xFE,0xFC, // D
+            0xFF,0xFF,0xC0,0xC0,0xFF,0xFF,0xC0,0xC0,0xFF,0xFF, // E
+            0xFF,0xFF,0xC0,0xC0,0xFF,0xFF,0xC0,0xC0,0xC0,0xC0, // F
+        ];
+        let font_start = FONT_ADDRESS as usize;
+        let target_slice =
+            &mut self.memory[font_start..font_start + hex_sprites.len()];
         target_slice.clone_from_slice(&hex_sprites);
+        let big_font_start = BIG_FONT_ADDRESS as usize;
+        let target_slice = &mut self.memory
+            [big_font_start..big_font_start + big_hex_sprites.len()];
+        target_slice.clone_from_slice(&big_hex_sprites);
     }
 
     pub fn load_program_memory(&mut self, memory: Vec<u8>) {
@@ -114,12 +281,28 @@ impl CPU {
         self.sp
     }
 
+    // Keys go from 0 to 0xF, others are ignored. See Keymap for mapping host
+    // keys to them.
     pub fn set_key_down(&mut self, key_code: u32) {
-        self.keyboard |= 0b000001 << key_code;
+        if key_code >= 16 {
+            return;
+        }
+        let key = 0b000001 << key_code;
+        if self.keyboard & key == 0
+            && self.record_input(InputKind::KeyDown(key_code as u8)) {
+            self.press_key(key_code as u8);
+        }
     }
 
     pub fn set_key_up(&mut self, key_code: u32) {
-        self.keyboard &= !(0b000001 << key_code);
+        if key_code >= 16 {
+            return;
+        }
+        let key = 0b000001 << key_code;
+        if self.keyboard & key != 0
+            && self.record_input(InputKind::KeyUp(key_code as u8)) {
+            self.release_key(key_code as u8);
+        }
     }
 
     pub fn reset(&mut self) {
@@ -131,66 +314,38 @@ impl CPU {
         self.sp = 0;
         self.dt = 0;
         self.st = 0;
-        self.display.iter_mut().for_each(|m| *m = 0);
-        self.keyboard = 0;    
+        self.keyboard = 0;
+        self.key_wait = None;
+        self.vblank_wait = false;
+        self.hires = false;
+        self.framebuffer.clear();
+        self.selected_planes = 0b01;
+        self.audio_pattern = [0u8; AUDIO_PATTERN_SIZE];
+        self.pitch = 64;
+        self.history.clear();
+        self.frame_cycles = 0;
+        self.cycles_elapsed = 0;
+        self.instructions = 0;
+        self.replay = ReplayMode::Idle;
     }
         
-    // Decrement timers at ~60Hz based on provide unix time
+    // Decrement timers at ~60Hz based on provide unix time. For timing that
+    // doesn't depend on the host's clock use run_frame instead.
     pub fn tick_timers(&mut self, time: u64) {
         let decrement = (time - self.last_tick_time) / 17;
         self.last_tick_time = time - (time - self.last_tick_time) % 17;
-        self.dt -= cmp::min(self.dt as u64, decrement) as u8;
-        self.st -= cmp::min(self.st as u64, decrement) as u8;
+        self.elapse_frames(decrement);
     }
         
+    // Execute a single instruction. If the instruction fails the CPU is left
+    // parked on it, use try_tick to find out why.
     pub fn tick(&mut self) {
-        // Get the 4 nibbles of the instruction, most significant first
-        let instruction_nibbles = [
-            self.memory[self.pc as usize] >> 4,
-            self.memory[self.pc as usize] & 0x0F,
-            self.memory[self.pc as usize + 1] >> 4,
-            self.memory[self.pc as usize + 1] & 0x0F,
-        ];
-        // Now that we have the instruction, increment PC
-        self.pc += 2;
-        // Execute the current instruction
-        match instruction_nibbles {
-            [0x0, 0x0, 0xE, 0x0] => self.instruction_cls(),
-            [0x0, 0x0, 0xE, 0xE] => self.instruction_ret(),
-            [0x1, n1, n2, n3] => self.instruction_jp(n1, n2, n3),
-            [0x2, n1, n2, n3] => self.instruction_call(n1, n2, n3),
-            [0x3, n1, n2, n3] => self.instruction_se_byte(n1, n2, n3),
-            [0x4, n1, n2, n3] => self.instruction_sne_byte(n1, n2, n3),
-            [0x5, n1, n2, 0x0] => self.instruction_se_gpr(n1, n2),
-            [0x6, n1, n2, n3] => self.instruction_ld_byte(n1, n2, n3),
-            [0x7, n1, n2, n3] => self.instruction_add_byte(n1, n2, n3),
-            [0x8, n1, n2, 0x0] => self.instruction_ld_gpr(n1, n2),
-            [0x8, n1, n2, 0x1] => self.instruction_or_gpr(n1, n2),
-            [0x8, n1, n2, 0x2] => self.instruction_and_gpr(n1, n2),
-            [0x8, n1, n2, 0x3] => self.instruction_xor_gpr(n1, n2),
-            [0x8, n1, n2, 0x4] => self.instruction_add_gpr(n1, n2),
-            [0x8, n1, n2, 0x5] => self.instruction_sub_gpr(n1, n2),
-            [0x8, n1, _, 0x6] => self.instruction_shr_gpr(n1),
-            [0x8, n1, n2, 0x7] => self.instruction_subn_gpr(n1, n2),
-            [0x8, n1, _, 0xE] => self.instruction_shl_gpr(n1),
-            [0x9, n1, n2, 0x0] => self.instruction_sne_gpr(n1, n2),
-            [0xA, n1, n2, n3] => self.instruction_ldi(n1, n2, n3),
-            [0xB, n1, n2, n3] => self.instruction_jpv0(n1, n2, n3),
-            [0xC, n1, n2, n3] => self.instruction_rnd(n1, n2, n3),
-            [0xD, n1, n2, n3] => self.instruction_drw(n1, n2, n3),
-            [0xE, n1, 0x9, 0xE] => self.instruction_skp(n1),
-            [0xE, n1, 0xA, 0x1] => self.instruction_sknp(n1),
-            [0xF, n1, 0x0, 0x7] => self.instruction_ld_gpr_dt(n1),
-            [0xF, n1, 0x0, 0xA] => self.instruction_ld_kp(n1),
-            [0xF, n1, 0x1, 0x5] => self.instruction_ld_dt_gpr(n1),
-            [0xF, n1, 0x1, 0x8] => self.instruction_ld_st_gpr(n1),
-            [0xF, n1, 0x1, 0xE] => self.instruction_add_i_gpr(n1),
-            [0xF, n1, 0x2, 0x9] => self.instruction_ld_i_font(n1),
-            [0xF, n1, 0x3, 0x3] => self.instruction_bcd(n1),
-            [0xF, n1, 0x5, 0x5] => self.instruction_ld_i_vx(n1),
-            [0xF, n1, 0x6, 0x5] => self.instruction_ld_vx_i(n1),
-            _ => self.reset(),
-        };
+        let _ = self.try_tick();
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = try_tick))]
+    pub fn try_tick_js(&mut self) -> Result<StepOutcome, ExecutionError> {
+        self.try_tick().map_err(|error| ExecutionError::new(error, self.pc))
     }
 }
 
@@ -202,19 +357,14 @@ impl Default for CPU {
 
 impl CPU {
     pub fn set_memory(&mut self, new_memory: &[u8]) {
-        self.memory = [0u8; 4096];
+        self.memory = [0u8; XO_MEMORY_SIZE];
         let target_slice = &mut self.memory[0x200..0x200 + new_memory.len()];
         target_slice.clone_from_slice(new_memory);
     }
 
-    pub fn set_display(&mut self, new_display: &[u8]) {
-        let target_slice = &mut self.display[..new_display.len()];
-        target_slice.clone_from_slice(new_display);
-    }
-
     // Set the stack values and pad with 0s, set the stack pointer accordingly
     pub fn set_stack(&mut self, new_stack: &[u16]) {
-        self.stack = [0u16; 16];
+        self.stack = [0u16; STACK_SIZE];
         let target_slice = &mut self.stack[0..new_stack.len()];
         target_slice.clone_from_slice(new_stack);
         self.sp = new_stack.len() as u8;
@@ -231,15 +381,27 @@ impl CPU {
         self.i = i;
     }
 
+    // Press and release keys to match the bits of keyboard
     pub fn set_keyboard(&mut self, keyboard: u16) {
-        self.keyboard = keyboard;
+        for key in 0..16 {
+            if keyboard & 1 << key != 0 {
+                self.set_key_down(key);
+            } else {
+                self.set_key_up(key);
+            }
+        }
+    }
+
+    // A bit for each key held down, key 0 in the lowest bit
+    pub fn get_keyboard(&self) -> u16 {
+        self.keyboard
     }
 
     pub fn set_dt(&mut self, dt: u8) {
         self.dt = dt;
     }
 
-    pub fn get_stack(&self) -> [u16; 16] {
+    pub fn get_stack(&self) -> [u16; STACK_SIZE] {
         self.stack
     }
 
@@ -247,19 +409,272 @@ impl CPU {
         self.gpr
     }
 
-    pub fn get_display(&self) -> [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 3] {
-        self.display
+    pub fn get_memory(&self) -> &[u8] {
+        &self.memory[..self.get_memory_size()]
+    }
+
+    pub fn get_audio_pattern(&self) -> [u8; AUDIO_PATTERN_SIZE] {
+        self.audio_pattern
+    }
+
+    // Execute a single instruction, reporting anything that stops it from
+    // running. On error PC is restored so it points at the failed instruction.
+    pub fn try_tick(&mut self) -> Result<StepOutcome, CpuError> {
+        let pc = self.pc;
+        let gpr = self.gpr;
+        let traced = self.trace_fetch();
+        self.debugger.start_instruction();
+        self.begin_history();
+        let result = self.execute_next();
+        if result.is_err() {
+            self.pc = pc;
+        }
+        self.debugger.finish_instruction(pc, result.is_ok());
+        self.finish_history(result.is_ok());
+        if let (Some(instruction), Ok(_)) = (traced, &result) {
+            self.trace(instruction, &gpr);
+        }
+        if result.is_ok() {
+            self.instructions += 1;
+            self.play_inputs();
+        }
+        result
+    }
+
+    fn decrement_timers(&mut self, decrement: u64) {
+        self.history.mark_frame();
+        if decrement > 0 {
+            self.vblank_wait = false;
+        }
+        self.dt -= cmp::min(self.dt as u64, decrement) as u8;
+        self.st -= cmp::min(self.st as u64, decrement) as u8;
+    }
+
+    fn execute_next(&mut self) -> Result<StepOutcome, CpuError> {
+        if self.key_wait.is_some() {
+            self.count_wait();
+            return Ok(self.wait_for_key());
+        }
+        if self.vblank_wait {
+            self.count_wait();
+            return Ok(StepOutcome::WaitingForVblank);
+        }
+        let pc = self.pc;
+        if pc as usize + 1 >= self.get_memory_size() {
+            return Err(CpuError::PcOutOfBounds { pc });
+        }
+        let opcode = (self.read_memory(pc as usize) as u16) << 8
+            | self.read_memory(pc as usize + 1) as u16;
+        // Now that we have the instruction, increment PC
+        self.pc = self.pc.wrapping_add(2);
+        let instruction = decode(opcode);
+        let gpr = self.gpr;
+        let outcome = self.execute(instruction)?;
+        self.count_instruction(instruction, pc, &gpr);
+        Ok(outcome)
+    }
+
+    // Execute an instruction that has already been fetched, so PC should
+    // already point past it as it would during a tick.
+    pub fn execute(&mut self, instruction: Instruction)
+        -> Result<StepOutcome, CpuError> {
+        if instruction.platform() > self.platform {
+            return Err(self.unknown_opcode(instruction));
+        }
+        match instruction {
+            Instruction::Scd(n) => self.instruction_scd(n),
+            Instruction::Scu(n) => self.instruction_scu(n),
+            Instruction::Cls => self.instruction_cls(),
+            Instruction::Ret => self.instruction_ret()?,
+            Instruction::Scr => self.instruction_scr(),
+            Instruction::Scl => self.instruction_scl(),
+            Instruction::Exit => {
+                // Stay on EXIT so the CPU keeps reporting it
+                self.pc = self.pc.wrapping_sub(2);
+                return Ok(StepOutcome::Exited);
+            }
+            Instruction::Low => self.instruction_low(),
+            Instruction::High => self.instruction_high(),
+            Instruction::Jp(nnn) => self.instruction_jp(nnn),
+            Instruction::Call(nnn) => self.instruction_call(nnn)?,
+            Instruction::SeByte { x, kk } => self.instruction_se_byte(x, kk),
+            Instruction::SneByte { x, kk } => self.instruction_sne_byte(x, kk),
+            Instruction::SeReg { x, y } => self.instruction_se_gpr(x, y),
+            Instruction::StoreRange { x, y } =>
+                self.instruction_ld_i_vx_vy(x, y)?,
+            Instruction::LoadRange { x, y } =>
+                self.instruction_ld_vx_vy_i(x, y)?,
+            Instruction::LdByte { x, kk } => self.instruction_ld_byte(x, kk),
+            Instruction::AddByte { x, kk } => self.instruction_add_byte(x, kk),
+            Instruction::LdReg { x, y } => self.instruction_ld_gpr(x, y),
+            Instruction::Or { x, y } => self.instruction_or_gpr(x, y),
+            Instruction::And { x, y } => self.instruction_and_gpr(x, y),
+            Instruction::Xor { x, y } => self.instruction_xor_gpr(x, y),
+            Instruction::AddReg { x, y } => self.instruction_add_gpr(x, y),
+            Instruction::Sub { x, y } => self.instruction_sub_gpr(x, y),
+            Instruction::Shr { x, y } => self.instruction_shr_gpr(x, y),
+            Instruction::Subn { x, y } => self.instruction_subn_gpr(x, y),
+            Instruction::Shl { x, y } => self.instruction_shl_gpr(x, y),
+            Instruction::SneReg { x, y } => self.instruction_sne_gpr(x, y),
+            Instruction::LdI(nnn) => self.instruction_ldi(nnn),
+            Instruction::JpV0(nnn) => self.instruction_jpv0(nnn),
+            Instruction::Rnd { x, kk } => self.instruction_rnd(x, kk),
+            Instruction::Drw { x, y, n } => {
+                self.instruction_drw(x, y, n)?;
+                return Ok(self.wait_for_vblank());
+            }
+            Instruction::Skp(x) => self.instruction_skp(x),
+            Instruction::Sknp(x) => self.instruction_sknp(x),
+            Instruction::LdILong => self.instruction_ldi_long()?,
+            Instruction::Plane(n) => self.instruction_plane(n),
+            Instruction::Audio => self.instruction_audio()?,
+            Instruction::LdVxDt(x) => self.instruction_ld_gpr_dt(x),
+            Instruction::LdVxK(x) => return Ok(self.instruction_ld_kp(x)),
+            Instruction::LdDtVx(x) => self.instruction_ld_dt_gpr(x),
+            Instruction::LdStVx(x) => self.instruction_ld_st_gpr(x),
+            Instruction::AddIVx(x) => self.instruction_add_i_gpr(x),
+            Instruction::LdF(x) => self.instruction_ld_i_font(x),
+            Instruction::LdHf(x) => self.instruction_ld_i_big_font(x),
+            Instruction::LdB(x) => self.instruction_bcd(x)?,
+            Instruction::Pitch(x) => self.instruction_pitch(x),
+            Instruction::LdIVx(x) => self.instruction_ld_i_vx(x)?,
+            Instruction::LdVxI(x) => self.instruction_ld_vx_i(x)?,
+            Instruction::LdRVx(x) => self.instruction_ld_r_vx(x),
+            Instruction::LdVxR(x) => self.instruction_ld_vx_r(x),
+            Instruction::Unknown(_) =>
+                return Err(self.unknown_opcode(instruction)),
+        };
+        Ok(StepOutcome::Executed)
+    }
+
+    // The error for an instruction this CPU can't execute, which PC has
+    // already moved past. Only the opcode itself has been fetched at this
+    // point, any operand words are read during execution.
+    fn unknown_opcode(&self, instruction: Instruction) -> CpuError {
+        CpuError::UnknownOpcode {
+            pc: self.pc.wrapping_sub(2),
+            opcode: instruction.opcode(),
+        }
+    }
+
+    // Memory accessed on behalf of the running program goes through these two
+    // so that watchpoints see it. The address must already be checked.
+    fn read_memory(&mut self, addr: usize) -> u8 {
+        let value = self.memory[addr];
+        self.debugger.watch(addr, WatchKind::Read, value, value);
+        value
+    }
+
+    fn write_memory(&mut self, addr: usize, value: u8) {
+        let old_value = self.memory[addr];
+        self.memory[addr] = value;
+        self.history.record_memory(addr, old_value);
+        self.debugger.watch(addr, WatchKind::Write, old_value, value);
+    }
+
+    // Make sure the len bytes starting at addr all fall inside memory
+    fn check_memory_range(&self, addr: usize, len: usize)
+        -> Result<(), CpuError> {
+        let memory_size = self.get_memory_size();
+        if addr + len > memory_size {
+            return Err(CpuError::MemoryOutOfBounds {
+                addr: cmp::max(addr, memory_size),
+            });
+        }
+        Ok(())
+    }
+
+    // Move PC past the next instruction, which is 4 bytes long if it's the
+    // XO-CHIP long load
+    fn skip_next_instruction(&mut self) {
+        let pc = self.pc as usize;
+        let long_load = self.platform >= Platform::XoChip
+            && pc + 1 < self.get_memory_size()
+            && self.memory[pc] == 0xF0 && self.memory[pc + 1] == 0x00;
+        let length = if long_load { 4 } else { 2 };
+        self.pc = self.pc.wrapping_add(length);
     }
 
-    pub fn get_memory(&self) -> [u8; 4096] {
-        self.memory
+    pub fn get_rpl_flags(&self) -> [u8; RPL_FLAGS] {
+        self.rpl
+    }
+
+    // The bitplanes set for the pixel at (x, y)
+    pub fn get_pixel_planes(&self, x: usize, y: usize) -> u8 {
+        self.framebuffer.get(y * self.get_display_width() + x)
+    }
+
+    fn set_pixel_planes(&mut self, x: usize, y: usize, planes: u8) {
+        let index = y * self.get_display_width() + x;
+        self.set_pixel_index_planes(index, planes);
+    }
+
+    // Update the bitplanes of the pixel at index, remembering the old ones
+    // for rewinding
+    fn set_pixel_index_planes(&mut self, index: usize, planes: u8) {
+        let old_planes = self.framebuffer.get(index);
+        if old_planes != planes {
+            self.history.record_pixel(index, old_planes);
+            self.framebuffer.set(index, planes);
+        }
+    }
+
+    // Move the selected planes of the display by (dx, dy), filling the gap with
+    // blank pixels
+    fn scroll_display(&mut self, dx: isize, dy: isize) {
+        let width = self.get_display_width() as isize;
+        let height = self.get_display_height() as isize;
+        let mask = self.selected_planes;
+        let mut scrolled = [0u8; HIRES_WIDTH * HIRES_HEIGHT];
+        for y in 0..height {
+            for x in 0..width {
+                let (source_x, source_y) = (x - dx, y - dy);
+                let source = if source_x >= 0 && source_x < width
+                    && source_y >= 0 && source_y < height {
+                    self.get_pixel_planes(source_x as usize, source_y as usize)
+                } else {
+                    0
+                };
+                let current = self.get_pixel_planes(x as usize, y as usize);
+                scrolled[(y * width + x) as usize] =
+                    current & !mask | source & mask;
+            }
+        }
+        let pixel_count = (width * height) as usize;
+        for (index, &planes) in scrolled[..pixel_count].iter().enumerate() {
+            self.set_pixel_index_planes(index, planes);
+        }
+    }
+
+    // 00Cn - SCD nibble
+    // Scroll the display down by n lines.
+    fn instruction_scd(&mut self, n: u8) {
+        self.scroll_display(0, n as isize);
+    }
+
+    // 00Dn - SCU nibble
+    // Scroll the display up by n lines.
+    fn instruction_scu(&mut self, n: u8) {
+        self.scroll_display(0, -(n as isize));
     }
 
     // 00E0 - CLS
     // Clear the display.
+    //
+    // On XO-CHIP only the selected planes are cleared.
     fn instruction_cls(&mut self) {
-        self.display.clone_from_slice(
-            &[0x0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 3]);
+        let mask = self.selected_planes;
+        for index in 0..HIRES_WIDTH * HIRES_HEIGHT {
+            let planes = self.framebuffer.get(index);
+            self.set_pixel_index_planes(index, planes & !mask);
+        }
+    }
+
+    // Clear every plane, whatever is selected
+    fn clear_display(&mut self) {
+        for index in 0..HIRES_WIDTH * HIRES_HEIGHT {
+            self.set_pixel_index_planes(index, 0);
+        }
     }
 
     // 00EE - RET
@@ -267,20 +682,47 @@ impl CPU {
     //
     // The interpreter sets the program counter to the address at the top of the
     // stack, then subtracts 1 from the stack pointer.
-    fn instruction_ret(&mut self) {
+    fn instruction_ret(&mut self) -> Result<(), CpuError> {
         if self.sp == 0 {
-            panic!("Stack underflow!");
+            return Err(CpuError::StackUnderflow);
         }
         self.sp -= 1;
         self.pc =  self.stack[self.sp as usize];
+        Ok(())
+    }
+
+    // 00FB - SCR
+    // Scroll the display right by 4 pixels.
+    fn instruction_scr(&mut self) {
+        self.scroll_display(4, 0);
+    }
+
+    // 00FC - SCL
+    // Scroll the display left by 4 pixels.
+    fn instruction_scl(&mut self) {
+        self.scroll_display(-4, 0);
+    }
+
+    // 00FE - LOW
+    // Switch to the 64x32 low resolution display and clear it.
+    fn instruction_low(&mut self) {
+        self.hires = false;
+        self.clear_display();
+    }
+
+    // 00FF - HIGH
+    // Switch to the 128x64 high resolution display and clear it.
+    fn instruction_high(&mut self) {
+        self.hires = true;
+        self.clear_display();
     }
 
     // 1nnn - JP addr
     // Jump to location nnn.
     //
     // The interpreter sets the program counter to nnn.
-    fn instruction_jp(&mut self, n1: u8, n2: u8, n3: u8) {
-        self.pc = ((n1 as u16) << 8) | ((n2 as u16) << 4) | (n3 as u16);
+    fn instruction_jp(&mut self, nnn: u16) {
+        self.pc = nnn;
     }
 
     // 2nnn - CALL addr
@@ -288,10 +730,14 @@ impl CPU {
     //
     // The interpreter increments the stack pointer, then puts the current PC on
     // the top of the stack. The PC is then set to nnn.
-    fn instruction_call(&mut self, n1: u8, n2: u8, n3: u8) {
+    fn instruction_call(&mut self, nnn: u16) -> Result<(), CpuError> {
+        if self.sp as usize >= STACK_SIZE {
+            return Err(CpuError::StackOverflow);
+        }
         self.stack[self.sp as usize] = self.pc;
         self.sp += 1;
-        self.pc = ((n1 as u16) << 8) | ((n2 as u16) << 4) | (n3 as u16);
+        self.pc = nnn;
+        Ok(())
     }
 
     // 3xkk - SE Vx, byte
@@ -299,9 +745,9 @@ impl CPU {
     //
     // The interpreter compares register Vx to kk, and if they are equal,
     // increments the program counter by 2.
-    fn instruction_se_byte(&mut self, n1: u8, n2: u8, n3: u8) {
-        if self.gpr[n1 as usize] == n2 << 4 | n3 {
-            self.pc += 2;
+    fn instruction_se_byte(&mut self, x: u8, kk: u8) {
+        if self.gpr[x as usize] == kk {
+            self.skip_next_instruction();
         }
     }
 
@@ -309,9 +755,9 @@ impl CPU {
     // Skip next instruction if Vx != kk.
     //
     // The interpreter compares register Vx to kk, and if they are not equal, increments the program counter by 2.
-    fn instruction_sne_byte(&mut self, n1: u8, n2: u8, n3: u8) {
-        if self.gpr[n1 as usize] != n2 << 4 | n3 {
-            self.pc += 2;
+    fn instruction_sne_byte(&mut self, x: u8, kk: u8) {
+        if self.gpr[x as usize] != kk {
+            self.skip_next_instruction();
         }
     }
 
@@ -320,9 +766,9 @@ impl CPU {
     //
     // The interpreter compares register Vx to register Vy, and if they are
     // equal, increments the program counter by 2.
-    fn instruction_se_gpr(&mut self, n1: u8, n2: u8) {
-        if self.gpr[n1 as usize] == self.gpr[n2 as usize] {
-            self.pc += 2;
+    fn instruction_se_gpr(&mut self, x: u8, y: u8) {
+        if self.gpr[x as usize] == self.gpr[y as usize] {
+            self.skip_next_instruction();
         }
     }
 
@@ -330,8 +776,8 @@ impl CPU {
     // Set Vx = kk.
     //
     // The interpreter puts the value kk into register Vx.
-    fn instruction_ld_byte(&mut self, n1: u8, n2: u8, n3: u8) {
-        self.gpr[n1 as usize] = n2 << 4 | n3;
+    fn instruction_ld_byte(&mut self, x: u8, kk: u8) {
+        self.gpr[x as usize] = kk;
     }
 
     // 7xkk - ADD Vx, byte
@@ -339,16 +785,16 @@ impl CPU {
     //
     // Adds the value kk to the value of register Vx, then stores the result in
     // Vx.
-    fn instruction_add_byte(&mut self, n1: u8, n2: u8, n3: u8) {
-        self.gpr[n1 as usize] += n2 << 4 | n3;
+    fn instruction_add_byte(&mut self, x: u8, kk: u8) {
+        self.gpr[x as usize] = self.gpr[x as usize].wrapping_add(kk);
     }
 
     // 8xy0 - LD Vx, Vy
     // Set Vx = Vy.
     //
     // Stores the value of register Vy in register Vx.
-    fn instruction_ld_gpr(&mut self, n1: u8, n2: u8) {
-        self.gpr[n1 as usize] = self.gpr[n2 as usize];
+    fn instruction_ld_gpr(&mut self, x: u8, y: u8) {
+        self.gpr[x as usize] = self.gpr[y as usize];
     }
 
     // 8xy1 - OR Vx, Vy
@@ -358,8 +804,11 @@ impl CPU {
     // in Vx. A bitwise OR compares the corrseponding bits from two values, and
     // if either bit is 1, then the same bit in the result is also 1. Otherwise,
     // it is 0.
-    fn instruction_or_gpr(&mut self, n1: u8, n2: u8) {
-        self.gpr[n1 as usize] |= self.gpr[n2 as usize];
+    fn instruction_or_gpr(&mut self, x: u8, y: u8) {
+        self.gpr[x as usize] |= self.gpr[y as usize];
+        if self.quirks.logic_resets_vf {
+            self.gpr[0xF] = 0;
+        }
     }
 
 
@@ -370,8 +819,11 @@ impl CPU {
     // in Vx. A bitwise AND compares the corrseponding bits from two values, and
     // if both bits are 1, then the same bit in the result is also 1.
     // Otherwise, it is 0.
-    fn instruction_and_gpr(&mut self, n1: u8, n2: u8) {
-        self.gpr[n1 as usize] &= self.gpr[n2 as usize];
+    fn instruction_and_gpr(&mut self, x: u8, y: u8) {
+        self.gpr[x as usize] &= self.gpr[y as usize];
+        if self.quirks.logic_resets_vf {
+            self.gpr[0xF] = 0;
+        }
     }
 
     // 8xy3 - XOR Vx, Vy
@@ -381,8 +833,11 @@ impl CPU {
     // the result in Vx. An exclusive OR compares the corrseponding bits from
     // two values, and if the bits are not both the same, then the corresponding
     // bit in the result is set to 1. Otherwise, it is 0.
-    fn instruction_xor_gpr(&mut self, n1: u8, n2: u8) {
-        self.gpr[n1 as usize] ^= self.gpr[n2 as usize];
+    fn instruction_xor_gpr(&mut self, x: u8, y: u8) {
+        self.gpr[x as usize] ^= self.gpr[y as usize];
+        if self.quirks.logic_resets_vf {
+            self.gpr[0xF] = 0;
+        }
     }
 
     // 8xy4 - ADD Vx, Vy
@@ -391,10 +846,10 @@ impl CPU {
     // The values of Vx and Vy are added together. If the result is greater than
     // 8 bits (i.e., > 255,) VF is set to 1, otherwise 0. Only the lowest 8 bits
     // of the result are kept, and stored in Vx.
-    fn instruction_add_gpr(&mut self, n1: u8, n2: u8) {
-        let add_result = self.gpr[n1 as usize]
-            .overflowing_add(self.gpr[n2 as usize]);
-        self.gpr[n1 as usize] = add_result.0;
+    fn instruction_add_gpr(&mut self, x: u8, y: u8) {
+        let add_result = self.gpr[x as usize]
+            .overflowing_add(self.gpr[y as usize]);
+        self.gpr[x as usize] = add_result.0;
         self.gpr[0xF] = if add_result.1 { 1 } else { 0 };
     }
 
@@ -403,10 +858,10 @@ impl CPU {
     //
     // If Vx > Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from
     // Vx, and the results stored in Vx.
-    fn instruction_sub_gpr(&mut self, n1: u8, n2: u8) {
-        let sub_result = self.gpr[n1 as usize]
-            .overflowing_sub(self.gpr[n2 as usize]);
-        self.gpr[n1 as usize] = sub_result.0;
+    fn instruction_sub_gpr(&mut self, x: u8, y: u8) {
+        let sub_result = self.gpr[x as usize]
+            .overflowing_sub(self.gpr[y as usize]);
+        self.gpr[x as usize] = sub_result.0;
         self.gpr[0xF] = if sub_result.1 { 0 } else { 1 };
     }
 
@@ -414,10 +869,13 @@ impl CPU {
     // Set Vx = Vx SHR 1.
     //
     // If the least-significant bit of Vx is 1, then VF is set to 1, otherwise
-    // 0. Then Vx is divided by 2.
-    fn instruction_shr_gpr(&mut self, n1: u8) {
-        self.gpr[0xF] = self.gpr[n1 as usize] & 0b00000001;
-        self.gpr[n1 as usize] >>= 1;
+    // 0. Then Vx is divided by 2. With the shift_uses_vy quirk Vy is shifted
+    // and the result stored in Vx.
+    fn instruction_shr_gpr(&mut self, x: u8, y: u8) {
+        let source = if self.quirks.shift_uses_vy { y } else { x };
+        let value = self.gpr[source as usize];
+        self.gpr[x as usize] = value >> 1;
+        self.gpr[0xF] = value & 0b00000001;
     }
 
     // 8xy7 - SUBN Vx, Vy
@@ -425,10 +883,10 @@ impl CPU {
     //
     // If Vy > Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from
     // Vy, and the results stored in Vx.
-    fn instruction_subn_gpr(&mut self, n1: u8, n2: u8) {
-        let sub_result = self.gpr[n2 as usize]
-            .overflowing_sub(self.gpr[n1 as usize]);
-        self.gpr[n1 as usize] = sub_result.0;
+    fn instruction_subn_gpr(&mut self, x: u8, y: u8) {
+        let sub_result = self.gpr[y as usize]
+            .overflowing_sub(self.gpr[x as usize]);
+        self.gpr[x as usize] = sub_result.0;
         self.gpr[0xF] = if sub_result.1 { 0 } else { 1 };
     }
 
@@ -436,18 +894,21 @@ impl CPU {
     // Set Vx = Vx SHL 1.
     //
     // If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is multiplied by 2.
-    fn instruction_shl_gpr(&mut self, n1: u8) {
-        self.gpr[0xF] = if self.gpr[n1 as usize] & 0b10000000 == 0 { 0 } else { 1 };
-        self.gpr[n1 as usize] <<= 1;
+    // With the shift_uses_vy quirk Vy is shifted and the result stored in Vx.
+    fn instruction_shl_gpr(&mut self, x: u8, y: u8) {
+        let source = if self.quirks.shift_uses_vy { y } else { x };
+        let value = self.gpr[source as usize];
+        self.gpr[x as usize] = value << 1;
+        self.gpr[0xF] = if value & 0b10000000 == 0 { 0 } else { 1 };
     }
 
     // 9xy0 - SNE Vx, Vy
     // Skip next instruction if Vx != Vy.
     //
     // The values of Vx and Vy are compared, and if they are not equal, the program counter is increased by 2.
-    fn instruction_sne_gpr(&mut self, n1: u8, n2: u8) {
-        if self.gpr[n1 as usize] != self.gpr[n2 as usize] {
-            self.pc += 2;
+    fn instruction_sne_gpr(&mut self, x: u8, y: u8) {
+        if self.gpr[x as usize] != self.gpr[y as usize] {
+            self.skip_next_instruction();
         }
     }
 
@@ -455,17 +916,18 @@ impl CPU {
     // Set I = nnn.
     //
     // The value of register I is set to nnn.
-    fn instruction_ldi(&mut self, n1: u8, n2: u8, n3: u8) {
-        self.i = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
+    fn instruction_ldi(&mut self, nnn: u16) {
+        self.i = nnn;
     }
 
     // Bnnn - JP V0, addr
     // Jump to location nnn + V0.
     //
-    // The program counter is set to nnn plus the value of V0.
-    fn instruction_jpv0(&mut self, n1: u8, n2: u8, n3: u8) {
-        self.pc = self.gpr[0] as u16
-            + ((n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16);
+    // The program counter is set to nnn plus the value of V0. With the
+    // jump_uses_vx quirk this becomes xnn plus the value of Vx.
+    fn instruction_jpv0(&mut self, nnn: u16) {
+        let register = if self.quirks.jump_uses_vx { nnn >> 8 } else { 0 };
+        self.pc = self.gpr[register as usize] as u16 + nnn;
     }
 
     // Cxkk - RND Vx, byte
@@ -474,9 +936,8 @@ impl CPU {
     // The interpreter generates a random number from 0 to 255, which is then
     // ANDed with the value kk. The results are stored in Vx. See instruction
     // 8xy2 for more information on AND.
-    fn instruction_rnd(&mut self, n1: u8, n2: u8, n3: u8) {
-        self.gpr[n1 as usize] = (js_sys::Math::random() * 256f64).floor() as u8
-            & (n2 << 4 | n3);
+    fn instruction_rnd(&mut self, x: u8, kk: u8) {
+        self.gpr[x as usize] = self.rng.next_byte() & kk;
     }
 
     // Dxyn - DRW Vx, Vy, nibble
@@ -488,46 +949,74 @@ impl CPU {
     // (Vx, Vy). Sprites are XORed onto the existing screen. If this causes any
     // pixels to be erased, VF is set to 1, otherwise it is set to 0. If the
     // sprite is positioned so part of it is outside the coordinates of the
-    // display, it wraps around to the opposite side of the screen.
+    // display, it wraps around to the opposite side of the screen. With the
+    // clip_sprites quirk only the origin wraps, the parts of the sprite that
+    // fall off the edge aren't drawn.
     // See instruction 8xy3 for more information on XOR, and section 2.4,
     // Display, for more information on the Chip-8 screen and sprites.
-    fn instruction_drw(&mut self, n1: u8, n2: u8, n3: u8) {
-        self.gpr[0xF] = 0;
-        let x_origin = self.gpr[n1 as usize] as usize;
-        let y_origin = self.gpr[n2 as usize] as usize;
+    //
+    // On SUPER-CHIP, Dxy0 draws a 16x16 sprite made of 32 bytes, two per row.
+    // On XO-CHIP the sprite is drawn to each selected plane in turn, with the
+    // data for the second plane directly following the first.
+    fn instruction_drw(&mut self, x: u8, y: u8, n: u8)
+        -> Result<(), CpuError> {
+        let (sprite_width, sprite_height) =
+            if n == 0 && self.platform >= Platform::SuperChip {
+                (16, 16)
+            } else {
+                (8, n as usize)
+            };
+        let bytes_per_row = sprite_width / 8;
+        let plane_length = sprite_height * bytes_per_row;
+        let planes: Vec<u8> = [0b01, 0b10].iter().cloned()
+            .filter(|plane| self.selected_planes & plane != 0)
+            .collect();
         // Find the sprite
         let sprite_start = self.i as usize;
-        let sprite_end = sprite_start + n3 as usize;
-        let sprite = self.memory[sprite_start..sprite_end].iter().enumerate();
+        self.check_memory_range(sprite_start, plane_length * planes.len())?;
+        self.gpr[0xF] = 0;
+        let width = self.get_display_width();
+        let height = self.get_display_height();
+        let x_origin = self.gpr[x as usize] as usize % width;
+        let y_origin = self.gpr[y as usize] as usize % height;
         // Draw the sprite
-        for (y, value) in sprite {
-            for x in 0..8 {
-                // If the value is 0, we don't need to do anything
-                if value & 0b10000000 >> x == 0 { continue }
-                // Get the (possibly wrapped) coords
-                let x_pos = (x_origin + x) % DISPLAY_WIDTH;
-                let y_pos = (y_origin + y) % DISPLAY_HEIGHT;
-                // Get the index of the pixel
-                let pixel_index = (y_pos * DISPLAY_WIDTH + x_pos) * 3;
-                let pixel = &mut self.display[pixel_index..pixel_index + 3];
-                // If the value of the targeted pixel is not zero set off + VF
-                if pixel[0] == 0 {
-                    pixel.clone_from_slice(&PIXEL_ON);
-                } else {
-                    self.gpr[0xF] = 1;
-                    pixel.clone_from_slice(&PIXEL_OFF);
+        for (plane_index, plane) in planes.into_iter().enumerate() {
+            let plane_start = sprite_start + plane_index * plane_length;
+            for row in 0..sprite_height {
+                // Left align the row in 16 bits, whatever the sprite width
+                let row_start = plane_start + row * bytes_per_row;
+                let mut value = (self.read_memory(row_start) as u16) << 8;
+                if bytes_per_row == 2 {
+                    value |= self.read_memory(row_start + 1) as u16;
+                }
+                for column in 0..sprite_width {
+                    // If the value is 0, we don't need to do anything
+                    if value & 0x8000 >> column == 0 { continue }
+                    // Skip anything off screen if we're clipping
+                    if self.quirks.clip_sprites && (x_origin + column >= width
+                        || y_origin + row >= height) { continue }
+                    // Get the (possibly wrapped) coords
+                    let x_pos = (x_origin + column) % width;
+                    let y_pos = (y_origin + row) % height;
+                    // If the targeted pixel is already on, turn it off + set VF
+                    let pixel_planes = self.get_pixel_planes(x_pos, y_pos);
+                    if pixel_planes & plane != 0 {
+                        self.gpr[0xF] = 1;
+                    }
+                    self.set_pixel_planes(x_pos, y_pos, pixel_planes ^ plane);
                 }
             }
         }
+        Ok(())
     }
 
     // Ex9E - SKP Vx
     // Skip next instruction if key with the value of Vx is pressed.
     //
     // Checks the keyboard, and if the key corresponding to the value of Vx is currently in the down position, PC is increased by 2.
-    fn instruction_skp(&mut self, n1: u8) {
-        if 1u16 << self.gpr[n1 as usize] & self.keyboard > 0 {
-            self.pc += 2;
+    fn instruction_skp(&mut self, x: u8) {
+        if self.key_pressed(self.gpr[x as usize]) {
+            self.skip_next_instruction();
         }
     }
 
@@ -535,44 +1024,33 @@ impl CPU {
     // Skip next instruction if key with the value of Vx is not pressed.
     //
     // Checks the keyboard, and if the key corresponding to the value of Vx is currently in the up position, PC is increased by 2.
-    fn instruction_sknp(&mut self, n1: u8) {
-        if 1u16 << self.gpr[n1 as usize] & self.keyboard == 0 {
-            self.pc += 2;
+    fn instruction_sknp(&mut self, x: u8) {
+        if !self.key_pressed(self.gpr[x as usize]) {
+            self.skip_next_instruction();
         }
     }
 
+    // Keys past F can't be pressed
+    fn key_pressed(&self, key: u8) -> bool {
+        1u16.checked_shl(key as u32)
+            .is_some_and(|bit| bit & self.keyboard != 0)
+    }
+
     // Fx07 - LD Vx, DT
     // Set Vx = delay timer value.
     //
     // The value of DT is placed into Vx.
-    fn instruction_ld_gpr_dt(&mut self, n1: u8) {
-        self.gpr[n1 as usize] = self.dt;
+    fn instruction_ld_gpr_dt(&mut self, x: u8) {
+        self.gpr[x as usize] = self.dt;
     }
 
 
-    // Fx0A - LD Vx, K
-    // Wait for a key press, store the value of the key in Vx.
-    //
-    // All execution stops until a key is pressed, then the value of that key is stored in Vx.
-    fn instruction_ld_kp(&mut self, n1: u8) {
-        if self.keyboard == 0 {
-            self.pc -= 2;
-        } else {
-            for i in 0..16 {
-                if (self.keyboard >> i) & 0x1 == 0x1 {
-                    self.gpr[n1 as usize] = i;
-                    break;
-                }
-            }
-        }
-    }
-
     // Fx15 - LD DT, Vx
     // Set delay timer = Vx.
     //
     // DT is set equal to the value of Vx.
-    fn instruction_ld_dt_gpr(&mut self, n1: u8) {
-        self.dt = self.gpr[n1 as usize];
+    fn instruction_ld_dt_gpr(&mut self, x: u8) {
+        self.dt = self.gpr[x as usize];
     }
 
 
@@ -580,16 +1058,16 @@ impl CPU {
     // Set sound timer = Vx.
     //
     // ST is set equal to the value of Vx.
-    fn instruction_ld_st_gpr(&mut self, n1: u8) {
-        self.st = self.gpr[n1 as usize];
+    fn instruction_ld_st_gpr(&mut self, x: u8) {
+        self.st = self.gpr[x as usize];
     }
 
     // Fx1E - ADD I, Vx
     // Set I = I + Vx.
     //
     // The values of I and Vx are added, and the results are stored in I.
-    fn instruction_add_i_gpr(&mut self, n1: u8) {
-        self.i += self.gpr[n1 as usize] as u16;
+    fn instruction_add_i_gpr(&mut self, x: u8) {
+        self.i = self.i.wrapping_add(self.gpr[x as usize] as u16);
     }
 
 
@@ -597,41 +1075,149 @@ impl CPU {
     // Set I = location of sprite for digit Vx.
     //
     // The value of I is set to the location for the hexadecimal sprite corresponding to the value of Vx. See section 2.4, Display, for more information on the Chip-8 hexadecimal font.
-    fn instruction_ld_i_font(&mut self, n1: u8) {
-        self.i = self.gpr[n1 as usize] as u16 * 5;
+    fn instruction_ld_i_font(&mut self, x: u8) {
+        self.i = FONT_ADDRESS + self.gpr[x as usize] as u16 * 5;
+    }
+
+    // Fx30 - LD HF, Vx
+    // Set I = location of the 8x10 SUPER-CHIP sprite for digit Vx.
+    fn instruction_ld_i_big_font(&mut self, x: u8) {
+        self.i = BIG_FONT_ADDRESS + (self.gpr[x as usize] & 0xF) as u16 * 10;
     }
 
     // Fx33 - LD B, Vx
     // Store BCD representation of Vx in memory locations I, I+1, and I+2.
     //
     // The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I, the tens digit at location I+1, and the ones digit at location I+2.
-    fn instruction_bcd(&mut self, n1: u8) {
-        let value = self.gpr[n1 as usize];
+    fn instruction_bcd(&mut self, x: u8) -> Result<(), CpuError> {
+        let value = self.gpr[x as usize];
         let output_address = self.i as usize;
-        self.memory[output_address] = value / 100;
-        self.memory[output_address + 1] = value % 100 / 10;
-        self.memory[output_address + 2] = value % 10;
+        self.check_memory_range(output_address, 3)?;
+        self.write_memory(output_address, value / 100);
+        self.write_memory(output_address + 1, value % 100 / 10);
+        self.write_memory(output_address + 2, value % 10);
+        Ok(())
     }
 
     // Fx55 - LD [I], Vx
     // Store registers V0 through Vx in memory starting at location I.
     //
     // The interpreter copies the values of registers V0 through Vx into memory, starting at the address in I.
-    fn instruction_ld_i_vx(&mut self, n1: u8) {
-        for register_index in 0..=n1 as usize {
-            self.memory[self.i as usize + register_index] =
-                self.gpr[register_index as usize];
+    // With the load_store_increments_i quirk I is left at I + x + 1.
+    fn instruction_ld_i_vx(&mut self, x: u8) -> Result<(), CpuError> {
+        self.check_memory_range(self.i as usize, x as usize + 1)?;
+        for register_index in 0..=x as usize {
+            self.write_memory(self.i as usize + register_index,
+                self.gpr[register_index]);
         }
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(x as u16 + 1);
+        }
+        Ok(())
     }
 
     // Fx65 - LD Vx, [I]
     // Read registers V0 through Vx from memory starting at location I.
     //
     // The interpreter reads values from memory starting at location I into registers V0 through Vx.
-    fn instruction_ld_vx_i(&mut self, n1: u8) {
-        for register_index in 0..=n1 as usize {
+    // With the load_store_increments_i quirk I is left at I + x + 1.
+    fn instruction_ld_vx_i(&mut self, x: u8) -> Result<(), CpuError> {
+        self.check_memory_range(self.i as usize, x as usize + 1)?;
+        for register_index in 0..=x as usize {
             self.gpr[register_index] =
-                self.memory[self.i as usize + register_index];
+                self.read_memory(self.i as usize + register_index);
+        }
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(x as u16 + 1);
+        }
+        Ok(())
+    }
+
+    // Fx75 - LD R, Vx
+    // Store registers V0 through Vx in the RPL user flags.
+    fn instruction_ld_r_vx(&mut self, x: u8) {
+        let count = x as usize + 1;
+        self.rpl[..count].clone_from_slice(&self.gpr[..count]);
+    }
+
+    // Fx85 - LD Vx, R
+    // Read registers V0 through Vx from the RPL user flags.
+    fn instruction_ld_vx_r(&mut self, x: u8) {
+        let count = x as usize + 1;
+        self.gpr[..count].clone_from_slice(&self.rpl[..count]);
+    }
+
+    // 5xy2 - LD [I], Vx-Vy
+    // Store registers Vx through Vy in memory starting at location I.
+    //
+    // The registers are stored in reverse order if x > y. I is not modified.
+    fn instruction_ld_i_vx_vy(&mut self, x: u8, y: u8)
+        -> Result<(), CpuError> {
+        let registers = Self::register_range(x, y);
+        self.check_memory_range(self.i as usize, registers.len())?;
+        for (offset, register_index) in registers.into_iter().enumerate() {
+            self.write_memory(self.i as usize + offset,
+                self.gpr[register_index]);
         }
+        Ok(())
     }
-}
\ No newline at end of file
+
+    // 5xy3 - LD Vx-Vy, [I]
+    // Read registers Vx through Vy from memory starting at location I.
+    //
+    // The registers are loaded in reverse order if x > y. I is not modified.
+    fn instruction_ld_vx_vy_i(&mut self, x: u8, y: u8)
+        -> Result<(), CpuError> {
+        let registers = Self::register_range(x, y);
+        self.check_memory_range(self.i as usize, registers.len())?;
+        for (offset, register_index) in registers.into_iter().enumerate() {
+            self.gpr[register_index] = self.read_memory(self.i as usize + offset);
+        }
+        Ok(())
+    }
+
+    // The register indexes from x to y inclusive, counting down if x > y
+    fn register_range(x: u8, y: u8) -> Vec<usize> {
+        if x <= y {
+            (x as usize..=y as usize).collect()
+        } else {
+            (y as usize..=x as usize).rev().collect()
+        }
+    }
+
+    // F000 nnnn - LD I, long addr
+    // Set I = nnnn.
+    //
+    // The 16 bit address is stored in the two bytes following the instruction.
+    fn instruction_ldi_long(&mut self) -> Result<(), CpuError> {
+        let operand_address = self.pc as usize;
+        self.check_memory_range(operand_address, 2)?;
+        self.i = (self.read_memory(operand_address) as u16) << 8
+            | self.read_memory(operand_address + 1) as u16;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // Fn01 - PLANE n
+    // Select the bitplanes affected by drawing, clearing and scrolling.
+    fn instruction_plane(&mut self, x: u8) {
+        self.selected_planes = x & 0b11;
+    }
+
+    // F002 - AUDIO
+    // Load the 16 byte audio pattern buffer from memory starting at I.
+    fn instruction_audio(&mut self) -> Result<(), CpuError> {
+        let start = self.i as usize;
+        self.check_memory_range(start, AUDIO_PATTERN_SIZE)?;
+        for offset in 0..AUDIO_PATTERN_SIZE {
+            self.audio_pattern[offset] = self.read_memory(start + offset);
+        }
+        Ok(())
+    }
+
+    // Fx3A - PITCH Vx
+    // Set the audio pattern playback rate to Vx.
+    fn instruction_pitch(&mut self, x: u8) {
+        self.pitch = self.gpr[x as usize];
+    }
+}
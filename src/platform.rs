@@ -0,0 +1,35 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::quirks::Quirks;
+
+// The CHIP-8 dialect being emulated. Each platform is a superset of the ones
+// before it, so opcodes check against the oldest platform that supports them.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    // The platform numbered as in save states and replays
+    pub(crate) fn from_index(index: u8) -> Option<Platform> {
+        match index {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::SuperChip),
+            2 => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    // The quirks a ROM written for this platform is most likely to expect
+    pub fn default_quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::superchip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// Interpreters disagree on how a handful of opcodes behave. Each flag picks
+// one of the two interpretations, the default being the behaviour this
+// emulator has always had.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    // 8xy6/8xyE shift Vy into Vx rather than shifting Vx in place
+    pub shift_uses_vy: bool,
+    // Fx55/Fx65 leave I pointing one past the last register stored/loaded
+    pub load_store_increments_i: bool,
+    // Bxnn jumps to xnn + Vx instead of nnn + V0
+    pub jump_uses_vx: bool,
+    // 8xy1/8xy2/8xy3 reset VF to 0
+    pub logic_resets_vf: bool,
+    // Sprites are clipped at the edge of the screen rather than wrapping
+    pub clip_sprites: bool,
+    // Dxyn waits for the next vertical blank before drawing
+    pub display_wait: bool,
+    // Fx0A ignores keys already held when it starts, and finishes when the
+    // key pressed is released rather than as soon as it's pressed
+    pub wait_for_key_release: bool,
+}
+
+// The interpreters with a well known set of quirks
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuirksPreset {
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Quirks {
+    pub fn new() -> Quirks {
+        Quirks::default()
+    }
+
+    pub fn preset(preset: QuirksPreset) -> Quirks {
+        match preset {
+            QuirksPreset::CosmacVip => Quirks::cosmac_vip(),
+            QuirksPreset::Chip48 => Quirks::chip48(),
+            QuirksPreset::SuperChip => Quirks::superchip(),
+            QuirksPreset::XoChip => Quirks::xochip(),
+        }
+    }
+
+    // The original CHIP-8 interpreter on the RCA COSMAC VIP
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            clip_sprites: true,
+            display_wait: true,
+            wait_for_key_release: true,
+        }
+    }
+
+    // CHIP-48 on the HP-48 calculators
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            display_wait: false,
+            wait_for_key_release: false,
+        }
+    }
+
+    // SUPER-CHIP 1.1, which kept the CHIP-48 behaviour
+    pub fn superchip() -> Quirks {
+        Quirks::chip48()
+    }
+
+    // XO-CHIP as implemented by Octo
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+            wait_for_key_release: true,
+        }
+    }
+}
+
+impl Quirks {
+    // Pack the flags into a bit field, one bit per flag in declaration order,
+    // for save states and replay files
+    pub fn to_bits(&self) -> u32 {
+        [
+            self.shift_uses_vy,
+            self.load_store_increments_i,
+            self.jump_uses_vx,
+            self.logic_resets_vf,
+            self.clip_sprites,
+            self.display_wait,
+            self.wait_for_key_release,
+        ].iter()
+            .enumerate()
+            .fold(0, |bits, (index, &flag)| bits | (flag as u32) << index)
+    }
+
+    // Unpack flags from to_bits, ignoring any unknown bits
+    pub fn from_bits(bits: u32) -> Quirks {
+        let flag = |index: u32| bits & 1 << index != 0;
+        Quirks {
+            shift_uses_vy: flag(0),
+            load_store_increments_i: flag(1),
+            jump_uses_vx: flag(2),
+            logic_resets_vf: flag(3),
+            clip_sprites: flag(4),
+            display_wait: flag(5),
+            wait_for_key_release: flag(6),
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use std::fmt;
+use std::io::Cursor;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::rng::DEFAULT_SEED;
+use crate::state::crc32;
+use crate::CPU;
+
+// Replays start with the magic and version, and end with a CRC-32 of
+// everything before it. All numbers are big endian. Version 1 then holds:
+//
+//     rom hash        u32, CRC-32 of the ROM
+//     platform        u8 (0 CHIP-8, 1 SUPER-CHIP, 2 XO-CHIP)
+//     quirks          u32, see Quirks::to_bits
+//     rng seed        u64
+//     length          u64, instructions recorded
+//     event count     u32
+//     events          event count of, in order of cycle
+//         cycle       u64, instructions executed before the event
+//         kind        u8 (0 key down, 1 key up, 2 timers)
+//         value       u8, the key, or how much the timers went down by
+//
+// Recording starts from a freshly loaded ROM, so the ROM, quirks and seed are
+// all it takes to get back to the same starting point. Nothing else can change
+// the CPU's state along the way, so save states can't be loaded while
+// recording or playing.
+const MAGIC: &[u8; 4] = b"C8RP";
+const VERSION: u16 = 1;
+
+// Why a replay couldn't be read or played
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    // Doesn't start with the replay magic
+    NotAReplay,
+    // Saved in a format version this emulator doesn't know
+    UnsupportedVersion,
+    // The checksum doesn't match, so the replay is corrupt
+    BadChecksum,
+    // Ends before all the replay has been read
+    Truncated,
+    // Holds a value that can't be replayed
+    InvalidValue,
+    // Recorded with a different ROM
+    WrongRom,
+    // Recorded on a different platform to the CPU playing it
+    WrongPlatform,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ReplayError::NotAReplay => "not a replay",
+            ReplayError::UnsupportedVersion => "unsupported replay version",
+            ReplayError::BadChecksum => "replay checksum mismatch",
+            ReplayError::Truncated => "replay is truncated",
+            ReplayError::InvalidValue => "replay holds an invalid value",
+            ReplayError::WrongRom => "replay was recorded with another ROM",
+            ReplayError::WrongPlatform =>
+                "replay was recorded on another platform",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    // Reading only fails by running out of bytes
+    fn from(_: std::io::Error) -> ReplayError {
+        ReplayError::Truncated
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputKind {
+    KeyDown(u8),
+    KeyUp(u8),
+    // The timers went down by this much
+    Timers(u8),
+}
+
+// An input, stamped with the number of instructions executed before it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub cycle: u64,
+    pub kind: InputKind,
+}
+
+// The inputs from a run of a ROM, along with what it takes to start the run
+// the same way
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    rom_hash: u32,
+    platform: Platform,
+    quirks: Quirks,
+    seed: u64,
+    length: u64,
+    events: Vec<InputEvent>,
+}
+
+impl Replay {
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Replay {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        if bytes.len() < MAGIC.len() + 2 + 4 {
+            return Err(ReplayError::Truncated);
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 4);
+        let mut reader = Cursor::new(body);
+        reader.set_position(MAGIC.len() as u64);
+        if reader.read_u16::<BigEndian>()? != VERSION {
+            return Err(ReplayError::UnsupportedVersion);
+        }
+        if crc32(body) != Cursor::new(checksum).read_u32::<BigEndian>()? {
+            return Err(ReplayError::BadChecksum);
+        }
+
+        let rom_hash = reader.read_u32::<BigEndian>()?;
+        let platform = Platform::from_index(reader.read_u8()?)
+            .ok_or(ReplayError::InvalidValue)?;
+        let quirks = Quirks::from_bits(reader.read_u32::<BigEndian>()?);
+        let seed = reader.read_u64::<BigEndian>()?;
+        let length = reader.read_u64::<BigEndian>()?;
+        let count = reader.read_u32::<BigEndian>()?;
+        let mut events = Vec::new();
+        for _ in 0..count {
+            let cycle = reader.read_u64::<BigEndian>()?;
+            let kind = match (reader.read_u8()?, reader.read_u8()?) {
+                (0, key) if key < 16 => InputKind::KeyDown(key),
+                (1, key) if key < 16 => InputKind::KeyUp(key),
+                (2, decrement) => InputKind::Timers(decrement),
+                _ => return Err(ReplayError::InvalidValue),
+            };
+            let out_of_order = events.last()
+                .is_some_and(|last: &InputEvent| last.cycle > cycle);
+            if out_of_order || cycle > length {
+                return Err(ReplayError::InvalidValue);
+            }
+            events.push(InputEvent { cycle, kind });
+        }
+        if reader.position() != body.len() as u64 {
+            return Err(ReplayError::InvalidValue);
+        }
+        Ok(Replay { rom_hash, platform, quirks, seed, length, events })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(40 + self.events.len() * 10);
+        // Writing to a Vec can't fail
+        let mut write = || -> std::io::Result<()> {
+            bytes.extend_from_slice(MAGIC);
+            bytes.write_u16::<BigEndian>(VERSION)?;
+            bytes.write_u32::<BigEndian>(self.rom_hash)?;
+            bytes.write_u8(self.platform as u8)?;
+            bytes.write_u32::<BigEndian>(self.quirks.to_bits())?;
+            bytes.write_u64::<BigEndian>(self.seed)?;
+            bytes.write_u64::<BigEndian>(self.length)?;
+            bytes.write_u32::<BigEndian>(self.events.len() as u32)?;
+            for event in &self.events {
+                bytes.write_u64::<BigEndian>(event.cycle)?;
+                let (kind, value) = match event.kind {
+                    InputKind::KeyDown(key) => (0, key),
+                    InputKind::KeyUp(key) => (1, key),
+                    InputKind::Timers(decrement) => (2, decrement),
+                };
+                bytes.write_u8(kind)?;
+                bytes.write_u8(value)?;
+            }
+            let checksum = crc32(&bytes);
+            bytes.write_u32::<BigEndian>(checksum)
+        };
+        write().expect("writing to a Vec failed");
+        bytes
+    }
+
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Number of instructions recorded
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn event_count(&self) -> usize {
+        self.events.len()
+    }
+}
+
+// Whether inputs are being recorded or played back. Cycles count from start,
+// the instruction count when recording or playback began.
+#[derive(Default)]
+pub enum ReplayMode {
+    #[default]
+    Idle,
+    Recording { replay: Replay, start: u64 },
+    Playing { replay: Replay, start: u64, next: usize },
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    // Reset, load the ROM and start recording inputs. A random source that
+    // can't be saved is swapped for a seeded one, so the run can be replayed.
+    pub fn start_recording(&mut self, rom: &[u8]) {
+        self.boot(rom);
+        let seed = match self.rng.state() {
+            Some(state) => state,
+            None => {
+                self.set_rng_seed(DEFAULT_SEED);
+                DEFAULT_SEED
+            }
+        };
+        let replay = Replay {
+            rom_hash: crc32(rom),
+            platform: self.platform,
+            quirks: self.quirks,
+            seed,
+            length: 0,
+            events: Vec::new(),
+        };
+        let start = self.instructions;
+        self.replay = ReplayMode::Recording { replay, start };
+    }
+
+    // Stop recording, returning what was recorded if recording
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        match std::mem::take(&mut self.replay) {
+            ReplayMode::Recording { mut replay, start } => {
+                replay.length = self.instructions - start;
+                Some(replay)
+            }
+            mode => {
+                self.replay = mode;
+                None
+            }
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.replay, ReplayMode::Recording { .. })
+    }
+
+    // Reset, load the ROM and play the replay's inputs back as instructions
+    // execute. Live input and timer ticks are ignored until it finishes.
+    pub fn start_replay(&mut self, replay: &Replay, rom: &[u8])
+        -> Result<(), ReplayError> {
+        if replay.platform != self.platform {
+            return Err(ReplayError::WrongPlatform);
+        }
+        if replay.rom_hash != crc32(rom) {
+            return Err(ReplayError::WrongRom);
+        }
+        self.boot(rom);
+        self.quirks = replay.quirks;
+        self.set_rng_seed(replay.seed);
+        self.replay = ReplayMode::Playing {
+            replay: replay.clone(),
+            start: self.instructions,
+            next: 0,
+        };
+        self.play_inputs();
+        Ok(())
+    }
+
+    pub fn stop_replay(&mut self) {
+        if self.is_replaying() {
+            self.replay = ReplayMode::Idle;
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self.replay, ReplayMode::Playing { .. })
+    }
+
+    // Number of instructions executed since the CPU was created, reset or
+    // loaded from a save state
+    pub fn get_instruction_count(&self) -> u64 {
+        self.instructions
+    }
+}
+
+impl CPU {
+    // Start from scratch with just the ROM and fonts loaded
+    fn boot(&mut self, rom: &[u8]) {
+        self.reset();
+        self.init_hex_sprites();
+        self.load_program_memory(rom.to_vec());
+    }
+
+    // Called for each input, returning false if live input should be
+    // ignored because a replay is playing
+    pub(crate) fn record_input(&mut self, kind: InputKind) -> bool {
+        match &mut self.replay {
+            ReplayMode::Idle => true,
+            ReplayMode::Recording { replay, start } => {
+                let cycle = self.instructions - *start;
+                replay.events.push(InputEvent { cycle, kind });
+                true
+            }
+            ReplayMode::Playing { .. } => false,
+        }
+    }
+
+    // Called when stepping back, once instructions has been rewound. Inputs
+    // recorded for the instructions undone are forgotten, and ones played
+    // for them play again, from the keyboard as it was before them.
+    pub(crate) fn rewind_inputs(&mut self, keyboard: u16) {
+        match &mut self.replay {
+            ReplayMode::Idle => {}
+            ReplayMode::Recording { replay, start } => {
+                let cycle = self.instructions - *start;
+                replay.events.retain(|event| event.cycle <= cycle);
+            }
+            ReplayMode::Playing { replay, start, next } => {
+                let cycle = self.instructions - *start;
+                *next = replay.events
+                    .partition_point(|event| event.cycle <= cycle);
+                self.keyboard = keyboard;
+            }
+        }
+    }
+
+    // Called by try_tick after each instruction to apply the inputs that came
+    // next, finishing after the last instruction recorded
+    pub(crate) fn play_inputs(&mut self) {
+        let (kinds, finished) = match &mut self.replay {
+            ReplayMode::Playing { replay, start, next } => {
+                let cycle = self.instructions - *start;
+                let due = replay.events[*next..].iter()
+                    .take_while(|event| event.cycle <= cycle)
+                    .map(|event| event.kind)
+                    .collect::<Vec<_>>();
+                *next += due.len();
+                (due, cycle >= replay.length)
+            }
+            _ => return,
+        };
+        for kind in kinds {
+            match kind {
+                InputKind::KeyDown(key) => self.press_key(key),
+                InputKind::KeyUp(key) => self.release_key(key),
+                InputKind::Timers(decrement) =>
+                    self.decrement_timers(decrement as u64),
+            }
+        }
+        if finished {
+            self.replay = ReplayMode::Idle;
+        }
+    }
+}
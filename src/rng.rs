@@ -0,0 +1,84 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::CPU;
+
+// The seed a CPU starts with, so runs are reproducible unless a different
+// seed or source is set
+pub const DEFAULT_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// Where RND gets its random bytes from
+pub trait Rng {
+    fn next_byte(&mut self) -> u8;
+
+    // The generator's state if it can be saved, such that XorShift::new(state)
+    // carries on with the same sequence. Never 0.
+    fn state(&self) -> Option<u64> {
+        None
+    }
+}
+
+// xorshift64*, small and fast with good enough output for games
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    // The state can't be 0, so a 0 seed is swapped for the default
+    pub fn new(seed: u64) -> XorShift {
+        XorShift { state: if seed == 0 { DEFAULT_SEED } else { seed } }
+    }
+}
+
+impl Default for XorShift {
+    fn default() -> XorShift {
+        XorShift::new(DEFAULT_SEED)
+    }
+}
+
+impl Rng for XorShift {
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        // The top bits are the most random
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.state)
+    }
+}
+
+// Math.random, for when reproducibility doesn't matter
+#[cfg(feature = "wasm")]
+struct JsRng;
+
+#[cfg(feature = "wasm")]
+impl Rng for JsRng {
+    fn next_byte(&mut self) -> u8 {
+        (js_sys::Math::random() * 256f64).floor() as u8
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    // Switch RND to the built in generator, starting from seed
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.set_rng(XorShift::new(seed));
+    }
+
+    // Switch RND to Math.random. Runs using it can't be replayed, and save
+    // states don't capture it.
+    #[cfg(feature = "wasm")]
+    pub fn use_js_rng(&mut self) {
+        self.set_rng(JsRng);
+    }
+}
+
+impl CPU {
+    pub fn set_rng<R: Rng + 'static>(&mut self, rng: R) {
+        self.rng = Box::new(rng);
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt;
+use std::io::{Cursor, Read};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::rng::XorShift;
+use crate::timing::Timing;
+use crate::wait::KeyWait;
+use crate::display::PLANE_SIZE;
+use crate::{AUDIO_PATTERN_SIZE, CPU, RPL_FLAGS, STACK_SIZE};
+
+// Save states start with the magic and version, and end with a CRC-32 of
+// everything before it. All numbers are big endian. Version 1 then holds:
+//
+//     platform        u8 (0 CHIP-8, 1 SUPER-CHIP, 2 XO-CHIP)
+//     quirks          u32, see Quirks::to_bits
+//     memory length   u32
+//     memory          memory length bytes
+//     gpr             16 bytes
+//     stack           16 u16s
+//     i, pc           u16 each
+//     sp, dt, st      u8 each
+//     keyboard        u16
+//     key wait x      u8, the register Fx0A is waiting to fill, or 0xFF
+//     ignored keys    u16, held when Fx0A started
+//     key pressed     u8, since Fx0A started, or 0xFF
+//     vblank wait     u8, 1 if Dxyn is waiting for the next frame
+//     last tick time  u64
+//     rng state       u64, 0 if the random source can't be saved
+//     ipf             u32, instructions per frame
+//     frame cycles    u32, cycles run so far in the current frame
+//     timing          u8 (0 instructions, 1 COSMAC VIP)
+//     cycles elapsed  u64
+//     hires           u8, 0 or 1
+//     rpl             16 bytes
+//     selected planes u8
+//     pitch           u8
+//     audio pattern   16 bytes
+//     plane 0, plane 1 128 * 64 / 8 bytes each, a bit a pixel as in
+//                     get_plane_pointer
+const MAGIC: &[u8; 4] = b"C8ST";
+// Once a version has been released, later ones should still load it, giving
+// the fields it lacks their defaults
+const VERSION: u16 = 1;
+// Stands for no register or key
+const NONE: u8 = 0xFF;
+
+// Why a save state couldn't be loaded. The CPU is left untouched when one of
+// these is returned.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    // Doesn't start with the save state magic
+    NotAState,
+    // Saved in a format version this emulator doesn't know
+    UnsupportedVersion,
+    // The checksum doesn't match, so the state is corrupt
+    BadChecksum,
+    // Ends before all the state has been read
+    Truncated,
+    // Holds a value the CPU can't be in
+    InvalidValue,
+    // A replay is recording or playing, and can't jump to another state
+    Replaying,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            StateError::NotAState => "not a save state",
+            StateError::UnsupportedVersion => "unsupported save state version",
+            StateError::BadChecksum => "save state checksum mismatch",
+            StateError::Truncated => "save state is truncated",
+            StateError::InvalidValue => "save state holds an invalid value",
+            StateError::Replaying =>
+                "can't load a save state while a replay records or plays",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<std::io::Error> for StateError {
+    // Reading only fails by running out of bytes
+    fn from(_: std::io::Error) -> StateError {
+        StateError::Truncated
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    pub fn save_state(&self) -> Vec<u8> {
+        let memory = self.get_memory();
+        let mut state = Vec::with_capacity(memory.len() + PLANE_SIZE * 2 + 128);
+        // Writing to a Vec can't fail
+        let mut write = || -> std::io::Result<()> {
+            state.extend_from_slice(MAGIC);
+            state.write_u16::<BigEndian>(VERSION)?;
+            state.write_u8(self.platform as u8)?;
+            state.write_u32::<BigEndian>(self.quirks.to_bits())?;
+            state.write_u32::<BigEndian>(memory.len() as u32)?;
+            state.extend_from_slice(memory);
+            state.extend_from_slice(&self.gpr);
+            for &address in self.stack.iter() {
+                state.write_u16::<BigEndian>(address)?;
+            }
+            state.write_u16::<BigEndian>(self.i)?;
+            state.write_u16::<BigEndian>(self.pc)?;
+            state.write_u8(self.sp)?;
+            state.write_u8(self.dt)?;
+            state.write_u8(self.st)?;
+            state.write_u16::<BigEndian>(self.keyboard)?;
+            let wait = self.key_wait;
+            state.write_u8(wait.map_or(NONE, |wait| wait.x))?;
+            state.write_u16::<BigEndian>(wait.map_or(0, |wait| wait.ignored))?;
+            state.write_u8(wait.and_then(|wait| wait.key).unwrap_or(NONE))?;
+            state.write_u8(self.vblank_wait as u8)?;
+            state.write_u64::<BigEndian>(self.last_tick_time)?;
+            state.write_u64::<BigEndian>(self.rng.state().unwrap_or(0))?;
+            state.write_u32::<BigEndian>(self.instructions_per_frame)?;
+            state.write_u32::<BigEndian>(self.frame_cycles)?;
+            state.write_u8(self.timing as u8)?;
+            state.write_u64::<BigEndian>(self.cycles_elapsed)?;
+            state.write_u8(self.hires as u8)?;
+            state.extend_from_slice(&self.rpl);
+            state.write_u8(self.selected_planes)?;
+            state.write_u8(self.pitch)?;
+            state.extend_from_slice(&self.audio_pattern);
+            state.extend_from_slice(self.framebuffer.plane(0));
+            state.extend_from_slice(self.framebuffer.plane(1));
+            let checksum = crc32(&state);
+            state.write_u32::<BigEndian>(checksum)
+        };
+        write().expect("writing to a Vec failed");
+        state
+    }
+
+    // Restore a state from save_state. Breakpoints, audio, the palette and
+    // other frontend settings are kept, but the rewind history is cleared.
+    // Replays only hold inputs, so a state can't be loaded while one is
+    // recording or playing; stop it first.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        if self.is_recording() || self.is_replaying() {
+            return Err(StateError::Replaying);
+        }
+        if state.len() < MAGIC.len() || &state[..MAGIC.len()] != MAGIC {
+            return Err(StateError::NotAState);
+        }
+        if state.len() < MAGIC.len() + 2 + 4 {
+            return Err(StateError::Truncated);
+        }
+        let (body, checksum) = state.split_at(state.len() - 4);
+        let mut reader = Cursor::new(body);
+        reader.set_position(MAGIC.len() as u64);
+        if reader.read_u16::<BigEndian>()? != VERSION {
+            return Err(StateError::UnsupportedVersion);
+        }
+        if crc32(body) != Cursor::new(checksum).read_u32::<BigEndian>()? {
+            return Err(StateError::BadChecksum);
+        }
+
+        // Read everything into a fresh CPU first, so a bad state doesn't
+        // leave this one half loaded
+        let platform = Platform::from_index(reader.read_u8()?)
+            .ok_or(StateError::InvalidValue)?;
+        let quirks = Quirks::from_bits(reader.read_u32::<BigEndian>()?);
+        let mut loaded = CPU::new_with_platform_and_quirks(platform, quirks);
+        let memory_length = reader.read_u32::<BigEndian>()? as usize;
+        if memory_length != loaded.get_memory_size() {
+            return Err(StateError::InvalidValue);
+        }
+        reader.read_exact(&mut loaded.memory[..memory_length])?;
+        reader.read_exact(&mut loaded.gpr)?;
+        for address in loaded.stack.iter_mut() {
+            *address = reader.read_u16::<BigEndian>()?;
+        }
+        loaded.i = reader.read_u16::<BigEndian>()?;
+        loaded.pc = reader.read_u16::<BigEndian>()?;
+        loaded.sp = reader.read_u8()?;
+        if loaded.sp as usize > STACK_SIZE {
+            return Err(StateError::InvalidValue);
+        }
+        loaded.dt = reader.read_u8()?;
+        loaded.st = reader.read_u8()?;
+        loaded.keyboard = reader.read_u16::<BigEndian>()?;
+        let x = reader.read_u8()?;
+        let ignored = reader.read_u16::<BigEndian>()?;
+        let key = match reader.read_u8()? {
+            NONE => None,
+            key if key < 16 => Some(key),
+            _ => return Err(StateError::InvalidValue),
+        };
+        loaded.key_wait = match x {
+            NONE => None,
+            x if x < 16 => Some(KeyWait { x, ignored, key }),
+            _ => return Err(StateError::InvalidValue),
+        };
+        loaded.vblank_wait = match reader.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(StateError::InvalidValue),
+        };
+        loaded.last_tick_time = reader.read_u64::<BigEndian>()?;
+        let rng_state = reader.read_u64::<BigEndian>()?;
+        loaded.instructions_per_frame = reader.read_u32::<BigEndian>()?;
+        if loaded.instructions_per_frame == 0 {
+            return Err(StateError::InvalidValue);
+        }
+        loaded.frame_cycles = reader.read_u32::<BigEndian>()?;
+        loaded.timing = Timing::from_index(reader.read_u8()?)
+            .ok_or(StateError::InvalidValue)?;
+        loaded.cycles_elapsed = reader.read_u64::<BigEndian>()?;
+        loaded.hires = match reader.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(StateError::InvalidValue),
+        };
+        reader.read_exact(&mut loaded.rpl[..RPL_FLAGS])?;
+        loaded.selected_planes = reader.read_u8()?;
+        loaded.pitch = reader.read_u8()?;
+        reader.read_exact(&mut loaded.audio_pattern[..AUDIO_PATTERN_SIZE])?;
+        reader.read_exact(loaded.framebuffer.plane_mut(0))?;
+        reader.read_exact(loaded.framebuffer.plane_mut(1))?;
+        if reader.position() != body.len() as u64 {
+            return Err(StateError::InvalidValue);
+        }
+
+        loaded.debugger = std::mem::take(&mut self.debugger);
+        loaded.history = std::mem::take(&mut self.history);
+        loaded.history.clear();
+        loaded.tracer = std::mem::take(&mut self.tracer);
+        loaded.audio = std::mem::take(&mut self.audio);
+        loaded.renderer = std::mem::take(&mut self.renderer);
+        // A source that couldn't be saved is kept as it is
+        loaded.rng = if rng_state == 0 {
+            std::mem::replace(&mut self.rng, Box::new(XorShift::default()))
+        } else {
+            Box::new(XorShift::new(rng_state))
+        };
+        *self = loaded;
+        Ok(())
+    }
+}
+
+// CRC-32 as used by zip and PNG
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
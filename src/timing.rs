@@ -0,0 +1,159 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::instruction::Instruction;
+use crate::CPU;
+
+// The VIP's 1802 runs at 1.7609 MHz, with 8 clock cycles to a machine cycle,
+// so a 60 Hz frame is this many machine cycles
+const VIP_CYCLES_PER_FRAME: u32 = 3668;
+// The display interrupt, and the 1861 video chip taking the bus to read the
+// display for each of its 128 lines, use this many of every frame
+const VIP_INTERRUPT_CYCLES: u32 = 1832;
+// The interpreter's loop fetching an instruction and jumping to its routine
+const VIP_FETCH_CYCLES: u32 = 40;
+// Extra cycles skip instructions take when they skip
+const VIP_SKIP_CYCLES: u32 = 4;
+
+// How long instructions take, which decides how many run in a frame
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timing {
+    // Every instruction takes one cycle, and a frame is
+    // instructions_per_frame cycles
+    #[default]
+    Instructions,
+    // Instructions take as many machine cycles as the COSMAC VIP interpreter
+    // spends on them, and frames are as long as on the VIP, less the time
+    // its display interrupt takes
+    CosmacVip,
+}
+
+impl Timing {
+    // The timing numbered as in save states
+    pub(crate) fn from_index(index: u8) -> Option<Timing> {
+        match index {
+            0 => Some(Timing::Instructions),
+            1 => Some(Timing::CosmacVip),
+            _ => None,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    pub fn get_timing(&self) -> Timing {
+        self.timing
+    }
+
+    // Frames are counted differently by each timing, so this starts a new one
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.frame_cycles = 0;
+    }
+
+    // Cycles run since the last reset, by the timing in use when they ran.
+    // With the VIP timing this includes its display interrupts, and time
+    // spent waiting for a key or vblank within run_cycles.
+    pub fn cycles_elapsed(&self) -> u64 {
+        self.cycles_elapsed
+    }
+}
+
+impl CPU {
+    // Cycles of instructions a frame has room for
+    pub(crate) fn frame_length(&self) -> u32 {
+        match self.timing {
+            Timing::Instructions => self.instructions_per_frame,
+            Timing::CosmacVip => VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES,
+        }
+    }
+
+    // Cycles between the frames that don't go to instructions
+    pub(crate) fn interrupt_cycles(&self) -> u32 {
+        match self.timing {
+            Timing::Instructions => 0,
+            Timing::CosmacVip => VIP_INTERRUPT_CYCLES,
+        }
+    }
+
+    // Called by execute_next once the instruction fetched from pc has run,
+    // with the registers from before it
+    pub(crate) fn count_instruction(&mut self, instruction: Instruction,
+        pc: u16, gpr: &[u8; 16]) {
+        let cycles = match self.timing {
+            Timing::Instructions => 1,
+            Timing::CosmacVip => {
+                VIP_FETCH_CYCLES + self.vip_cycles(instruction, pc, gpr)
+            }
+        };
+        self.cycles_elapsed += cycles as u64;
+    }
+
+    // Called by execute_next for a tick spent waiting for a key or vblank.
+    // On the VIP, run_cycles counts the time waited instead.
+    pub(crate) fn count_wait(&mut self) {
+        if self.timing == Timing::Instructions {
+            self.cycles_elapsed += 1;
+        }
+    }
+
+    // Machine cycles the VIP interpreter's routine for an instruction takes
+    fn vip_cycles(&self, instruction: Instruction, pc: u16, gpr: &[u8; 16])
+        -> u32 {
+        let skip = if self.pc != pc.wrapping_add(2) {
+            VIP_SKIP_CYCLES
+        } else {
+            0
+        };
+        match instruction {
+            // Clearing each of the 256 bytes of the display
+            Instruction::Cls => 24 + 256 * 12,
+            Instruction::Ret => 10,
+            Instruction::Jp(_) => 12,
+            Instruction::Call(_) => 26,
+            Instruction::SeByte { .. } | Instruction::SneByte { .. } =>
+                10 + skip,
+            Instruction::SeReg { .. } | Instruction::SneReg { .. } => 14 + skip,
+            Instruction::LdByte { .. } => 6,
+            Instruction::AddByte { .. } => 10,
+            Instruction::LdReg { .. } => 12,
+            Instruction::Or { .. }
+            | Instruction::And { .. }
+            | Instruction::Xor { .. }
+            | Instruction::AddReg { .. }
+            | Instruction::Sub { .. }
+            | Instruction::Shr { .. }
+            | Instruction::Subn { .. }
+            | Instruction::Shl { .. } => 44,
+            Instruction::LdI(_) => 12,
+            Instruction::JpV0(_) => 22,
+            Instruction::Rnd { .. } => 36,
+            // Each row is shifted into place a bit at a time, then XORed into
+            // the two bytes it covers, checking them for collisions
+            Instruction::Drw { x, n, .. } => {
+                let shift = (gpr[x as usize] % 8) as u32;
+                let collision = if self.gpr[0xF] != 0 { 4 } else { 0 };
+                26 + n as u32 * (46 + 8 * shift) + collision
+            }
+            Instruction::Skp(_) | Instruction::Sknp(_) => 14 + skip,
+            Instruction::LdVxDt(_)
+            | Instruction::LdVxK(_)
+            | Instruction::LdDtVx(_)
+            | Instruction::LdStVx(_) => 10,
+            Instruction::AddIVx(_) => 12,
+            Instruction::LdF(_) => 16,
+            // Each digit is found by subtracting powers of ten
+            Instruction::LdB(x) => {
+                let value = gpr[x as usize] as u32;
+                let digits = value / 100 + value / 10 % 10 + value % 10;
+                80 + 16 * digits
+            }
+            Instruction::LdIVx(x) | Instruction::LdVxI(x) =>
+                14 + 14 * (x as u32 + 1),
+            // The VIP didn't have the rest, so they cost about as much as the
+            // simplest instructions it did
+            _ => 10,
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::disassembler::{disassemble_one, DisassembledInstruction, Syntax};
+use crate::CPU;
+
+// A register that an instruction changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub register: u8,
+    pub old_value: u8,
+    pub new_value: u8,
+}
+
+// A single executed instruction, along with the state it left behind
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    cycle: u64,
+    pc: u16,
+    opcode: u16,
+    text: String,
+    changes: Vec<RegisterChange>,
+    i: u16,
+    dt: u8,
+    st: u8,
+}
+
+impl TraceEntry {
+    pub fn changes(&self) -> &[RegisterChange] {
+        &self.changes
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl TraceEntry {
+    // Number of instructions executed since tracing started, counting ones
+    // that were filtered out
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    // The instruction in Cowgod syntax
+    pub fn text(&self) -> String {
+        self.text.clone()
+    }
+
+    // The changed registers flattened into register, old value, new value
+    // triples
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = changes))]
+    pub fn changes_js(&self) -> Vec<u8> {
+        self.changes.iter()
+            .flat_map(|change|
+                vec![change.register, change.old_value, change.new_value])
+            .collect()
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn st(&self) -> u8 {
+        self.st
+    }
+}
+
+// One line per entry, like
+// `     42 0204: 7A01 ADD VA, 0x01 | VA 2F->30 | I=0300 DT=00 ST=00`
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:7} {:04X}: {:04X} {} |", self.cycle, self.pc,
+            self.opcode, self.text)?;
+        for change in &self.changes {
+            write!(f, " V{:X} {:02X}->{:02X}", change.register,
+                change.old_value, change.new_value)?;
+        }
+        write!(f, " | I={:04X} DT={:02X} ST={:02X}", self.i, self.dt, self.st)
+    }
+}
+
+enum TraceSink {
+    // Keeps the most recent entries up to a capacity
+    Buffer(VecDeque<TraceEntry>, usize),
+    Callback(Box<dyn FnMut(&TraceEntry)>),
+}
+
+// Records executed instructions when a sink is set. Only instructions with a
+// PC inside the range and an opcode class in the mask are recorded.
+pub struct Tracer {
+    sink: Option<TraceSink>,
+    cycle: u64,
+    pc_range: (u16, u16),
+    // Bit n allows opcodes whose top nibble is n
+    opcode_classes: u16,
+}
+
+impl Default for Tracer {
+    fn default() -> Tracer {
+        Tracer {
+            sink: None,
+            cycle: 0,
+            pc_range: (0, 0xFFFF),
+            opcode_classes: 0xFFFF,
+        }
+    }
+}
+
+impl Tracer {
+    fn set_sink(&mut self, sink: TraceSink) {
+        self.sink = Some(sink);
+        self.cycle = 0;
+    }
+
+    fn accepts(&self, pc: u16, opcode: u16) -> bool {
+        let (start, end) = self.pc_range;
+        start <= pc && pc <= end
+            && self.opcode_classes & 1 << (opcode >> 12) != 0
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    // Trace into a ring buffer holding the last capacity instructions
+    pub fn enable_trace_buffer(&mut self, capacity: usize) {
+        self.tracer.set_sink(TraceSink::Buffer(VecDeque::new(), capacity));
+    }
+
+    // Stream each entry to a JS function as it's recorded
+    #[cfg(feature = "wasm")]
+    pub fn set_trace_callback(&mut self, callback: js_sys::Function) {
+        self.trace_with(move |entry| {
+            let _ = callback.call1(&JsValue::NULL, &entry.clone().into());
+        });
+    }
+
+    pub fn disable_trace(&mut self) {
+        self.tracer.sink = None;
+    }
+
+    // Only trace instructions from start to end inclusive
+    pub fn set_trace_pc_range(&mut self, start: u16, end: u16) {
+        self.tracer.pc_range = (start, end);
+    }
+
+    pub fn clear_trace_pc_range(&mut self) {
+        self.tracer.pc_range = (0, 0xFFFF);
+    }
+
+    // Only trace opcodes whose top nibble n has bit n set in the mask, e.g.
+    // 0x2002 for just CALL and RET
+    pub fn set_trace_opcode_classes(&mut self, mask: u16) {
+        self.tracer.opcode_classes = mask;
+    }
+
+    // Empty the trace buffer, oldest entry first
+    pub fn take_trace(&mut self) -> Vec<TraceEntry> {
+        match self.tracer.sink.as_mut() {
+            Some(TraceSink::Buffer(entries, _)) => entries.drain(..).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl CPU {
+    // Trace by calling a function with each entry
+    pub fn trace_with<F: FnMut(&TraceEntry) + 'static>(&mut self, callback: F) {
+        self.tracer.set_sink(TraceSink::Callback(Box::new(callback)));
+    }
+
+    // Called by try_tick before an instruction executes, so the trace shows
+    // the instruction even if it overwrites itself
+    pub(crate) fn trace_fetch(&self) -> Option<DisassembledInstruction> {
+        self.tracer.sink.as_ref()?;
+        // While Fx0A or Dxyn waits, ticks go to it rather than the instruction
+        // at PC
+        let pc = if self.key_wait.is_some() || self.vblank_wait {
+            self.pc.wrapping_sub(2)
+        } else {
+            self.pc
+        };
+        disassemble_one(self.get_memory(), pc as usize)
+    }
+
+    // Called by try_tick after the instruction executes, with the registers
+    // from before it
+    pub(crate) fn trace(&mut self, instruction: DisassembledInstruction,
+        gpr: &[u8; 16]) {
+        let cycle = self.tracer.cycle;
+        self.tracer.cycle += 1;
+        let pc = instruction.address();
+        if !self.tracer.accepts(pc, instruction.opcode()) {
+            return;
+        }
+        let changes = gpr.iter().zip(self.gpr.iter()).enumerate()
+            .filter(|(_, (old_value, new_value))| old_value != new_value)
+            .map(|(register, (&old_value, &new_value))| RegisterChange {
+                register: register as u8,
+                old_value,
+                new_value,
+            })
+            .collect();
+        let entry = TraceEntry {
+            cycle,
+            pc,
+            opcode: instruction.opcode(),
+            text: instruction.text(Syntax::Cowgod),
+            changes,
+            i: self.i,
+            dt: self.dt,
+            st: self.st,
+        };
+        match self.tracer.sink.as_mut() {
+            Some(TraceSink::Buffer(entries, capacity)) => {
+                if *capacity == 0 {
+                    return;
+                }
+                if entries.len() == *capacity {
+                    entries.pop_front();
+                }
+                entries.push_back(entry);
+            }
+            Some(TraceSink::Callback(callback)) => callback(&entry),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,115 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::error::StepOutcome;
+use crate::CPU;
+
+// What the CPU is doing between instructions
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuState {
+    // Executing instructions
+    Running,
+    // Fx0A has started and ticks go to it until a key finishes it
+    WaitingForKey,
+    // Dxyn has drawn with the display_wait quirk, and ticks do nothing until
+    // the timers next decrement
+    WaitingForVblank,
+}
+
+// An Fx0A in progress. PC has already moved past it, and each tick checks
+// whether a key has finished it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyWait {
+    // The register the key goes in
+    pub x: u8,
+    // With the wait_for_key_release quirk, keys that were already held when
+    // Fx0A started, which only count once they've been released
+    pub ignored: u16,
+    // With the wait_for_key_release quirk, the key pressed since Fx0A
+    // started, which finishes it once released
+    pub key: Option<u8>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CPU {
+    pub fn get_state(&self) -> CpuState {
+        if self.key_wait.is_some() {
+            CpuState::WaitingForKey
+        } else if self.vblank_wait {
+            CpuState::WaitingForVblank
+        } else {
+            CpuState::Running
+        }
+    }
+}
+
+impl CPU {
+    // Fx0A - LD Vx, K
+    // Wait for a key press, store the value of the key in Vx.
+    //
+    // All execution stops until a key is pressed, then the value of that key
+    // is stored in Vx. On the COSMAC VIP the key has to be pressed after Fx0A
+    // starts, and Fx0A only finishes once it's released again, which the
+    // wait_for_key_release quirk picks.
+    pub(crate) fn instruction_ld_kp(&mut self, x: u8) -> StepOutcome {
+        let ignored = if self.quirks.wait_for_key_release {
+            self.keyboard
+        } else {
+            0
+        };
+        self.key_wait = Some(KeyWait { x, ignored, key: None });
+        self.wait_for_key()
+    }
+
+    // Called by execute_next instead of fetching while Fx0A waits
+    pub(crate) fn wait_for_key(&mut self) -> StepOutcome {
+        let wait = match self.key_wait {
+            Some(wait) => wait,
+            None => return StepOutcome::Executed,
+        };
+        let key = if self.quirks.wait_for_key_release {
+            wait.key.filter(|key| self.keyboard & 1 << key == 0)
+        } else {
+            (0..16).find(|key| self.keyboard & 1 << key != 0)
+        };
+        match key {
+            Some(key) => {
+                self.gpr[wait.x as usize] = key;
+                self.key_wait = None;
+                StepOutcome::Executed
+            }
+            None => StepOutcome::WaitingForKey,
+        }
+    }
+
+    // Called after Dxyn draws. On the COSMAC VIP sprites are drawn during the
+    // vertical blank, so with the display_wait quirk nothing else runs until
+    // the next frame starts, which is when the timers decrement.
+    pub(crate) fn wait_for_vblank(&mut self) -> StepOutcome {
+        if self.quirks.display_wait {
+            self.vblank_wait = true;
+            StepOutcome::WaitingForVblank
+        } else {
+            StepOutcome::Executed
+        }
+    }
+
+    // Every change to the keyboard goes through these two, so a key pressed
+    // and released between ticks still finishes Fx0A
+    pub(crate) fn press_key(&mut self, key: u8) {
+        self.keyboard |= 1 << key;
+        if let Some(wait) = &mut self.key_wait {
+            if wait.ignored & 1 << key == 0 && wait.key.is_none() {
+                wait.key = Some(key);
+            }
+        }
+    }
+
+    pub(crate) fn release_key(&mut self, key: u8) {
+        self.keyboard &= !(1 << key);
+        if let Some(wait) = &mut self.key_wait {
+            wait.ignored &= !(1 << key);
+        }
+    }
+}
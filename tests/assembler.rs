@@ -0,0 +1,145 @@
+//! Tests for the Cowgod-style assembler.
+
+extern crate chip_8_emu;
+use chip_8_emu::{assemble, decode, disassemble, Instruction, Syntax, CPU};
+
+#[test]
+fn hello_world() {
+    let rom = assemble("
+        LD F, V1    ; Load address for sprite \"1\" to I
+        DRW V0, V1, 5
+        LD F, V2
+        LD V0, 6
+        DRW V0, V1, 5
+        LD F, V3
+        LD V0, 12
+        DRW V0, V1, 5
+    ").unwrap();
+    assert_eq!(rom, [
+        0xF1, 0x29, 0xD0, 0x15, 0xF2, 0x29, 0x60, 0x06,
+        0xD0, 0x15, 0xF3, 0x29, 0x60, 0x0C, 0xD0, 0x15,
+    ]);
+}
+
+#[test]
+fn labels_and_constants() {
+    let rom = assemble("
+        SPEED EQU 2
+        LIMIT EQU SPEED + 0x3C
+    loop:
+        ld v0, SPEED
+        Sne V0, LIMIT
+        JP end
+        CALL sub
+        JP loop
+    sub: RET
+    end:
+        LD I, sprite + 1
+    sprite:
+        DB 0b10000001, #FF, $7E
+        DW end
+    ").unwrap();
+    assert_eq!(rom, [
+        0x60, 0x02, // 0x200 LD V0, SPEED
+        0x40, 0x3E, // 0x202 SNE V0, LIMIT
+        0x12, 0x0C, // 0x204 JP end
+        0x22, 0x0A, // 0x206 CALL sub
+        0x12, 0x00, // 0x208 JP loop
+        0x00, 0xEE, // 0x20A RET
+        0xA2, 0x0F, // 0x20C LD I, sprite + 1
+        0x81, 0xFF, 0x7E, // 0x20E sprite
+        0x02, 0x0C, // 0x211 DW end
+    ]);
+}
+
+#[test]
+fn long_load_and_ranges() {
+    let rom = assemble("
+        LD I, LONG data
+        LD [I], V2-V5
+        LD V5-V2, [I]
+        SHR V3
+    data: DB 1
+    ").unwrap();
+    assert_eq!(rom, [
+        0xF0, 0x00, 0x02, 0x0A,
+        0x52, 0x52, 0x55, 0x23, 0x83, 0x36, 0x01,
+    ]);
+}
+
+#[test]
+fn errors() {
+    let error = assemble("CLS\n  JP nowhere").unwrap_err();
+    assert_eq!((error.line(), error.column()), (2, 6));
+    assert_eq!(error.message(), "undefined symbol 'nowhere'");
+
+    let error = assemble("LD V0, 0x100").unwrap_err();
+    assert_eq!((error.line(), error.column()), (1, 8));
+    assert_eq!(error.message(), "value 256 doesn't fit in 8 bits");
+
+    let error = assemble("  FOO V0").unwrap_err();
+    assert_eq!((error.line(), error.column()), (1, 3));
+    assert_eq!(error.message(), "unknown instruction 'FOO'");
+
+    let error = assemble("DRW V0, V1").unwrap_err();
+    assert_eq!(error.message(), "invalid operands for DRW");
+
+    let error = assemble("a:\na: CLS").unwrap_err();
+    assert_eq!((error.line(), error.column()), (2, 1));
+    assert_eq!(error.message(), "'a' is already defined");
+
+    let error = assemble("LD V0, 12 @").unwrap_err();
+    assert_eq!((error.line(), error.column()), (1, 11));
+
+    let error = assemble("A EQU B2\nB2 EQU A\nLD V0, A").unwrap_err();
+    assert_eq!(error.message(), "constant refers to itself");
+
+    let error = assemble("DB 0x7FFFFFFFFFFFFFFF + 1").unwrap_err();
+    assert_eq!((error.line(), error.column()), (1, 25));
+    assert_eq!(error.message(), "value is too big");
+
+    // Errors in a constant are where it's defined, not where it's used
+    let error = assemble("CLS\nCLS\nCLS\nA EQU missing\nLD V0, A")
+        .unwrap_err();
+    assert_eq!((error.line(), error.column()), (4, 7));
+    assert_eq!(error.message(), "undefined symbol 'missing'");
+
+    assert_eq!(assemble("VF: CLS").unwrap_err().message(),
+        "'VF' is a reserved name");
+    assert_eq!(assemble("LD V0, 0x1G").unwrap_err().message(),
+        "invalid number '0x1G'");
+}
+
+#[test]
+fn loads_and_runs() {
+    let rom = assemble("
+        LD V0, 5
+        ADD V0, V0
+        LD I, 0x300
+        LD B, V0
+    ").unwrap();
+    let mut cpu = CPU::new();
+    cpu.load_program_memory(rom);
+    for _ in 0..4 {
+        cpu.try_tick().unwrap();
+    }
+    assert_eq!(cpu.get_memory()[0x300..0x303], [0, 1, 0]);
+}
+
+#[test]
+fn disassembler_round_trip() {
+    for opcode in 0..=0xFFFFu16 {
+        if decode(opcode) == Instruction::LdILong {
+            continue;
+        }
+        let bytes = opcode.to_be_bytes();
+        let text = disassemble(&bytes, 0, 2)[0].text(Syntax::Cowgod);
+        assert_eq!(assemble(&text), Ok(bytes.to_vec()), "{}", text);
+    }
+    let program = [0xF0, 0x00, 0xBE, 0xEF, 0x00, 0xE0];
+    let source: Vec<String> = disassemble(&program, 0, program.len())
+        .iter()
+        .map(|instruction| instruction.text(Syntax::Cowgod))
+        .collect();
+    assert_eq!(assemble(&source.join("\n")), Ok(program.to_vec()));
+}
@@ -0,0 +1,105 @@
+//! Tests for generating sound while the sound timer runs.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, Platform};
+
+// Start the sound timer, then wait
+const BEEP: [u8; 6] = [
+    0x60, 0x05, // 0x200 LD V0, 5
+    0xF0, 0x18, // 0x202 LD ST, V0
+    0x12, 0x04, // 0x204 JP 0x204
+];
+
+fn beeping(platform: Platform, program: &[u8]) -> CPU {
+    let mut cpu = CPU::new_with_platform(platform);
+    cpu.set_memory(program);
+    while cpu.get_st() == 0 {
+        cpu.tick();
+    }
+    cpu.set_audio_sample_rate(8000);
+    cpu.set_audio_envelope(0.0);
+    cpu
+}
+
+#[test]
+fn silent_without_the_sound_timer() {
+    let mut cpu = CPU::new();
+    let mut samples = [1.0; 64];
+    cpu.fill_audio(&mut samples);
+    assert!(samples.iter().all(|&sample| sample == 0.0));
+}
+
+#[test]
+fn square_wave() {
+    let mut cpu = beeping(Platform::Chip8, &BEEP);
+    cpu.set_audio_frequency(1000.0);
+    cpu.set_audio_volume(0.5);
+    let mut samples = [0.0; 16];
+    cpu.fill_audio(&mut samples);
+    assert_eq!(samples[..8], [0.5, 0.5, 0.5, 0.5, -0.5, -0.5, -0.5, -0.5]);
+    assert_eq!(samples[8..], samples[..8]);
+
+    let mut samples = [0; 8];
+    cpu.fill_audio_i16(&mut samples);
+    assert_eq!(samples, [16383, 16383, 16383, 16383, -16383, -16383, -16383,
+        -16383]);
+}
+
+#[test]
+fn envelope_avoids_clicks() {
+    let mut cpu = beeping(Platform::Chip8, &BEEP);
+    // 10 samples to fade
+    cpu.set_audio_envelope(10.0 / 8000.0);
+    cpu.set_audio_volume(1.0);
+    let mut samples = [0.0; 20];
+    cpu.fill_audio(&mut samples);
+    let levels: Vec<f32> = samples.iter().map(|sample| sample.abs()).collect();
+    assert!((levels[0] - 0.1).abs() < 1e-4);
+    assert!(levels.windows(2).take(9).all(|pair| pair[0] < pair[1]));
+    assert!(levels[10..].iter().all(|&level| level == 1.0));
+
+    // Fade out once the timer runs down
+    cpu.set_instructions_per_frame(1);
+    while cpu.get_st() > 0 {
+        cpu.run_frame();
+    }
+    cpu.fill_audio(&mut samples);
+    assert!((samples[0].abs() - 0.9).abs() < 1e-4);
+    assert!(samples[10..].iter().all(|&sample| sample == 0.0));
+}
+
+#[test]
+fn buffers_for_js() {
+    let mut cpu = beeping(Platform::Chip8, &BEEP);
+    cpu.set_audio_frequency(2000.0);
+    cpu.generate_audio(4);
+    let samples = unsafe {
+        std::slice::from_raw_parts(cpu.get_audio_pointer(), 4)
+    };
+    assert_eq!(samples, [0.25, 0.25, -0.25, -0.25]);
+    let samples = unsafe {
+        std::slice::from_raw_parts(cpu.get_audio_i16_pointer(), 4)
+    };
+    assert_eq!(samples, [8191, 8191, -8191, -8191]);
+}
+
+#[test]
+fn xochip_plays_the_pattern() {
+    let mut program = vec![
+        0xA2, 0x0A, // 0x200 LD I, 0x20A
+        0xF0, 0x02, // 0x202 AUDIO
+        0x60, 0x05, // 0x204 LD V0, 5
+        0xF0, 0x18, // 0x206 LD ST, V0
+        0x12, 0x08, // 0x208 JP 0x208
+        0xF0, 0xCC, // 0x20A pattern
+    ];
+    program.resize(program.len() + 14, 0);
+    let mut cpu = beeping(Platform::XoChip, &program);
+    // The default pitch plays 4000 bits a second
+    cpu.set_audio_sample_rate(4000);
+    let mut samples = [0.0; 16];
+    cpu.fill_audio(&mut samples);
+    let bits: Vec<bool> = samples.iter().map(|&sample| sample > 0.0).collect();
+    assert_eq!(bits, [true, true, true, true, false, false, false, false,
+        true, true, false, false, true, true, false, false]);
+}
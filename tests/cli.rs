@@ -0,0 +1,172 @@
+//! Tests for the headless chip8 binary.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+extern crate chip_8_emu;
+use chip_8_emu::CPU;
+
+// Write a ROM to a temporary file unique to the test
+fn rom_file(name: &str, rom: &[u8]) -> PathBuf {
+    let path = env::temp_dir()
+        .join(format!("chip8-cli-{}-{}.ch8", name, std::process::id()));
+    fs::write(&path, rom).unwrap();
+    path
+}
+
+fn chip8(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+// Draws the digit in V0 when key 5 is pressed, then exits
+const DRAW_KEY: [u8; 8] = [
+    0xF0, 0x0A, // 0x200 LD V0, K
+    0xF0, 0x29, // 0x202 LD F, V0
+    0xD1, 0x15, // 0x204 DRW V1, V1, 5
+    0x00, 0xFD, // 0x206 EXIT
+];
+
+#[test]
+fn runs_frames_with_scripted_keys() {
+    let rom = rom_file("keys", &DRAW_KEY);
+    let output = chip8(&["--platform", "schip", "--key", "5@2-4",
+        rom.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    let mut lines = stdout.lines();
+    // Two frames waiting, then the key arrives. Only the waiting instruction
+    // counts in each.
+    assert_eq!(lines.next(), Some("frames 2 cycles 5"));
+    assert_eq!(lines.next(), Some("PC=0206 I=0019 SP=00 DT=00 ST=00"));
+    assert!(lines.next().unwrap().starts_with("V0=05 V1=00"));
+    assert_eq!(lines.nth(1), Some("stack []"));
+    let display: Vec<&str> = lines.collect();
+    assert_eq!(display.len(), 32);
+    assert!(display[..5].iter().map(|line| &line[..4])
+        .eq(["####", "#...", "####", "...#", "####"].iter().cloned()));
+    assert!(display[0][4..].chars().all(|c| c == '.'));
+    fs::remove_file(rom).unwrap();
+}
+
+#[test]
+fn cycle_limit_and_memory_dump() {
+    let rom = rom_file("cycles", &[
+        0x70, 0x01, // 0x200 ADD V0, 1
+        0x12, 0x00, // 0x202 JP 0x200
+    ]);
+    let output = chip8(&["-c", "0x15", "-d", "registers,memory",
+        rom.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.starts_with("frames 2 cycles 21\n"));
+    assert!(stdout.contains("\nV0=0B V1=00"));
+    assert!(stdout.contains(concat!(
+        "\n0200: 70 01 12 00 00 00 00 00 00 00 00 00 00 00 00 00",
+        "\n0210: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+        "\n*\n")));
+    fs::remove_file(rom).unwrap();
+}
+
+#[test]
+fn cycle_limit_while_waiting() {
+    let rom = rom_file("waiting", &DRAW_KEY);
+    let output = chip8(&["-c", "25", rom.to_str().unwrap()]);
+    assert!(output.status.success());
+    // Each frame tries the waiting instruction once, and twice once there
+    // are fewer than a frame's cycles left, as the part of a frame run waits
+    // and the rest idles
+    assert!(stdout(&output).starts_with("frames 20 cycles 25\n"));
+    fs::remove_file(rom).unwrap();
+}
+
+#[test]
+fn writes_pbm() {
+    let rom = rom_file("pbm", &[
+        0x00, 0xFF, // 0x200 HIGH
+        0xD0, 0x01, // 0x202 DRW V0, V0, 1
+        0x00, 0xFD, // 0x204 EXIT
+    ]);
+    let pbm = env::temp_dir()
+        .join(format!("chip8-cli-{}.pbm", std::process::id()));
+    let output = chip8(&["-p", "schip", "-d", "display", "--display", "pbm",
+        "-o", pbm.to_str().unwrap(), rom.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "");
+    let image = fs::read_to_string(&pbm).unwrap();
+    let mut lines = image.lines();
+    assert_eq!(lines.next(), Some("P1"));
+    assert_eq!(lines.next(), Some("128 64"));
+    // The first byte of the font, 0xF0, at the top left
+    assert!(lines.next().unwrap().starts_with("1 1 1 1 0 0 "));
+    assert_eq!(lines.count(), 63);
+    fs::remove_file(rom).unwrap();
+    fs::remove_file(pbm).unwrap();
+}
+
+#[test]
+fn plays_replays() {
+    let rom = [
+        0xF0, 0x0A, // 0x200 LD V0, K
+        0xC1, 0xFF, // 0x202 RND V1, 0xFF
+        0xF1, 0x15, // 0x204 LD DT, V1
+        0x12, 0x00, // 0x206 JP 0x200
+    ];
+    let mut cpu = CPU::new();
+    cpu.start_recording(&rom);
+    for frame in 0..20 {
+        match frame % 4 {
+            1 => cpu.set_key_down(frame % 16),
+            3 => cpu.set_key_up((frame - 2) % 16),
+            _ => {}
+        }
+        for _ in 0..3 {
+            cpu.tick();
+        }
+        cpu.tick_timers(frame as u64 * 17);
+    }
+    let replay = cpu.stop_recording().unwrap();
+    let replay_path = env::temp_dir()
+        .join(format!("chip8-cli-{}.c8r", std::process::id()));
+    fs::write(&replay_path, replay.to_bytes()).unwrap();
+    let rom_path = rom_file("replay", &rom);
+
+    let output = chip8(&["-d", "registers", "--replay",
+        replay_path.to_str().unwrap(), rom_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    let expected = format!("PC={:04X} I=0000 SP=00 DT={:02X} ST=00",
+        cpu.get_pc(), cpu.get_dt());
+    assert_eq!(stdout(&output).lines().nth(1), Some(&expected[..]));
+    let registers = cpu.get_registers();
+    assert!(stdout(&output).contains(&format!("V0={:02X} V1={:02X} ",
+        registers[0], registers[1])));
+
+    // Replays only play with the ROM they were recorded with
+    let other_rom = rom_file("other", &[0x12, 0x00]);
+    let output = chip8(&["--replay", replay_path.to_str().unwrap(),
+        other_rom.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    for path in [replay_path, rom_path, other_rom].iter() {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn errors() {
+    let rom = rom_file("error", &[0xFF, 0xFF]);
+    let output = chip8(&["-d", "registers", rom.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("PC=0200"));
+    assert!(!output.stderr.is_empty());
+    fs::remove_file(rom).unwrap();
+
+    assert_eq!(chip8(&["--frames"]).status.code(), Some(2));
+    assert_eq!(chip8(&["--key", "G@1", "rom.ch8"]).status.code(), Some(2));
+    assert_eq!(chip8(&["does-not-exist.ch8"]).status.code(), Some(2));
+    assert!(stdout(&chip8(&["--help"])).starts_with("usage: chip8"));
+}
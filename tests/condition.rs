@@ -0,0 +1,119 @@
+//! Tests for conditional breakpoint expressions.
+
+extern crate chip_8_emu;
+use chip_8_emu::{Condition, CPU, StopReason};
+
+fn evaluate(source: &str, cpu: &CPU) -> i64 {
+    Condition::parse(source).unwrap().evaluate(cpu)
+}
+
+#[test]
+fn variables() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0xAB]);
+    cpu.set_registers(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+    cpu.set_stack(&[0x300, 0x400]);
+    cpu.set_i(0x123);
+    cpu.set_dt(9);
+    assert_eq!(evaluate("pc", &cpu), 0x200);
+    assert_eq!(evaluate("I", &cpu), 0x123);
+    assert_eq!(evaluate("sp", &cpu), 2);
+    assert_eq!(evaluate("dt", &cpu), 9);
+    assert_eq!(evaluate("st", &cpu), 0);
+    assert_eq!(evaluate("v0", &cpu), 1);
+    assert_eq!(evaluate("VF", &cpu), 16);
+    assert_eq!(evaluate("mem[0x200]", &cpu), 0xAB);
+    assert_eq!(evaluate("mem[pc + 1]", &cpu), 0);
+    assert_eq!(evaluate("mem[0x10000]", &cpu), 0);
+}
+
+#[test]
+fn operators() {
+    let mut cpu = CPU::new();
+    cpu.set_registers(&[0, 0, 0, 11]);
+    assert_eq!(evaluate("pc == 0x200 && V3 > 10", &cpu), 1);
+    assert_eq!(evaluate("pc == 0x2A4 && V3 > 10", &cpu), 0);
+    assert_eq!(evaluate("pc != 0x200 || v3 >= 11", &cpu), 1);
+    assert_eq!(evaluate("1 + 2 == 3", &cpu), 1);
+    assert_eq!(evaluate("2 - 5", &cpu), -3);
+    assert_eq!(evaluate("-v3 + 1", &cpu), -10);
+    assert_eq!(evaluate("v3 & 3 | 4 ^ 1", &cpu), 3 | (4 ^ 1));
+    assert_eq!(evaluate("!(v3 < 5)", &cpu), 1);
+    assert_eq!(evaluate("!!v3", &cpu), 1);
+    assert_eq!(evaluate("v3 <= 11 && v3 < 11", &cpu), 0);
+}
+
+#[test]
+fn errors() {
+    let error = Condition::parse("pc == ").unwrap_err();
+    assert_eq!(error.column(), 7);
+    assert_eq!(error.message(), "unexpected end of condition");
+    let error = Condition::parse("pc == vz").unwrap_err();
+    assert_eq!(error.column(), 7);
+    assert_eq!(error.message(), "unknown name 'vz'");
+    let error = Condition::parse("(pc == 1").unwrap_err();
+    assert_eq!(error.message(), "unexpected end of condition");
+    let error = Condition::parse("pc 1").unwrap_err();
+    assert_eq!(error.column(), 4);
+    assert_eq!(error.message(), "expected an operator");
+    let error = Condition::parse("mem 1").unwrap_err();
+    assert_eq!(error.message(), "expected '['");
+    let error = Condition::parse("pc @ 1").unwrap_err();
+    assert_eq!(error.message(), "unexpected character '@'");
+}
+
+#[test]
+fn nesting_limit() {
+    let too_deep = "too long or nested too deeply";
+    let nested = |depth: usize| format!("{}1{}", "(".repeat(depth),
+        ")".repeat(depth));
+    assert!(Condition::parse(&nested(63)).is_ok());
+    let error = Condition::parse(&nested(64)).unwrap_err();
+    assert_eq!(error.column(), 65);
+    assert_eq!(error.message(), too_deep);
+    let error = Condition::parse(&nested(100_000)).unwrap_err();
+    assert_eq!(error.message(), too_deep);
+    let error = Condition::parse(&"-".repeat(100_000)).unwrap_err();
+    assert_eq!(error.column(), 65);
+    assert_eq!(error.message(), too_deep);
+
+    // Each operator in a chain nests the ones before it
+    let chain = |terms: usize| vec!["v0"; terms].join(" + ");
+    let mut cpu = CPU::new();
+    cpu.set_registers(&[1]);
+    assert_eq!(Condition::parse(&chain(64)).unwrap().evaluate(&cpu), 64);
+    let error = Condition::parse(&chain(65)).unwrap_err();
+    assert_eq!(error.column(), 5 * 64 + 1);
+    assert_eq!(error.message(), too_deep);
+    let error = cpu.add_conditional_breakpoint(&chain(100_000)).unwrap_err();
+    assert_eq!(error.message(), too_deep);
+    let wide = vec![nested(50); 10].join(" + ");
+    assert_eq!(Condition::parse(&wide).unwrap().evaluate(&cpu), 10);
+    let error = Condition::parse(&vec![nested(60); 10].join(" + "))
+        .unwrap_err();
+    assert_eq!(error.message(), too_deep);
+}
+
+#[test]
+fn conditional_breakpoints() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[
+        0x70, 0x01, // ADD V0, 1
+        0x12, 0x00, // JP 0x200
+    ]);
+    let id = cpu.add_conditional_breakpoint("v0 == 5 && pc == 0x202").unwrap();
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Condition);
+    assert_eq!(result.condition(), Some(id));
+    assert_eq!(result.cycles(), 9);
+    assert_eq!(cpu.get_registers()[0], 5);
+
+    let other = cpu.add_conditional_breakpoint("v0 == 7").unwrap();
+    assert_ne!(id, other);
+    assert_eq!(cpu.run(100).condition(), Some(other));
+    assert!(cpu.remove_conditional_breakpoint(other));
+    assert!(!cpu.remove_conditional_breakpoint(other));
+    cpu.clear_conditional_breakpoints();
+    assert_eq!(cpu.run(100).reason(), StopReason::CycleLimit);
+    assert!(cpu.add_conditional_breakpoint("v0 ==").is_err());
+}
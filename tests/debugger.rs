@@ -0,0 +1,171 @@
+//! Tests for breakpoints, watchpoints and running until something stops the
+//! CPU.
+
+extern crate chip_8_emu;
+use chip_8_emu::{
+    CPU, CpuError, CpuState, Platform, StopReason, WatchKind,
+};
+
+fn cpu_with(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new_with_platform(Platform::SuperChip);
+    cpu.set_memory(program);
+    cpu
+}
+
+// A loop incrementing V0 forever
+const COUNTER: [u8; 6] = [
+    0x70, 0x01, // 0x200 ADD V0, 1
+    0x71, 0x01, // 0x202 ADD V1, 1
+    0x12, 0x00, // 0x204 JP 0x200
+];
+
+#[test]
+fn cycle_limit() {
+    let mut cpu = cpu_with(&COUNTER);
+    let result = cpu.run(30);
+    assert_eq!(result.reason(), StopReason::CycleLimit);
+    assert_eq!(result.cycles(), 30);
+    assert_eq!(cpu.get_registers()[0], 10);
+    assert_eq!(cpu.run(0).cycles(), 0);
+}
+
+#[test]
+fn breakpoints() {
+    let mut cpu = cpu_with(&COUNTER);
+    cpu.add_breakpoint(0x202);
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Breakpoint);
+    assert_eq!(result.cycles(), 1);
+    assert_eq!(result.pc(), 0x202);
+    // Running again steps off the breakpoint and comes back round to it
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Breakpoint);
+    assert_eq!(result.cycles(), 3);
+    assert_eq!(cpu.get_registers()[..2], [2, 1]);
+
+    cpu.add_breakpoint(0x204);
+    assert_eq!(cpu.get_breakpoints(), [0x202, 0x204]);
+    assert_eq!(cpu.run(100).pc(), 0x204);
+    assert!(cpu.remove_breakpoint(0x204));
+    assert!(!cpu.remove_breakpoint(0x204));
+    assert_eq!(cpu.run(100).pc(), 0x202);
+    cpu.clear_breakpoints();
+    assert_eq!(cpu.run(100).reason(), StopReason::CycleLimit);
+}
+
+#[test]
+fn breakpoints_survive_reset() {
+    let mut cpu = cpu_with(&COUNTER);
+    cpu.add_breakpoint(0x202);
+    cpu.reset();
+    assert_eq!(cpu.get_breakpoints(), [0x202]);
+}
+
+#[test]
+fn stops_on_error() {
+    let mut cpu = cpu_with(&[
+        0x70, 0x01, // ADD V0, 1
+        0xFF, 0xFF, // Not an instruction
+    ]);
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Error);
+    assert_eq!(result.cycles(), 1);
+    assert_eq!(result.pc(), 0x202);
+    assert_eq!(result.error(),
+        Some(CpuError::UnknownOpcode { pc: 0x202, opcode: 0xFFFF }));
+    assert_eq!(result.execution_error().unwrap().pc(), 0x202);
+}
+
+#[test]
+fn stops_on_exit_and_key_wait() {
+    let mut cpu = cpu_with(&[0x00, 0xFD]); // EXIT
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Exited);
+    assert_eq!(result.error(), None);
+
+    let mut cpu = cpu_with(&[0xF0, 0x0A]); // LD V0, K
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::WaitingForKey);
+    // PC moves on, the CPU's state says it's waiting
+    assert_eq!(result.pc(), 0x202);
+    assert_eq!(cpu.get_state(), CpuState::WaitingForKey);
+}
+
+#[test]
+fn write_watchpoints() {
+    let mut cpu = cpu_with(&[
+        0x60, 0x7B, // LD V0, 123
+        0xF0, 0x33, // LD B, V0
+        0xF0, 0x55, // LD [I], V0
+        0x12, 0x06, // JP 0x206
+    ]);
+    cpu.set_i(0x300);
+    cpu.add_watchpoint(0x301, 0x302, WatchKind::Write);
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Watchpoint);
+    assert_eq!(result.cycles(), 2);
+    // The instruction completes before stopping
+    assert_eq!(result.pc(), 0x204);
+    assert_eq!(cpu.get_memory()[0x300..0x303], [1, 2, 3]);
+    let hit = result.watchpoint().unwrap();
+    assert_eq!(hit.addr(), 0x301);
+    assert_eq!(hit.kind(), WatchKind::Write);
+    assert_eq!((hit.old_value(), hit.new_value()), (0, 2));
+    assert_eq!(hit.pc(), 0x202);
+    assert_eq!(cpu.get_watchpoint_hit(), Some(hit));
+    // Fx55 only writes to 0x300
+    assert_eq!(cpu.run(100).reason(), StopReason::CycleLimit);
+    assert_eq!(cpu.get_watchpoint_hit(), None);
+}
+
+#[test]
+fn read_watchpoints() {
+    let mut program = vec![
+        0xF1, 0x65, // LD V1, [I]
+        0xD0, 0x01, // DRW V0, V0, 1
+        0x12, 0x04, // JP 0x204
+    ];
+    program.resize(0x100, 0);
+    program.extend_from_slice(&[0xAA, 0xBB]);
+    let mut cpu = cpu_with(&program);
+    cpu.set_i(0x300);
+    cpu.add_watchpoint(0x300, 0x300, WatchKind::Read);
+    cpu.add_watchpoint(0x300, 0x301, WatchKind::Write);
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Watchpoint);
+    assert_eq!(result.cycles(), 1);
+    let hit = result.watchpoint().unwrap();
+    assert_eq!((hit.addr(), hit.kind()), (0x300, WatchKind::Read));
+    assert_eq!((hit.old_value(), hit.new_value()), (0xAA, 0xAA));
+    // Sprite data is read by DRW too
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Watchpoint);
+    assert_eq!(result.watchpoint().unwrap().pc(), 0x202);
+    assert!(cpu.remove_watchpoint(0x300, 0x300, WatchKind::Read));
+    assert!(!cpu.remove_watchpoint(0x300, 0x300, WatchKind::Read));
+    assert_eq!(cpu.run(100).reason(), StopReason::CycleLimit);
+}
+
+#[test]
+fn fetch_watchpoints() {
+    let mut cpu = cpu_with(&COUNTER);
+    cpu.add_watchpoint(0x205, 0x205, WatchKind::Access);
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Watchpoint);
+    assert_eq!(result.cycles(), 3);
+    let hit = result.watchpoint().unwrap();
+    assert_eq!((hit.addr(), hit.pc()), (0x205, 0x204));
+    assert_eq!(hit.new_value(), 0x00);
+    cpu.clear_watchpoints();
+    assert_eq!(cpu.run(100).reason(), StopReason::CycleLimit);
+}
+
+#[test]
+fn failed_instructions_dont_hit_watchpoints() {
+    let mut cpu = cpu_with(&[0xFF, 0xFF]);
+    cpu.add_watchpoint(0x200, 0x201, WatchKind::Read);
+    let result = cpu.run(100);
+    assert_eq!(result.reason(), StopReason::Error);
+    assert_eq!(result.watchpoint(), None);
+    assert_eq!(cpu.get_watchpoint_hit(), None);
+}
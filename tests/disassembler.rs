@@ -0,0 +1,79 @@
+//! Tests for the Cowgod and Octo disassembler.
+
+extern crate chip_8_emu;
+use chip_8_emu::{disassemble, Instruction, Syntax, CPU};
+
+fn texts(memory: &[u8], syntax: Syntax) -> Vec<String> {
+    disassemble(memory, 0, memory.len())
+        .iter()
+        .map(|instruction| instruction.text(syntax))
+        .collect()
+}
+
+#[test]
+fn cowgod() {
+    let program = [
+        0x00, 0xE0, 0x12, 0x08, 0x6A, 0x2F, 0x81, 0x26,
+        0xD1, 0x25, 0xF3, 0x55, 0xF0, 0x00, 0xBE, 0xEF,
+        0xFF, 0xFF,
+    ];
+    assert_eq!(texts(&program, Syntax::Cowgod), [
+        "CLS",
+        "JP 0x208",
+        "LD VA, 0x2F",
+        "SHR V1, V2",
+        "DRW V1, V2, 5",
+        "LD [I], V3",
+        "LD I, LONG 0xBEEF",
+        "DW 0xFFFF",
+    ]);
+}
+
+#[test]
+fn octo() {
+    let program = [
+        0x00, 0xE0, 0x12, 0x08, 0x6A, 0x2F, 0x31, 0x02,
+        0xE4, 0xA1, 0xF3, 0x55, 0xF0, 0x00, 0xBE, 0xEF,
+        0xFF, 0xFF,
+    ];
+    assert_eq!(texts(&program, Syntax::Octo), [
+        "clear",
+        "jump 0x208",
+        "va := 0x2F",
+        "if v1 != 0x02 then",
+        "if v4 key then",
+        "save v3",
+        "i := long 0xBEEF",
+        "0xFF 0xFF",
+    ]);
+}
+
+#[test]
+fn addresses_and_lengths() {
+    let program = [0x00, 0xE0, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xEE, 0x00];
+    let instructions = disassemble(&program, 2, 100);
+    assert_eq!(instructions.len(), 2);
+    assert_eq!(instructions[0].address(), 2);
+    assert_eq!(instructions[0].opcode(), 0xF000);
+    assert_eq!(instructions[0].length(), 4);
+    assert_eq!(instructions[0].operand(), Some(0x1234));
+    assert_eq!(instructions[1].address(), 6);
+    assert_eq!(instructions[1].instruction(), Instruction::Ret);
+    // A long load with its address cut off is just data
+    let instructions = disassemble(&program, 2, 2);
+    assert_eq!(instructions[0].instruction(), Instruction::Unknown(0xF000));
+    assert_eq!(instructions[0].length(), 2);
+}
+
+#[test]
+fn disassemble_at() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0x60, 0x01, 0x70, 0x02, 0x12, 0x00]);
+    let instructions = cpu.disassemble_at(0x202, 2);
+    assert_eq!(instructions.len(), 2);
+    assert_eq!(instructions[0].address(), 0x202);
+    assert_eq!(instructions[0].opcode(), 0x7002);
+    assert_eq!(instructions[1].text(Syntax::Cowgod), "JP 0x200");
+    // Stops at the end of memory
+    assert_eq!(cpu.disassemble_at(0xFFC, 10).len(), 2);
+}
@@ -0,0 +1,114 @@
+//! Tests for the packed framebuffer and rendering it through the palette.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, Platform};
+
+// Draw the 0 font sprite at (0, 0) twice
+const DRAW_TWICE: [u8; 8] = [
+    0x60, 0x00, // LD V0, 0
+    0xF0, 0x29, // LD F, V0
+    0xD0, 0x05, // DRW V0, V0, 5
+    0xD0, 0x05, // DRW V0, V0, 5
+];
+
+fn drawn_cpu(platform: Platform) -> CPU {
+    let mut cpu = CPU::new_with_platform(platform);
+    cpu.set_memory(&DRAW_TWICE);
+    cpu.init_hex_sprites();
+    for _ in 0..3 {
+        cpu.tick();
+    }
+    cpu
+}
+
+#[test]
+fn renders_through_the_palette() {
+    let mut cpu = drawn_cpu(Platform::Chip8);
+    cpu.set_palette(0x123456, 0xABCDEF);
+    let rgb = cpu.get_display();
+    assert_eq!(rgb.len(), 64 * 32 * 3);
+    // The top of the 0 is 0xF0
+    assert_eq!(rgb[..3], [0x12, 0x34, 0x56]);
+    assert_eq!(rgb[3 * 3..4 * 3], [0x12, 0x34, 0x56]);
+    assert_eq!(rgb[4 * 3..5 * 3], [0xAB, 0xCD, 0xEF]);
+
+    let mut rgba = vec![0; 64 * 32 * 4];
+    cpu.render_rgba(&mut rgba);
+    assert_eq!(rgba[..4], [0x12, 0x34, 0x56, 0xFF]);
+    assert_eq!(rgba[4 * 4..5 * 4], [0xAB, 0xCD, 0xEF, 0xFF]);
+
+    cpu.render_display();
+    let rendered = unsafe {
+        std::slice::from_raw_parts(cpu.get_display_pointer(), rgb.len())
+    };
+    assert_eq!(rendered, &rgb[..]);
+}
+
+#[test]
+fn palette_doesnt_affect_collisions() {
+    let mut cpu = drawn_cpu(Platform::Chip8);
+    // Lit pixels the same colour as unlit ones still collide
+    cpu.set_palette(0, 0);
+    cpu.tick();
+    assert_eq!(cpu.get_registers()[0xF], 1);
+    assert!(cpu.get_display().iter().all(|&byte| byte == 0));
+    assert_eq!(cpu.get_pixel_planes(0, 0), 0);
+}
+
+#[test]
+fn packs_a_bit_a_pixel() {
+    let cpu = drawn_cpu(Platform::Chip8);
+    let plane = unsafe {
+        std::slice::from_raw_parts(cpu.get_plane_pointer(0), 128 * 64 / 8)
+    };
+    // Lores rows are 8 bytes
+    assert_eq!(plane[0], 0xF0);
+    assert_eq!(plane[8], 0x90);
+    assert_eq!(plane[32], 0xF0);
+    assert!(plane[1..8].iter().all(|&byte| byte == 0));
+    let second = unsafe {
+        std::slice::from_raw_parts(cpu.get_plane_pointer(1), 128 * 64 / 8)
+    };
+    assert!(second.iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn xochip_colours() {
+    let mut program = vec![
+        0xF3, 0x01, // PLANE 3
+        0xD0, 0x01, // DRW V0, V0, 1
+    ];
+    // Plane 1 gets the left two pixels, plane 2 the first and third
+    program.resize(0x100, 0);
+    program.extend_from_slice(&[0xC0, 0xA0]);
+    let mut cpu = CPU::new_with_platform(Platform::XoChip);
+    cpu.set_memory(&program);
+    cpu.set_i(0x300);
+    cpu.tick();
+    cpu.tick();
+    for (planes, colour) in [0x000001, 0x000002, 0x000003, 0x000004]
+        .iter().enumerate() {
+        cpu.set_palette_colour(planes as u8, *colour);
+    }
+    assert_eq!(cpu.get_palette_colour(3), 0x000004);
+    let blue = cpu.get_display().chunks(3).map(|pixel| pixel[2])
+        .take(4).collect::<Vec<_>>();
+    assert_eq!(blue, [4, 2, 3, 1]);
+}
+
+#[test]
+fn save_states_keep_the_palette() {
+    let cpu = drawn_cpu(Platform::Chip8);
+    let state = cpu.save_state();
+    let mut loaded = CPU::new();
+    loaded.set_palette(0xFFFFFF, 0x000080);
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.get_palette_colour(0), 0x000080);
+    assert_eq!(loaded.get_palette_colour(1), 0xFFFFFF);
+    for y in 0..32 {
+        for x in 0..64 {
+            assert_eq!(loaded.get_pixel_planes(x, y),
+                cpu.get_pixel_planes(x, y));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Tests for the errors reported by CPU::try_tick.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, CpuError, StepOutcome};
+
+#[test]
+fn executed() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0x60, 0x01]); // LD V0, 0x01
+    assert_eq!(cpu.try_tick(), Ok(StepOutcome::Executed));
+}
+
+#[test]
+fn unknown_opcode() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[
+        0x60, 0x01, // LD V0, 0x01
+        0xFF, 0xFF, // Not an instruction
+    ]);
+    cpu.tick();
+    assert_eq!(cpu.try_tick(),
+        Err(CpuError::UnknownOpcode { pc: 0x202, opcode: 0xFFFF }));
+    // The CPU is left on the bad instruction rather than being reset
+    assert_eq!(cpu.get_pc(), 0x202);
+    assert_eq!(cpu.get_registers()[0], 0x01);
+    assert_eq!(cpu.get_memory()[0x202..0x204], [0xFF, 0xFF]);
+    // Plain tick doesn't get any further either
+    cpu.tick();
+    assert_eq!(cpu.get_pc(), 0x202);
+}
+
+#[test]
+fn stack_underflow() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0x00, 0xEE]); // RET
+    assert_eq!(cpu.try_tick(), Err(CpuError::StackUnderflow));
+    assert_eq!(cpu.get_pc(), 0x200);
+    assert_eq!(cpu.get_sp(), 0);
+}
+
+#[test]
+fn stack_overflow() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0x22, 0x00]); // CALL 0x200
+    for _ in 0..16 {
+        assert_eq!(cpu.try_tick(), Ok(StepOutcome::Executed));
+    }
+    assert_eq!(cpu.get_sp(), 16);
+    assert_eq!(cpu.try_tick(), Err(CpuError::StackOverflow));
+    assert_eq!(cpu.get_sp(), 16);
+}
+
+#[test]
+fn memory_out_of_bounds() {
+    { // BCD straddling the end of memory
+        let mut cpu = CPU::new();
+        cpu.set_memory(&[0xF0, 0x33]); // LD B, V0
+        cpu.set_i(0xFFE);
+        assert_eq!(cpu.try_tick(),
+            Err(CpuError::MemoryOutOfBounds { addr: 0x1000 }));
+    }
+    { // Register store past the end of memory
+        let mut cpu = CPU::new();
+        cpu.set_memory(&[0xFF, 0x55]); // LD [I], VF
+        cpu.set_registers(&[0xAA; 16]);
+        cpu.set_i(0xFF8);
+        assert_eq!(cpu.try_tick(),
+            Err(CpuError::MemoryOutOfBounds { addr: 0x1000 }));
+        // Nothing was written before the error was raised
+        assert_eq!(cpu.get_memory()[0xFF8..], [0; 8]);
+    }
+    { // Register load past the end of memory
+        let mut cpu = CPU::new();
+        cpu.set_memory(&[0xFF, 0x65]); // LD VF, [I]
+        cpu.set_i(0x2000);
+        assert_eq!(cpu.try_tick(),
+            Err(CpuError::MemoryOutOfBounds { addr: 0x2000 }));
+    }
+    { // Sprite read past the end of memory
+        let mut cpu = CPU::new();
+        cpu.set_memory(&[0xD0, 0x1F]); // DRW V0, V1, 15
+        cpu.set_registers(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x7]);
+        cpu.set_i(0xFFA);
+        assert_eq!(cpu.try_tick(),
+            Err(CpuError::MemoryOutOfBounds { addr: 0x1000 }));
+        // VF wasn't cleared by the failed draw
+        assert_eq!(cpu.get_registers()[0xF], 0x7);
+    }
+}
+
+#[test]
+fn pc_out_of_bounds() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0x1F, 0xFF]); // JP 0xFFF
+    cpu.tick();
+    assert_eq!(cpu.try_tick(), Err(CpuError::PcOutOfBounds { pc: 0xFFF }));
+}
+
+#[test]
+fn add_byte_wraps() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0x70, 0x02]); // ADD V0, 0x02
+    cpu.set_registers(&[0xFF]);
+    assert_eq!(cpu.try_tick(), Ok(StepOutcome::Executed));
+    assert_eq!(cpu.get_registers()[0], 0x01);
+}
+
+#[test]
+fn key_out_of_range() {
+    // Keys past F are never pressed, so SKP doesn't skip and SKNP does
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[
+        0x60, 0x20, // 0x200 LD V0, 0x20
+        0xE0, 0x9E, // 0x202 SKP V0
+        0xE0, 0xA1, // 0x204 SKNP V0
+    ]);
+    cpu.set_keyboard(0xFFFF);
+    assert_eq!(cpu.try_tick(), Ok(StepOutcome::Executed));
+    assert_eq!(cpu.try_tick(), Ok(StepOutcome::Executed));
+    assert_eq!(cpu.get_pc(), 0x204);
+    assert_eq!(cpu.try_tick(), Ok(StepOutcome::Executed));
+    assert_eq!(cpu.get_pc(), 0x208);
+}
+
+#[test]
+fn waiting_for_key() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0xF0, 0x0A]); // LD V0, K
+    assert_eq!(cpu.try_tick(), Ok(StepOutcome::WaitingForKey));
+    cpu.set_keyboard(0x0002);
+    assert_eq!(cpu.try_tick(), Ok(StepOutcome::Executed));
+    assert_eq!(cpu.get_registers()[0], 0x1);
+}
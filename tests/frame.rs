@@ -0,0 +1,170 @@
+//! Tests for running a frame at a time.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, CpuState, Quirks, StopReason};
+
+// Count instructions in V0 forever
+const COUNTER: [u8; 4] = [
+    0x70, 0x01, // 0x200 ADD V0, 1
+    0x12, 0x00, // 0x202 JP 0x200
+];
+
+fn cpu_with(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.set_memory(program);
+    cpu
+}
+
+#[test]
+fn runs_a_frame_then_ticks_the_timers() {
+    let mut cpu = cpu_with(&COUNTER);
+    assert_eq!(cpu.get_instructions_per_frame(), 10);
+    cpu.set_dt(3);
+    let result = cpu.run_frame();
+    assert_eq!(result.reason(), StopReason::CycleLimit);
+    assert_eq!(result.cycles(), 10);
+    assert_eq!(cpu.get_registers()[0], 5);
+    assert_eq!(cpu.get_dt(), 2);
+
+    cpu.set_instructions_per_frame(4);
+    cpu.run_frame();
+    cpu.run_frame();
+    assert_eq!(cpu.get_registers()[0], 9);
+    assert_eq!(cpu.get_dt(), 0);
+    cpu.run_frame();
+    assert_eq!(cpu.get_dt(), 0);
+
+    cpu.set_instructions_per_frame(0);
+    assert_eq!(cpu.get_instructions_per_frame(), 1);
+}
+
+#[test]
+fn interrupted_frames_carry_on() {
+    let mut cpu = cpu_with(&COUNTER);
+    cpu.set_dt(10);
+    cpu.add_breakpoint(0x202);
+    let result = cpu.run_frame();
+    assert_eq!(result.reason(), StopReason::Breakpoint);
+    assert_eq!(result.cycles(), 1);
+    assert_eq!(cpu.get_dt(), 10);
+    cpu.clear_breakpoints();
+    // The rest of the frame
+    let result = cpu.run_frame();
+    assert_eq!(result.cycles(), 9);
+    assert_eq!(cpu.get_dt(), 9);
+    assert_eq!(cpu.run_frame().cycles(), 10);
+}
+
+#[test]
+fn key_waits_end_the_frame() {
+    let mut cpu = cpu_with(&[
+        0x70, 0x01, // 0x200 ADD V0, 1
+        0xF1, 0x0A, // 0x202 LD V1, K
+        0x12, 0x00, // 0x204 JP 0x200
+    ]);
+    cpu.set_dt(5);
+    let result = cpu.run_frame();
+    assert_eq!(result.reason(), StopReason::WaitingForKey);
+    assert_eq!(result.cycles(), 2);
+    assert_eq!(cpu.get_dt(), 4);
+    cpu.run_frame();
+    assert_eq!(cpu.get_dt(), 3);
+    assert_eq!(cpu.get_registers()[0], 1);
+}
+
+#[test]
+fn display_waits_end_the_frame() {
+    let program = [
+        0x70, 0x01, // 0x200 ADD V0, 1
+        0xD1, 0x11, // 0x202 DRW V1, V1, 1
+        0x12, 0x00, // 0x204 JP 0x200
+    ];
+    for &display_wait in &[false, true] {
+        let mut cpu = CPU::new_with_quirks(Quirks {
+            display_wait,
+            ..Quirks::default()
+        });
+        cpu.set_memory(&program);
+        cpu.set_dt(10);
+        let result = cpu.run_frame();
+        if !display_wait {
+            assert_eq!(result.reason(), StopReason::CycleLimit);
+            assert_eq!(cpu.get_registers()[0], 4);
+            continue;
+        }
+        // A sprite a frame
+        assert_eq!(result.reason(), StopReason::WaitingForVblank);
+        assert_eq!(result.cycles(), 2);
+        assert_eq!(cpu.get_dt(), 9);
+        assert_eq!(cpu.get_state(), CpuState::Running);
+        let result = cpu.run_frame();
+        assert_eq!(result.cycles(), 3);
+        assert_eq!(cpu.get_registers()[0], 2);
+        assert_eq!(cpu.get_pc(), 0x204);
+        // Catching up carries on past frames cut short
+        assert!(cpu.run_realtime(1000).is_some());
+        assert_eq!(cpu.get_dt(), 4);
+        assert_eq!(cpu.get_registers()[0], 6);
+    }
+}
+
+#[test]
+fn errors_stop_the_frame() {
+    let mut cpu = cpu_with(&[0xFF, 0xFF]);
+    cpu.set_dt(5);
+    assert_eq!(cpu.run_frame().reason(), StopReason::Error);
+    assert_eq!(cpu.get_dt(), 5);
+    assert!(cpu.run_realtime(1000).is_some());
+    assert_eq!(cpu.get_dt(), 5);
+}
+
+#[test]
+fn realtime() {
+    let mut cpu = cpu_with(&COUNTER);
+    cpu.set_dt(100);
+    // Only a few frames are caught up after a long gap
+    let result = cpu.run_realtime(1_000_000).unwrap();
+    assert_eq!(result.cycles(), 10);
+    assert_eq!(cpu.get_dt(), 96);
+    // Not a whole frame later
+    assert!(cpu.run_realtime(1_000_010).is_none());
+    assert_eq!(cpu.get_dt(), 96);
+    // Frame boundaries fall every 1/60th of a second
+    cpu.run_realtime(1_000_017);
+    assert_eq!(cpu.get_dt(), 95);
+    cpu.run_realtime(1_000_050);
+    assert_eq!(cpu.get_dt(), 93);
+    assert_eq!(cpu.get_registers()[0], 7 * 5);
+    // Time going backwards doesn't run anything
+    assert!(cpu.run_realtime(0).is_none());
+}
+
+#[test]
+fn frames_are_recorded_in_replays() {
+    let rom = [
+        0xF0, 0x07, // 0x200 LD V0, DT
+        0x81, 0x04, // 0x202 ADD V1, V0
+        0x12, 0x00, // 0x204 JP 0x200
+    ];
+    let mut cpu = CPU::new();
+    cpu.start_recording(&rom);
+    cpu.set_dt(50);
+    for _ in 0..5 {
+        cpu.run_frame();
+    }
+    let replay = cpu.stop_recording().unwrap();
+    assert_eq!(replay.event_count(), 5);
+    assert_eq!(replay.events()[1].cycle, 20);
+}
+
+#[test]
+fn frame_position_is_saved() {
+    let mut cpu = cpu_with(&COUNTER);
+    cpu.set_instructions_per_frame(6);
+    cpu.add_breakpoint(0x202);
+    cpu.run_frame();
+    let mut loaded = CPU::new();
+    loaded.load_state(&cpu.save_state()).unwrap();
+    assert_eq!(loaded.get_instructions_per_frame(), 6);
+    assert_eq!(loaded.run_frame().cycles(), 5);
+}
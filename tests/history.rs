@@ -0,0 +1,186 @@
+//! Tests for recording history and rewinding through it.
+
+extern crate chip_8_emu;
+use chip_8_emu::CPU;
+
+fn cpu_with(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.set_memory(program);
+    cpu.init_hex_sprites();
+    cpu
+}
+
+// Draws a digit, stores BCD of V0 and loops
+const PROGRAM: [u8; 14] = [
+    0x00, 0xE0, // 0x200 CLS
+    0xF0, 0x29, // 0x202 LD F, V0
+    0xD1, 0x15, // 0x204 DRW V1, V1, 5
+    0xA3, 0x00, // 0x206 LD I, 0x300
+    0xF0, 0x33, // 0x208 LD B, V0
+    0x70, 0x7B, // 0x20A ADD V0, 123
+    0x12, 0x00, // 0x20C JP 0x200
+];
+
+#[test]
+fn disabled_by_default() {
+    let mut cpu = cpu_with(&PROGRAM);
+    cpu.tick();
+    assert_eq!(cpu.get_history_length(), 0);
+    assert!(!cpu.step_back());
+    assert_eq!(cpu.get_pc(), 0x202);
+}
+
+#[test]
+fn step_back_undoes_everything() {
+    let mut cpu = cpu_with(&PROGRAM);
+    // Get rid of the loading pattern, which isn't made of pixels to rewind
+    cpu.tick();
+    cpu.enable_history(1 << 20);
+    let start = cpu.save_state();
+    let mut states = vec![start];
+    for _ in 0..20 {
+        cpu.tick();
+        states.push(cpu.save_state());
+    }
+    assert_eq!(cpu.get_history_length(), 20);
+    states.pop();
+    while let Some(state) = states.pop() {
+        assert!(cpu.step_back());
+        assert_eq!(cpu.save_state(), state);
+    }
+    assert!(!cpu.step_back());
+}
+
+#[test]
+fn rewind_frames() {
+    let mut cpu = cpu_with(&PROGRAM);
+    cpu.enable_history(1 << 20);
+    let mut time = 0;
+    cpu.tick_timers(time);
+    for _ in 0..3 {
+        time += 17;
+        cpu.tick_timers(time);
+        for _ in 0..5 {
+            cpu.tick();
+        }
+    }
+    assert_eq!(cpu.get_history_length(), 15);
+    // Back to the start of the current frame
+    assert_eq!(cpu.rewind_frames(1), 1);
+    assert_eq!(cpu.get_history_length(), 10);
+    assert_eq!(cpu.get_pc(), 0x206);
+    // Now at a frame boundary, so this goes back a whole frame
+    assert_eq!(cpu.rewind_frames(1), 1);
+    assert_eq!(cpu.get_history_length(), 5);
+    // Running forwards again starts a new frame where the old one started
+    cpu.tick();
+    assert_eq!(cpu.rewind_frames(1), 1);
+    assert_eq!(cpu.get_history_length(), 5);
+    assert_eq!(cpu.rewind_frames(5), 1);
+    assert_eq!(cpu.get_pc(), 0x200);
+}
+
+#[test]
+fn step_back_across_a_frame() {
+    let mut cpu = CPU::new();
+    cpu.enable_history(1 << 20);
+    cpu.start_recording(&[
+        0x70, 0x01, // 0x200 ADD V0, 1
+        0x12, 0x00, // 0x202 JP 0x200
+    ]);
+    cpu.set_instructions_per_frame(4);
+    cpu.run_frame();
+    cpu.run_frame();
+    assert_eq!(cpu.get_instruction_count(), 8);
+    // Back into the middle of the first frame
+    assert_eq!(cpu.rewind_frames(1), 1);
+    assert!(cpu.step_back());
+    assert_eq!(cpu.get_instruction_count(), 3);
+    assert_eq!(cpu.run_frame().cycles(), 1);
+    assert_eq!(cpu.get_instruction_count(), 4);
+    // Inputs are stamped with the rewound count, and the ones recorded after
+    // it are forgotten
+    cpu.set_key_down(1);
+    let replay = cpu.stop_recording().unwrap();
+    let cycles: Vec<u64> = replay.events().iter()
+        .map(|event| event.cycle)
+        .collect();
+    assert_eq!(cycles, [4, 4]);
+}
+
+#[test]
+fn step_back_while_replaying() {
+    let rom = [
+        0xF0, 0x0A, // 0x200 LD V0, K
+        0x61, 0x01, // 0x202 LD V1, 1
+        0x12, 0x04, // 0x204 JP 0x204
+    ];
+    let mut cpu = CPU::new();
+    cpu.start_recording(&rom);
+    cpu.tick();
+    cpu.set_key_down(3);
+    cpu.tick();
+    cpu.set_key_up(3);
+    for _ in 0..4 {
+        cpu.tick();
+    }
+    let replay = cpu.stop_recording().unwrap();
+    assert_eq!(cpu.get_registers()[..2], [3, 1]);
+
+    let mut cpu = CPU::new();
+    cpu.enable_history(1 << 20);
+    cpu.start_replay(&replay, &rom).unwrap();
+    for _ in 0..5 {
+        cpu.run(1);
+    }
+    let mut steps = 0;
+    while steps < 8 && cpu.step_back() {
+        steps += 1;
+    }
+    assert_eq!(steps, 5);
+    assert_eq!(cpu.get_keyboard(), 0);
+    // The inputs play again as the instructions they came after do
+    cpu.run(2);
+    assert_eq!(cpu.get_keyboard(), 1 << 3);
+    cpu.step_back();
+    assert_eq!(cpu.get_keyboard(), 0);
+    while cpu.is_replaying() {
+        cpu.tick();
+    }
+    assert_eq!(cpu.get_registers()[..2], [3, 1]);
+    assert_eq!(cpu.get_instruction_count(), replay.length());
+}
+
+#[test]
+fn budget() {
+    let mut cpu = cpu_with(&PROGRAM);
+    cpu.enable_history(4096);
+    for _ in 0..1000 {
+        cpu.tick();
+    }
+    let length = cpu.get_history_length();
+    assert!(length > 0 && length < 1000);
+    // Shrinking the budget drops the oldest history
+    cpu.enable_history(1024);
+    assert!(cpu.get_history_length() < length);
+    let mut steps = 0;
+    while cpu.step_back() {
+        steps += 1;
+    }
+    assert!(steps > 0);
+    cpu.disable_history();
+    cpu.tick();
+    assert_eq!(cpu.get_history_length(), 0);
+}
+
+#[test]
+fn reset_clears_history() {
+    let mut cpu = cpu_with(&PROGRAM);
+    cpu.enable_history(1 << 20);
+    cpu.tick();
+    cpu.reset();
+    assert_eq!(cpu.get_history_length(), 0);
+    cpu.load_program_memory(PROGRAM.to_vec());
+    cpu.tick();
+    assert_eq!(cpu.get_history_length(), 1);
+}
@@ -0,0 +1,62 @@
+//! Tests for decoding opcodes into instructions and executing them directly.
+
+extern crate chip_8_emu;
+use chip_8_emu::{decode, CPU, CpuError, Instruction, Platform, StepOutcome};
+
+#[test]
+fn decode_operands() {
+    assert_eq!(decode(0x00E0), Instruction::Cls);
+    assert_eq!(decode(0x1ABC), Instruction::Jp(0xABC));
+    assert_eq!(decode(0x3A42), Instruction::SeByte { x: 0xA, kk: 0x42 });
+    assert_eq!(decode(0x8126), Instruction::Shr { x: 1, y: 2 });
+    assert_eq!(decode(0xD12F), Instruction::Drw { x: 1, y: 2, n: 0xF });
+    assert_eq!(decode(0xF30A), Instruction::LdVxK(3));
+    assert_eq!(decode(0xF201), Instruction::Plane(2));
+    assert_eq!(decode(0x5121), Instruction::Unknown(0x5121));
+    assert_eq!(decode(0xFFFF), Instruction::Unknown(0xFFFF));
+}
+
+#[test]
+fn opcode_round_trip() {
+    for opcode in 0..=0xFFFF {
+        assert_eq!(decode(opcode).opcode(), opcode, "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn platform_and_length() {
+    assert_eq!(Instruction::Cls.platform(), Platform::Chip8);
+    assert_eq!(Instruction::High.platform(), Platform::SuperChip);
+    assert_eq!(Instruction::LdHf(0).platform(), Platform::SuperChip);
+    assert_eq!(Instruction::Scu(1).platform(), Platform::XoChip);
+    assert_eq!(Instruction::LdILong.platform(), Platform::XoChip);
+    assert_eq!(Instruction::Cls.length(), 2);
+    assert_eq!(Instruction::LdILong.length(), 4);
+}
+
+#[test]
+fn execute() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[]);
+    assert_eq!(cpu.execute(Instruction::LdByte { x: 2, kk: 0x40 }),
+        Ok(StepOutcome::Executed));
+    assert_eq!(cpu.execute(Instruction::AddByte { x: 2, kk: 0x02 }),
+        Ok(StepOutcome::Executed));
+    assert_eq!(cpu.get_registers()[2], 0x42);
+    cpu.execute(Instruction::Jp(0x300)).unwrap();
+    assert_eq!(cpu.get_pc(), 0x300);
+}
+
+#[test]
+fn execute_checks_platform() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[]);
+    cpu.execute(Instruction::Jp(0x302)).unwrap();
+    assert_eq!(cpu.execute(Instruction::High),
+        Err(CpuError::UnknownOpcode { pc: 0x300, opcode: 0x00FF }));
+    assert_eq!(cpu.execute(Instruction::Unknown(0xFFFF)),
+        Err(CpuError::UnknownOpcode { pc: 0x300, opcode: 0xFFFF }));
+    let mut cpu = CPU::new_with_platform(Platform::SuperChip);
+    assert_eq!(cpu.execute(Instruction::High), Ok(StepOutcome::Executed));
+    assert_eq!(cpu.get_display_width(), 128);
+}
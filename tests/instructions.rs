@@ -1,16 +1,9 @@
-//! Test suite for the Web and headless browsers.
-
-#![cfg(target_arch = "wasm32")]
-
-extern crate wasm_bindgen_test;
-use wasm_bindgen_test::*;
+//! Tests for each CHIP-8 instruction.
 
 extern crate chip_8_emu;
-use chip_8_emu::CPU;
+use chip_8_emu::{CPU, CpuState};
 
-wasm_bindgen_test_configure!(run_in_browser);
-
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ret() {
     let mut cpu = CPU::new();
     // Set the RET instruction in memory
@@ -22,7 +15,7 @@ fn instruction_ret() {
     assert_eq!(cpu.get_sp(), 0);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_jp() {
     let mut cpu = CPU::new();
     // Set the JP instruction in memory targeting 0xABC
@@ -31,7 +24,7 @@ fn instruction_jp() {
     assert_eq!(cpu.get_pc(), 0xABC);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_call() {
     let mut cpu = CPU::new();
     // Store the current PC value to validate the stack entry
@@ -47,7 +40,7 @@ fn instruction_call() {
     assert_eq!(cpu.get_stack()[0], original_pc + 2);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_se_vx_byte() {
     { // Skip
         let mut cpu = CPU::new();
@@ -71,7 +64,7 @@ fn instruction_se_vx_byte() {
     }
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_sne_vx_byte() {
     { // Skip
         let mut cpu = CPU::new();
@@ -95,7 +88,7 @@ fn instruction_sne_vx_byte() {
     }
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_se_vx_vy() {
     { // Skip
         let mut cpu = CPU::new();
@@ -121,7 +114,7 @@ fn instruction_se_vx_vy() {
     }
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ld_byte() {
     let mut cpu = CPU::new();
     // Set 4x load byte instructions targeting gpr0, gpr1, gpr2, gpr15
@@ -141,7 +134,7 @@ fn instruction_ld_byte() {
     assert_eq!(cpu.get_registers()[15], 0x0F);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_add_byte() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -166,7 +159,7 @@ fn instruction_add_byte() {
     assert_eq!(cpu.get_registers()[15], 0x11);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ld_register() {
     let mut cpu = CPU::new();
     cpu.set_registers(&[0x0, 0xF]);
@@ -180,7 +173,7 @@ fn instruction_ld_register() {
     assert_eq!(cpu.get_registers()[1], 0x0F);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_or_register() {
     let mut cpu = CPU::new();
     cpu.set_registers(&[0x00, 0xFF, 0xA0, 0x0A, 0x00]);
@@ -197,7 +190,7 @@ fn instruction_or_register() {
     assert_eq!(cpu.get_registers()[4], 0x00);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_and_register() {
     let mut cpu = CPU::new();
     cpu.set_registers(&[0x00, 0xFF, 0xA0, 0x0A, 0xFF]);
@@ -214,7 +207,7 @@ fn instruction_and_register() {
     assert_eq!(cpu.get_registers()[4], 0xFF);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_xor_register() {
     let mut cpu = CPU::new();
     cpu.set_registers(&[0x00, 0xFF, 0xA0, 0x0A, 0xFF]);
@@ -231,7 +224,7 @@ fn instruction_xor_register() {
     assert_eq!(cpu.get_registers()[4], 0x00);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_add_register() {
     let mut cpu = CPU::new();
     cpu.set_registers(&[0x00, 0xFF, 0xFF, 0xFF, 0xAA, 0x11]);
@@ -251,7 +244,7 @@ fn instruction_add_register() {
     assert_eq!(cpu.get_registers()[0xF], 0);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_sub_register() {
     let mut cpu = CPU::new();
     cpu.set_registers(&[0x00, 0xFF, 0xFF, 0xFF, 0xAA, 0x11]);
@@ -271,7 +264,7 @@ fn instruction_sub_register() {
     assert_eq!(cpu.get_registers()[0xF], 1);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_shr_register() {
     let mut cpu = CPU::new();
     cpu.set_registers(&[0x00, 0x01, 0xFF]);
@@ -291,7 +284,7 @@ fn instruction_shr_register() {
     assert_eq!(cpu.get_registers()[0xF], 1);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_subn_register() {
     let mut cpu = CPU::new();
     cpu.set_registers(&[0x01, 0x00, 0xFF, 0xFF, 0x11, 0xAA]);
@@ -311,7 +304,7 @@ fn instruction_subn_register() {
     assert_eq!(cpu.get_registers()[0xF], 1);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_shl_register() {
     let mut cpu = CPU::new();
     cpu.set_registers(&[0x00, 0x0F, 0xFF]);
@@ -331,7 +324,7 @@ fn instruction_shl_register() {
     assert_eq!(cpu.get_registers()[0xF], 1);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_sne_register() {
     { // Skip
         let mut cpu = CPU::new();
@@ -357,7 +350,7 @@ fn instruction_sne_register() {
     }
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ldi() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -367,7 +360,7 @@ fn instruction_ldi() {
     assert_eq!(cpu.get_i(), 0x123);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_jpv0() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -378,7 +371,7 @@ fn instruction_jpv0() {
     assert_eq!(cpu.get_pc(), 0x226);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_rnd() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -394,12 +387,12 @@ fn instruction_rnd() {
     assert!(cpu.get_registers()[0] == 0x00);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_drw() {
     // TODO : Implement testing of the DRW instruction
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_skp() {
     { // Skip
         let mut cpu = CPU::new();
@@ -433,7 +426,7 @@ fn instruction_skp() {
     }
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_sknp() {
     { // Skip
         let mut cpu = CPU::new();
@@ -467,7 +460,7 @@ fn instruction_sknp() {
     }
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ld_gpr_dt() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -478,7 +471,7 @@ fn instruction_ld_gpr_dt() {
     assert_eq!(cpu.get_registers()[0], 0xAB);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ld_kp() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -489,9 +482,12 @@ fn instruction_ld_kp() {
     cpu.tick();
     cpu.tick();
     cpu.tick();
-    assert_eq!(cpu.get_pc(), original_pc);
+    // Still waiting on the first
+    assert_eq!(cpu.get_pc(), original_pc + 2);
+    assert_eq!(cpu.get_state(), CpuState::WaitingForKey);
     cpu.set_keyboard(0x0400); // A key is pressed
     cpu.tick();
+    assert_eq!(cpu.get_state(), CpuState::Running);
     assert_eq!(cpu.get_pc(), original_pc + 2);
     assert_eq!(cpu.get_registers()[0], 0xA);
     cpu.set_keyboard(0b1000000000000000); // F is pressed
@@ -500,7 +496,7 @@ fn instruction_ld_kp() {
     assert_eq!(cpu.get_registers()[0], 0xF);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ld_dt_gpr() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -511,7 +507,7 @@ fn instruction_ld_dt_gpr() {
     assert_eq!(cpu.get_dt(), 0xAB);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ld_st_gpr() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -522,7 +518,7 @@ fn instruction_ld_st_gpr() {
     assert_eq!(cpu.get_st(), 0xAB);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_add_i_gpr() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -534,7 +530,7 @@ fn instruction_add_i_gpr() {
     assert_eq!(cpu.get_i(), 0x22);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ld_i_font() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -548,7 +544,7 @@ fn instruction_ld_i_font() {
     assert_eq!(cpu.get_i(), 0x05);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_bcd() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -563,7 +559,7 @@ fn instruction_bcd() {
     assert_eq!(cpu.get_memory()[output_address as usize] + 2, 3);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ld_i_vx() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -588,7 +584,7 @@ fn instruction_ld_i_vx() {
         [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_ld_vx_i() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
@@ -613,15 +609,16 @@ fn instruction_ld_vx_i() {
         [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
 }
 
-#[wasm_bindgen_test]
+#[test]
 fn instruction_cls() {
     let mut cpu = CPU::new();
     cpu.set_memory(&[
         0x00, 0xE0, // CLS
     ]);
     cpu.set_display(&[
-        0xFF, 0xFF,
+        0x01, 0x01,
     ]);
     cpu.tick();
-    assert_eq!(cpu.get_display()[0..2], [0x00, 0x00]);
+    assert_eq!(cpu.get_pixel_planes(0, 0), 0);
+    assert_eq!(cpu.get_pixel_planes(1, 0), 0);
 }
\ No newline at end of file
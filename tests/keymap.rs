@@ -0,0 +1,112 @@
+//! Tests for mapping host keys to CHIP-8 keys.
+
+extern crate chip_8_emu;
+use chip_8_emu::{Keymap, KeymapError, CPU};
+
+const ROM: [u8; 2] = [0x12, 0x00];
+
+#[test]
+fn cosmac_layout() {
+    let keymap = Keymap::cosmac();
+    let keys: Vec<Option<u8>> = ["x", "1", "2", "3", "q", "w", "e", "a", "s",
+        "d", "z", "c", "4", "r", "f", "v"].iter()
+        .map(|host| keymap.get(host))
+        .collect();
+    let expected: Vec<Option<u8>> = (0..16).map(Some).collect();
+    assert_eq!(keys, expected);
+    // Case doesn't matter, so shift doesn't change what's pressed
+    assert_eq!(keymap.get("W"), Some(5));
+    assert_eq!(keymap.get("5"), None);
+    assert_eq!(Keymap::hex().get("B"), Some(0xB));
+}
+
+#[test]
+fn presses_keys() {
+    let mut cpu = CPU::new();
+    let mut keymap = Keymap::cosmac();
+    keymap.bind("ArrowLeft", 4).unwrap();
+    assert!(keymap.press(&mut cpu, "arrowleft"));
+    assert!(keymap.press(&mut cpu, "V"));
+    assert!(!keymap.press(&mut cpu, "Enter"));
+    assert_eq!(cpu.get_keyboard(), 1 << 4 | 1 << 0xF);
+    assert!(keymap.release(&mut cpu, "ArrowLeft"));
+    assert_eq!(cpu.get_keyboard(), 1 << 0xF);
+    assert!(keymap.unbind("v"));
+    assert!(!keymap.release(&mut cpu, "v"));
+}
+
+#[test]
+fn validates_bindings() {
+    let mut keymap = Keymap::new();
+    assert_eq!(keymap.bind("q", 16), Err(KeymapError::InvalidKey));
+    for host in &["", "\n", "a\tb"] {
+        assert_eq!(keymap.bind(host, 1), Err(KeymapError::InvalidHostKey));
+    }
+    assert_eq!(keymap, Keymap::new());
+    // Any key a browser names can be bound
+    for (key, host) in [" ", "=", "[", "#", "\""].iter().enumerate() {
+        keymap.bind(host, key as u8).unwrap();
+        assert_eq!(keymap.get(host), Some(key as u8));
+    }
+
+    // Out of range keys are ignored rather than overflowing the keyboard
+    let mut cpu = CPU::new();
+    cpu.set_key_down(16);
+    cpu.set_key_down(u32::MAX);
+    cpu.set_key_up(31);
+    assert_eq!(cpu.get_keyboard(), 0);
+}
+
+#[test]
+fn rom_overrides() {
+    let mut keymap = Keymap::cosmac();
+    keymap.bind_for_rom(&ROM, "q", 0xF).unwrap();
+    keymap.bind_for_rom(&ROM, "ArrowUp", 2).unwrap();
+    assert_eq!(keymap.get("q"), Some(4));
+    let for_rom = keymap.for_rom(&ROM);
+    assert_eq!(for_rom.get("q"), Some(0xF));
+    assert_eq!(for_rom.get("arrowup"), Some(2));
+    assert_eq!(for_rom.get("w"), Some(5));
+    let other = keymap.for_rom(&[0x00, 0xE0]);
+    assert_eq!(other.get("q"), Some(4));
+    assert_eq!(other.get("ArrowUp"), None);
+}
+
+#[test]
+fn text_round_trip() {
+    let mut keymap = Keymap::new();
+    keymap.bind("x", 0).unwrap();
+    keymap.bind("ArrowLeft", 0xA).unwrap();
+    keymap.bind_for_rom(&ROM, "x", 5).unwrap();
+    let text = keymap.to_text();
+    assert_eq!(text, "arrowleft = A\nx = 0\n\n[rom 392D622C]\nx = 5\n");
+    assert_eq!(Keymap::from_text(&text), Ok(keymap));
+
+    let written = "# Arrows\nArrowUp=5\n  ArrowDown = 8  \n";
+    let keymap = Keymap::from_text(written).unwrap();
+    assert_eq!(keymap.get("arrowdown"), Some(8));
+
+    let mut keymap = Keymap::new();
+    for (key, host) in [" ", "=", "[rom", "#", "\"", "\\", "a=b"].iter()
+        .enumerate() {
+        keymap.bind(host, key as u8).unwrap();
+    }
+    let text = keymap.to_text();
+    assert_eq!(text, concat!("\" \" = 0\n", "\"\\\"\" = 4\n",
+        "\"#\" = 3\n", "\"=\" = 1\n", "\"[rom\" = 2\n", "\\ = 5\n",
+        "\"a=b\" = 6\n"));
+    assert_eq!(Keymap::from_text(&text), Ok(keymap));
+    let keymap = Keymap::from_text("\"\\\\\"=7").unwrap();
+    assert_eq!(keymap.get("\\"), Some(7));
+
+    assert_eq!(Keymap::from_text("q 4"), Err(KeymapError::InvalidLine));
+    assert_eq!(Keymap::from_text("\" = 4"), Err(KeymapError::InvalidLine));
+    assert_eq!(Keymap::from_text("\"q\" 4"), Err(KeymapError::InvalidLine));
+    assert_eq!(Keymap::from_text(" = 4"), Err(KeymapError::InvalidHostKey));
+    assert_eq!(Keymap::from_text("q = 10"), Err(KeymapError::InvalidKey));
+    assert_eq!(Keymap::from_text("q = G"), Err(KeymapError::InvalidKey));
+    assert_eq!(Keymap::from_text("[rom 1234]"),
+        Err(KeymapError::InvalidLine));
+    assert_eq!(KeymapError::InvalidKey.to_string(),
+        "CHIP-8 keys go from 0 to F");
+}
@@ -0,0 +1,249 @@
+//! Tests for the opcodes whose behaviour depends on the quirks profile.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, CpuState, Quirks, QuirksPreset};
+
+fn pixel_is_on(cpu: &CPU, x: usize, y: usize) -> bool {
+    cpu.get_pixel_planes(x, y) != 0
+}
+
+#[test]
+fn default_quirks() {
+    assert_eq!(CPU::new().get_quirks(), Quirks::default());
+    assert_eq!(Quirks::preset(QuirksPreset::XoChip), Quirks::xochip());
+}
+
+#[test]
+fn shift_uses_vy() {
+    for &shift_uses_vy in &[false, true] {
+        let mut cpu = CPU::new_with_quirks(Quirks {
+            shift_uses_vy,
+            ..Quirks::default()
+        });
+        cpu.set_registers(&[0x04, 0x81]);
+        cpu.set_memory(&[
+            0x80, 0x16, // SHR V0, V1
+            0x80, 0x1E, // SHL V0, V1
+        ]);
+        cpu.tick();
+        if shift_uses_vy {
+            assert_eq!(cpu.get_registers()[0], 0x40);
+            assert_eq!(cpu.get_registers()[0xF], 1);
+        } else {
+            assert_eq!(cpu.get_registers()[0], 0x02);
+            assert_eq!(cpu.get_registers()[0xF], 0);
+        }
+        cpu.tick();
+        if shift_uses_vy {
+            assert_eq!(cpu.get_registers()[0], 0x02);
+            assert_eq!(cpu.get_registers()[0xF], 1);
+        } else {
+            assert_eq!(cpu.get_registers()[0], 0x04);
+            assert_eq!(cpu.get_registers()[0xF], 0);
+        }
+    }
+}
+
+#[test]
+fn load_store_increments_i() {
+    for &load_store_increments_i in &[false, true] {
+        let mut cpu = CPU::new_with_quirks(Quirks {
+            load_store_increments_i,
+            ..Quirks::default()
+        });
+        cpu.set_memory(&[
+            0xF3, 0x55, // LD [I], V3
+            0xF1, 0x65, // LD V1, [I]
+        ]);
+        cpu.set_i(0x300);
+        cpu.tick();
+        cpu.tick();
+        if load_store_increments_i {
+            assert_eq!(cpu.get_i(), 0x306);
+        } else {
+            assert_eq!(cpu.get_i(), 0x300);
+        }
+    }
+}
+
+#[test]
+fn jump_uses_vx() {
+    for &jump_uses_vx in &[false, true] {
+        let mut cpu = CPU::new_with_quirks(Quirks {
+            jump_uses_vx,
+            ..Quirks::default()
+        });
+        cpu.set_memory(&[
+            0xB3, 0x00, // JP V0, 0x300
+        ]);
+        cpu.set_registers(&[0x10, 0, 0, 0x20]);
+        cpu.tick();
+        if jump_uses_vx {
+            assert_eq!(cpu.get_pc(), 0x320);
+        } else {
+            assert_eq!(cpu.get_pc(), 0x310);
+        }
+    }
+}
+
+#[test]
+fn logic_resets_vf() {
+    for &logic_resets_vf in &[false, true] {
+        let mut cpu = CPU::new_with_quirks(Quirks {
+            logic_resets_vf,
+            ..Quirks::default()
+        });
+        cpu.set_memory(&[
+            0x80, 0x11, // OR V0, V1
+            0x80, 0x12, // AND V0, V1
+            0x80, 0x13, // XOR V0, V1
+        ]);
+        for _ in 0..3 {
+            cpu.set_registers(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0x5]);
+            cpu.tick();
+            let expected_vf = if logic_resets_vf { 0 } else { 0x5 };
+            assert_eq!(cpu.get_registers()[0xF], expected_vf);
+        }
+    }
+}
+
+#[test]
+fn clip_sprites() {
+    for &clip_sprites in &[false, true] {
+        let mut cpu = CPU::new_with_quirks(Quirks {
+            clip_sprites,
+            ..Quirks::default()
+        });
+        cpu.set_memory(&[
+            0x00, 0xE0, // CLS
+            0xD0, 0x12, // DRW V0, V1, 2
+        ]);
+        cpu.set_registers(&[60, 31]);
+        cpu.set_i(0x000);
+        cpu.init_hex_sprites(); // "0" is 0xF0, 0x90
+        cpu.tick();
+        cpu.tick();
+        // The on screen part is always drawn
+        assert!(pixel_is_on(&cpu, 60, 31));
+        assert!(pixel_is_on(&cpu, 63, 31));
+        // The second row would wrap to the top of the screen
+        assert_eq!(pixel_is_on(&cpu, 60, 0), !clip_sprites);
+        assert_eq!(pixel_is_on(&cpu, 63, 0), !clip_sprites);
+    }
+    { // The origin always wraps
+        let mut cpu = CPU::new_with_quirks(Quirks::cosmac_vip());
+        cpu.set_memory(&[
+            0x00, 0xE0, // CLS
+            0xD0, 0x11, // DRW V0, V1, 1
+        ]);
+        cpu.set_registers(&[64 + 2, 32 + 3]);
+        cpu.set_i(0x000);
+        cpu.init_hex_sprites();
+        cpu.tick();
+        cpu.tick();
+        assert!(pixel_is_on(&cpu, 2, 3));
+    }
+}
+
+#[test]
+fn display_wait() {
+    let mut cpu = CPU::new_with_quirks(Quirks::cosmac_vip());
+    cpu.enable_history(1024);
+    cpu.set_memory(&[
+        0xD0, 0x01, // DRW V0, V0, 1
+        0x70, 0x01, // ADD V0, 1
+    ]);
+    cpu.set_i(0x000);
+    cpu.init_hex_sprites();
+    cpu.tick();
+    assert!(pixel_is_on(&cpu, 0, 0));
+    assert_eq!(cpu.get_state(), CpuState::WaitingForVblank);
+    // Ticks before the next frame do nothing
+    cpu.tick();
+    assert_eq!(cpu.get_registers()[0], 0);
+    let mut loaded = CPU::new();
+    loaded.load_state(&cpu.save_state()).unwrap();
+    assert_eq!(loaded.get_state(), CpuState::WaitingForVblank);
+    loaded.tick_timers(17);
+    assert_eq!(loaded.get_state(), CpuState::Running);
+    loaded.tick();
+    assert_eq!(loaded.get_registers()[0], 1);
+    // Stepping back over the wait waits again
+    cpu.step_back();
+    assert_eq!(cpu.get_state(), CpuState::WaitingForVblank);
+    cpu.step_back();
+    assert_eq!(cpu.get_state(), CpuState::Running);
+    assert!(!pixel_is_on(&cpu, 0, 0));
+}
+
+#[test]
+fn wait_for_key_release() {
+    for &wait_for_key_release in &[false, true] {
+        let mut cpu = CPU::new_with_quirks(Quirks {
+            wait_for_key_release,
+            ..Quirks::default()
+        });
+        cpu.set_memory(&[
+            0xF0, 0x0A, // LD V0, K
+        ]);
+        // Held from before Fx0A started
+        cpu.set_key_down(3);
+        cpu.tick();
+        if !wait_for_key_release {
+            assert_eq!(cpu.get_state(), CpuState::Running);
+            assert_eq!(cpu.get_registers()[0], 3);
+            continue;
+        }
+        assert_eq!(cpu.get_state(), CpuState::WaitingForKey);
+        cpu.set_key_down(7);
+        cpu.tick();
+        assert_eq!(cpu.get_state(), CpuState::WaitingForKey);
+        // Releasing the held key doesn't finish it, releasing 7 does
+        cpu.set_key_up(3);
+        cpu.tick();
+        assert_eq!(cpu.get_state(), CpuState::WaitingForKey);
+        cpu.set_key_up(7);
+        cpu.tick();
+        assert_eq!(cpu.get_state(), CpuState::Running);
+        assert_eq!(cpu.get_registers()[0], 7);
+        assert_eq!(cpu.get_pc(), 0x202);
+    }
+}
+
+#[test]
+fn key_tapped_between_ticks() {
+    let mut cpu = CPU::new_with_quirks(Quirks::cosmac_vip());
+    cpu.set_memory(&[
+        0xF5, 0x0A, // LD V5, K
+    ]);
+    cpu.tick();
+    let state = cpu.save_state();
+    cpu.set_key_down(0xA);
+    cpu.set_key_up(0xA);
+    cpu.tick();
+    assert_eq!(cpu.get_state(), CpuState::Running);
+    assert_eq!(cpu.get_registers()[5], 0xA);
+
+    // The wait survives a save state
+    let mut loaded = CPU::new_with_quirks(Quirks::cosmac_vip());
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.get_state(), CpuState::WaitingForKey);
+    loaded.set_key_down(2);
+    loaded.tick();
+    assert_eq!(loaded.get_state(), CpuState::WaitingForKey);
+    loaded.set_key_up(2);
+    loaded.tick();
+    assert_eq!(loaded.get_registers()[5], 2);
+}
+
+#[test]
+fn bits_round_trip() {
+    for preset in [QuirksPreset::CosmacVip, QuirksPreset::Chip48,
+        QuirksPreset::SuperChip, QuirksPreset::XoChip].iter() {
+        let quirks = Quirks::preset(*preset);
+        assert_eq!(Quirks::from_bits(quirks.to_bits()), quirks);
+    }
+    assert_eq!(Quirks::default().to_bits(), 0);
+    assert_eq!(Quirks::from_bits(0xFFFF_FF80), Quirks::default());
+}
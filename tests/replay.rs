@@ -0,0 +1,162 @@
+//! Tests for recording inputs and playing them back.
+
+extern crate chip_8_emu;
+use chip_8_emu::{
+    CPU, InputEvent, InputKind, Platform, Quirks, Replay, ReplayError,
+    StateError,
+};
+
+const ROM: [u8; 16] = [
+    0xF0, 0x0A, // 0x200 LD V0, K
+    0xC1, 0xFF, // 0x202 RND V1, 0xFF
+    0x82, 0x14, // 0x204 ADD V2, V1
+    0xF3, 0x07, // 0x206 LD V3, DT
+    0x84, 0x34, // 0x208 ADD V4, V3
+    0xF1, 0x15, // 0x20A LD DT, V1
+    0xD0, 0x15, // 0x20C DRW V0, V1, 5
+    0x12, 0x00, // 0x20E JP 0x200
+];
+
+// Play for a while, pressing keys and ticking the timers
+fn play(cpu: &mut CPU) {
+    let mut time = 0;
+    for frame in 0..40u32 {
+        match frame % 7 {
+            2 => cpu.set_key_down(frame % 16),
+            4 => cpu.set_key_up((frame - 2) % 16),
+            _ => {}
+        }
+        for _ in 0..9 {
+            cpu.tick();
+        }
+        time += 17;
+        cpu.tick_timers(time);
+    }
+}
+
+fn record() -> (CPU, Replay) {
+    let mut cpu = CPU::new();
+    cpu.set_rng_seed(1234);
+    cpu.start_recording(&ROM);
+    assert!(cpu.is_recording());
+    play(&mut cpu);
+    let replay = cpu.stop_recording().unwrap();
+    assert!(!cpu.is_recording());
+    (cpu, replay)
+}
+
+fn play_back(cpu: &mut CPU, replay: &Replay) {
+    cpu.start_replay(replay, &ROM).unwrap();
+    while cpu.is_replaying() {
+        cpu.tick();
+    }
+}
+
+#[test]
+fn records_inputs() {
+    let (cpu, replay) = record();
+    assert_eq!(replay.length(), 360);
+    assert_eq!(cpu.get_instruction_count(), 360);
+    assert_eq!(replay.platform(), Platform::Chip8);
+    assert_eq!(replay.quirks(), Quirks::default());
+    assert_eq!(replay.seed(), 1234);
+    assert_eq!(replay.events()[..3], [
+        InputEvent { cycle: 9, kind: InputKind::Timers(1) },
+        InputEvent { cycle: 18, kind: InputKind::Timers(1) },
+        InputEvent { cycle: 18, kind: InputKind::KeyDown(2) },
+    ]);
+    assert_eq!(replay.event_count(), 40 + 6 + 6);
+}
+
+#[test]
+fn replays_the_same_run() {
+    let (recorded, replay) = record();
+    let replay = Replay::from_bytes(&replay.to_bytes()).unwrap();
+    let mut cpu = CPU::new();
+    // Live input is ignored while the replay plays
+    cpu.start_replay(&replay, &ROM).unwrap();
+    cpu.set_key_down(0);
+    cpu.tick_timers(1_000_000);
+    assert_eq!(cpu.get_pc(), 0x200);
+    while cpu.is_replaying() {
+        cpu.tick();
+    }
+    // The replay finishes after the last recorded instruction
+    assert_eq!(cpu.get_instruction_count(), replay.length());
+    assert_eq!(cpu.get_registers(), recorded.get_registers());
+    assert_eq!(cpu.get_pc(), recorded.get_pc());
+    assert_eq!(cpu.get_dt(), recorded.get_dt());
+    assert_eq!(cpu.get_memory(), recorded.get_memory());
+    assert_eq!(cpu.get_display()[..], recorded.get_display()[..]);
+}
+
+#[test]
+fn replays_use_the_recorded_quirks() {
+    let mut cpu = CPU::new_with_quirks(Quirks::cosmac_vip());
+    cpu.start_recording(&ROM);
+    play(&mut cpu);
+    let replay = cpu.stop_recording().unwrap();
+    let mut other = CPU::new();
+    play_back(&mut other, &replay);
+    assert_eq!(other.get_quirks(), Quirks::cosmac_vip());
+    // And live input works again
+    other.set_key_down(3);
+    other.tick_timers(0);
+}
+
+#[test]
+fn rejects_bad_replays() {
+    let (_, replay) = record();
+    let bytes = replay.to_bytes();
+    let mut cpu = CPU::new();
+    let mut rom = ROM.to_vec();
+    rom[1] = 0x0B;
+    assert_eq!(cpu.start_replay(&replay, &rom), Err(ReplayError::WrongRom));
+    let mut schip = CPU::new_with_platform(Platform::SuperChip);
+    assert_eq!(schip.start_replay(&replay, &ROM),
+        Err(ReplayError::WrongPlatform));
+    assert!(!cpu.is_replaying());
+
+    assert_eq!(Replay::from_bytes(b"nope"), Err(ReplayError::NotAReplay));
+    assert_eq!(Replay::from_bytes(&bytes[..5]), Err(ReplayError::Truncated));
+    let mut version = bytes.clone();
+    version[5] += 1;
+    assert_eq!(Replay::from_bytes(&version),
+        Err(ReplayError::UnsupportedVersion));
+    let mut corrupt = bytes.clone();
+    corrupt[40] ^= 1;
+    assert_eq!(Replay::from_bytes(&corrupt), Err(ReplayError::BadChecksum));
+    assert_eq!(ReplayError::WrongRom.to_string(),
+        "replay was recorded with another ROM");
+}
+
+#[test]
+fn reset_stops_recording() {
+    let mut cpu = CPU::new();
+    cpu.start_recording(&ROM);
+    cpu.tick();
+    cpu.reset();
+    assert!(!cpu.is_recording());
+    assert_eq!(cpu.stop_recording(), None);
+    assert_eq!(cpu.get_instruction_count(), 0);
+}
+
+#[test]
+fn no_loading_states_mid_replay() {
+    let (mut cpu, replay) = record();
+    let state = cpu.save_state();
+    cpu.start_recording(&ROM);
+    cpu.tick();
+    assert_eq!(cpu.load_state(&state), Err(StateError::Replaying));
+    assert!(cpu.is_recording());
+    assert_eq!(cpu.stop_recording().unwrap().length(), 1);
+
+    cpu.start_replay(&replay, &ROM).unwrap();
+    cpu.tick();
+    assert_eq!(cpu.load_state(&state), Err(StateError::Replaying));
+    assert!(cpu.is_replaying());
+    assert_eq!(cpu.get_instruction_count(), 1);
+    cpu.stop_replay();
+    assert_eq!(cpu.load_state(&state), Ok(()));
+    assert_eq!(cpu.save_state(), state);
+}
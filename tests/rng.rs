@@ -0,0 +1,107 @@
+//! Tests for the random number source used by RND.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, Rng, XorShift};
+
+// Fill V0 to VF with random bytes
+const RANDOM_REGISTERS: [u8; 32] = [
+    0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF,
+    0xC4, 0xFF, 0xC5, 0xFF, 0xC6, 0xFF, 0xC7, 0xFF,
+    0xC8, 0xFF, 0xC9, 0xFF, 0xCA, 0xFF, 0xCB, 0xFF,
+    0xCC, 0xFF, 0xCD, 0xFF, 0xCE, 0xFF, 0xCF, 0xFF,
+];
+
+fn random_registers(cpu: &mut CPU) -> [u8; 16] {
+    cpu.set_memory(&RANDOM_REGISTERS);
+    for _ in 0..16 {
+        cpu.tick();
+    }
+    cpu.get_registers()
+}
+
+#[test]
+fn seeds_are_reproducible() {
+    let first = random_registers(&mut CPU::new());
+    assert_eq!(random_registers(&mut CPU::new()), first);
+    // Not all the same byte
+    assert!(first.iter().any(|&byte| byte != first[0]));
+
+    let mut cpu = CPU::new();
+    cpu.set_rng_seed(42);
+    let seeded = random_registers(&mut cpu);
+    assert_ne!(seeded, first);
+    let mut cpu = CPU::new();
+    cpu.set_rng_seed(42);
+    assert_eq!(random_registers(&mut cpu), seeded);
+}
+
+#[test]
+fn xorshift() {
+    let mut rng = XorShift::new(1);
+    let bytes: Vec<u8> = (0..1000).map(|_| rng.next_byte()).collect();
+    // Restoring the state carries on the same sequence
+    let mut restored = XorShift::new(rng.state().unwrap());
+    assert_eq!(restored.next_byte(), rng.next_byte());
+    // Roughly even
+    let high = bytes.iter().filter(|&&byte| byte >= 0x80).count();
+    assert!(400 < high && high < 600, "{} high bytes", high);
+    // A zero seed still produces numbers
+    let mut zero = XorShift::new(0);
+    assert!((0..16).any(|_| zero.next_byte() != 0));
+}
+
+struct Counter(u8);
+
+impl Rng for Counter {
+    fn next_byte(&mut self) -> u8 {
+        self.0 = self.0.wrapping_add(1);
+        self.0
+    }
+}
+
+#[test]
+fn custom_sources() {
+    let mut cpu = CPU::new();
+    cpu.set_rng(Counter(0));
+    assert_eq!(random_registers(&mut cpu)[..4], [1, 2, 3, 4]);
+    // The mask still applies
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0xC0, 0x0F]);
+    cpu.set_rng(Counter(0x3F));
+    cpu.tick();
+    assert_eq!(cpu.get_registers()[0], 0x00);
+}
+
+#[test]
+fn save_states_and_history_keep_the_sequence() {
+    let mut cpu = CPU::new();
+    cpu.set_rng_seed(7);
+    let state = cpu.save_state();
+    let expected = random_registers(&mut cpu);
+
+    let mut loaded = CPU::new();
+    loaded.load_state(&state).unwrap();
+    assert_eq!(random_registers(&mut loaded), expected);
+
+    // Stepping back rewinds the generator too
+    let mut cpu = CPU::new();
+    cpu.set_memory(&RANDOM_REGISTERS);
+    cpu.enable_history(1 << 20);
+    cpu.tick();
+    cpu.tick();
+    let v1 = cpu.get_registers()[1];
+    assert!(cpu.step_back());
+    cpu.tick();
+    assert_eq!(cpu.get_registers()[1], v1);
+
+    // Sources that can't be saved are left alone
+    let mut cpu = CPU::new();
+    cpu.set_rng(Counter(0));
+    let state = cpu.save_state();
+    let mut loaded = CPU::new();
+    loaded.set_rng(Counter(10));
+    loaded.load_state(&state).unwrap();
+    loaded.set_memory(&[0xC0, 0xFF]);
+    loaded.tick();
+    assert_eq!(loaded.get_registers()[0], 11);
+}
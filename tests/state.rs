@@ -0,0 +1,85 @@
+//! Tests for saving and loading CPU state.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, Platform, Quirks, StateError};
+
+fn running_cpu() -> CPU {
+    let mut cpu = CPU::new_with_platform(Platform::SuperChip);
+    cpu.set_memory(&[
+        0x00, 0xFF, // HIGH
+        0x60, 0x2A, // LD V0, 0x2A
+        0xF0, 0x29, // LD F, V0
+        0xD1, 0x15, // DRW V1, V1, 5
+        0x22, 0x0C, // CALL 0x20C
+        0x00, 0x00,
+        0x70, 0x01, // ADD V0, 1
+        0x12, 0x0C, // JP 0x20C
+    ]);
+    cpu.init_hex_sprites();
+    cpu.set_dt(30);
+    for _ in 0..8 {
+        cpu.tick();
+    }
+    cpu
+}
+
+#[test]
+fn round_trip() {
+    let cpu = running_cpu();
+    let state = cpu.save_state();
+    let mut loaded = CPU::new();
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.get_platform(), Platform::SuperChip);
+    assert_eq!(loaded.get_quirks(), Quirks::superchip());
+    assert_eq!(loaded.get_pc(), cpu.get_pc());
+    assert_eq!(loaded.get_i(), cpu.get_i());
+    assert_eq!(loaded.get_sp(), 1);
+    assert_eq!(loaded.get_dt(), 30);
+    assert_eq!(loaded.get_registers(), cpu.get_registers());
+    assert_eq!(loaded.get_stack(), cpu.get_stack());
+    assert_eq!(loaded.get_memory(), cpu.get_memory());
+    assert_eq!(loaded.get_display_width(), 128);
+    assert_eq!(loaded.get_display()[..], cpu.get_display()[..]);
+    assert_eq!(loaded.save_state(), state);
+    // And it carries on from the same place
+    let mut cpu = cpu;
+    cpu.tick();
+    loaded.tick();
+    assert_eq!(loaded.save_state(), cpu.save_state());
+}
+
+#[test]
+fn keeps_breakpoints() {
+    let state = running_cpu().save_state();
+    let mut cpu = CPU::new();
+    cpu.add_breakpoint(0x300);
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.get_breakpoints(), [0x300]);
+}
+
+#[test]
+fn rejects_bad_states() {
+    let state = running_cpu().save_state();
+    let mut cpu = CPU::new();
+    cpu.set_registers(&[7]);
+
+    assert_eq!(cpu.load_state(b"nope"), Err(StateError::NotAState));
+    assert_eq!(cpu.load_state(&state[..5]), Err(StateError::Truncated));
+
+    let mut version = state.clone();
+    version[5] += 1;
+    assert_eq!(cpu.load_state(&version),
+        Err(StateError::UnsupportedVersion));
+
+    let mut corrupt = state.clone();
+    corrupt[100] ^= 1;
+    assert_eq!(cpu.load_state(&corrupt), Err(StateError::BadChecksum));
+
+    let mut truncated = state[..state.len() - 100].to_vec();
+    truncated.extend_from_slice(&state[state.len() - 4..]);
+    assert_eq!(cpu.load_state(&truncated), Err(StateError::BadChecksum));
+
+    // None of that touched the CPU
+    assert_eq!(cpu.get_registers()[0], 7);
+    assert_eq!(cpu.get_platform(), Platform::Chip8);
+}
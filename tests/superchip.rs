@@ -0,0 +1,140 @@
+//! Tests for the SUPER-CHIP 1.1 instructions.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, CpuError, Platform, StepOutcome};
+
+fn pixel_is_on(cpu: &CPU, x: usize, y: usize) -> bool {
+    cpu.get_pixel_planes(x, y) != 0
+}
+
+fn superchip_cpu(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new_with_platform(Platform::SuperChip);
+    cpu.set_memory(program);
+    cpu.init_hex_sprites();
+    cpu
+}
+
+#[test]
+fn superchip_opcodes_need_superchip() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&[0x00, 0xFF]); // HIGH
+    assert_eq!(cpu.try_tick(),
+        Err(CpuError::UnknownOpcode { pc: 0x200, opcode: 0x00FF }));
+}
+
+#[test]
+fn resolution() {
+    let mut cpu = superchip_cpu(&[
+        0x00, 0xFF, // HIGH
+        0x00, 0xFE, // LOW
+    ]);
+    assert_eq!(cpu.get_display_width(), 64);
+    assert_eq!(cpu.get_display_height(), 32);
+    cpu.tick();
+    assert_eq!(cpu.get_display_width(), 128);
+    assert_eq!(cpu.get_display_height(), 64);
+    cpu.tick();
+    assert_eq!(cpu.get_display_width(), 64);
+    assert_eq!(cpu.get_display_height(), 32);
+}
+
+#[test]
+fn hires_drawing() {
+    let mut cpu = superchip_cpu(&[
+        0x00, 0xFF, // HIGH
+        0xD0, 0x11, // DRW V0, V1, 1
+    ]);
+    cpu.set_registers(&[120, 60]);
+    cpu.set_i(0x000); // Top row of "0", 0xF0
+    cpu.tick();
+    cpu.tick();
+    assert!(pixel_is_on(&cpu, 120, 60));
+    assert!(pixel_is_on(&cpu, 123, 60));
+    assert!(!pixel_is_on(&cpu, 124, 60));
+}
+
+#[test]
+fn big_sprite() {
+    let mut program = vec![
+        0x00, 0xFF, // HIGH
+        0xD0, 0x10, // DRW V0, V1, 0
+        0xD0, 0x10, // DRW V0, V1, 0
+    ];
+    // 16x16 sprite with the top left and bottom right pixels set at 0x300
+    program.resize(0x100 + 32, 0);
+    program[0x100] = 0x80;
+    program[0x100 + 31] = 0x01;
+    let mut cpu = superchip_cpu(&program);
+    cpu.set_i(0x300);
+    cpu.tick();
+    cpu.tick();
+    assert!(pixel_is_on(&cpu, 0, 0));
+    assert!(pixel_is_on(&cpu, 15, 15));
+    assert_eq!(cpu.get_registers()[0xF], 0);
+    cpu.tick();
+    assert!(!pixel_is_on(&cpu, 0, 0));
+    assert!(!pixel_is_on(&cpu, 15, 15));
+    assert_eq!(cpu.get_registers()[0xF], 1);
+}
+
+#[test]
+fn scrolling() {
+    let mut cpu = superchip_cpu(&[
+        0x00, 0xE0, // CLS
+        0xD0, 0x11, // DRW V0, V1, 1
+        0x00, 0xC3, // SCD 3
+        0x00, 0xFB, // SCR
+        0x00, 0xFC, // SCL
+        0x00, 0xFC, // SCL
+    ]);
+    cpu.set_registers(&[8, 2]);
+    cpu.set_i(0x000); // Top row of "0", 0xF0
+    cpu.tick();
+    cpu.tick();
+    assert!(pixel_is_on(&cpu, 8, 2));
+    cpu.tick();
+    assert!(!pixel_is_on(&cpu, 8, 2));
+    assert!(pixel_is_on(&cpu, 8, 5));
+    cpu.tick();
+    assert!(!pixel_is_on(&cpu, 8, 5));
+    assert!(pixel_is_on(&cpu, 12, 5));
+    assert!(pixel_is_on(&cpu, 15, 5));
+    cpu.tick();
+    cpu.tick();
+    assert!(pixel_is_on(&cpu, 4, 5));
+    assert!(pixel_is_on(&cpu, 7, 5));
+    assert!(!pixel_is_on(&cpu, 8, 5));
+}
+
+#[test]
+fn exit() {
+    let mut cpu = superchip_cpu(&[0x00, 0xFD]); // EXIT
+    assert_eq!(cpu.try_tick(), Ok(StepOutcome::Exited));
+    assert_eq!(cpu.try_tick(), Ok(StepOutcome::Exited));
+    assert_eq!(cpu.get_pc(), 0x200);
+}
+
+#[test]
+fn big_font() {
+    let mut cpu = superchip_cpu(&[
+        0xF0, 0x30, // LD HF, V0
+    ]);
+    cpu.set_registers(&[0x9]);
+    cpu.tick();
+    let address = cpu.get_i() as usize;
+    assert_eq!(address, 0x50 + 9 * 10);
+    assert_eq!(cpu.get_memory()[address], 0x3C);
+}
+
+#[test]
+fn rpl_flags() {
+    let mut cpu = superchip_cpu(&[
+        0xF3, 0x75, // LD R, V3
+        0xF7, 0x85, // LD V7, R
+    ]);
+    cpu.set_registers(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    cpu.tick();
+    assert_eq!(cpu.get_rpl_flags()[..8], [1, 2, 3, 4, 0, 0, 0, 0]);
+    cpu.tick();
+    assert_eq!(cpu.get_registers()[..8], [1, 2, 3, 4, 0, 0, 0, 0]);
+}
@@ -0,0 +1,158 @@
+//! Tests for counting cycles and running by them.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, StopReason, Timing};
+
+// Count loops in V0 forever
+const COUNTER: [u8; 4] = [
+    0x70, 0x01, // 0x200 ADD V0, 1
+    0x12, 0x00, // 0x202 JP 0x200
+];
+
+fn vip_cpu(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.set_timing(Timing::CosmacVip);
+    cpu.set_memory(program);
+    cpu
+}
+
+// Cycles the next instruction takes
+fn tick_cycles(cpu: &mut CPU) -> u64 {
+    let start = cpu.cycles_elapsed();
+    cpu.tick();
+    cpu.cycles_elapsed() - start
+}
+
+#[test]
+fn instructions_take_a_cycle_by_default() {
+    let mut cpu = CPU::new();
+    cpu.set_memory(&COUNTER);
+    assert_eq!(cpu.get_timing(), Timing::Instructions);
+    cpu.set_dt(5);
+    let result = cpu.run_cycles(25);
+    assert_eq!(result.reason(), StopReason::CycleLimit);
+    assert_eq!(result.cycles(), 25);
+    assert_eq!(cpu.cycles_elapsed(), 25);
+    assert_eq!(cpu.get_dt(), 3);
+    // The rest of the third frame
+    assert_eq!(cpu.run_frame().cycles(), 5);
+    assert_eq!(cpu.get_dt(), 2);
+}
+
+#[test]
+fn vip_instruction_costs() {
+    let mut cpu = vip_cpu(&[
+        0x60, 0xFE, // 0x200 LD V0, 0xFE
+        0xF0, 0x33, // 0x202 LD B, V0
+        0xF3, 0x55, // 0x204 LD [I], V3
+        0x30, 0xFE, // 0x206 SE V0, 0xFE
+        0x00, 0x00, // 0x208
+        0x30, 0xFF, // 0x20A SE V0, 0xFF
+        0x61, 0x03, // 0x20C LD V1, 3
+        0xD1, 0x12, // 0x20E DRW V1, V1, 2
+        0xD1, 0x12, // 0x210 DRW V1, V1, 2
+        0x00, 0xE0, // 0x212 CLS
+    ]);
+    cpu.set_i(0x300);
+    assert_eq!(tick_cycles(&mut cpu), 46);
+    // BCD takes longer the bigger the digits
+    assert_eq!(tick_cycles(&mut cpu), 40 + 80 + 16 * (2 + 5 + 4));
+    // Stores take longer the more registers
+    assert_eq!(tick_cycles(&mut cpu), 40 + 14 + 14 * 4);
+    // Skipping takes longer than not skipping
+    assert_eq!(tick_cycles(&mut cpu), 54);
+    assert_eq!(tick_cycles(&mut cpu), 50);
+    assert_eq!(tick_cycles(&mut cpu), 46);
+    // Sprites cost by row and by how far they're shifted, and a little more
+    // when they collide
+    assert_eq!(tick_cycles(&mut cpu), 40 + 26 + 2 * (46 + 8 * 3));
+    assert_eq!(cpu.get_registers()[0xF], 0);
+    assert_eq!(tick_cycles(&mut cpu), 40 + 26 + 2 * (46 + 8 * 3) + 4);
+    assert_eq!(cpu.get_registers()[0xF], 1);
+    assert_eq!(tick_cycles(&mut cpu), 40 + 24 + 256 * 12);
+    assert_eq!(cpu.get_instruction_count(), 9);
+}
+
+#[test]
+fn vip_frames() {
+    let mut cpu = vip_cpu(&COUNTER);
+    cpu.set_dt(10);
+    // A frame has room for 18 loops of 102 cycles, and the display interrupt
+    // takes the rest
+    let result = cpu.run_frame();
+    assert_eq!(result.reason(), StopReason::CycleLimit);
+    assert_eq!(result.cycles(), 36);
+    assert_eq!(cpu.get_registers()[0], 18);
+    assert_eq!(cpu.cycles_elapsed(), 3668);
+    assert_eq!(cpu.get_dt(), 9);
+
+    let result = cpu.run_cycles(3668 * 3);
+    assert_eq!(result.cycles(), 36 * 3);
+    assert_eq!(cpu.get_dt(), 6);
+    // The instructions per frame setting doesn't matter
+    cpu.set_instructions_per_frame(1000);
+    assert_eq!(cpu.run_frame().cycles(), 36);
+}
+
+#[test]
+fn long_instructions_run_into_the_next_frame() {
+    let mut cpu = vip_cpu(&[
+        0x00, 0xE0, // 0x200 CLS
+        0x12, 0x00, // 0x202 JP 0x200
+    ]);
+    cpu.set_dt(10);
+    // CLS doesn't fit in a frame, so the next starts with what's left of it
+    assert_eq!(cpu.run_frame().cycles(), 1);
+    assert_eq!(cpu.get_dt(), 9);
+    assert_eq!(cpu.cycles_elapsed(), 3136 + 1832);
+    // JP fits in the rest, then the next CLS starts and fills two more
+    assert_eq!(cpu.run_frame().cycles(), 2);
+    assert_eq!(cpu.get_dt(), 7);
+}
+
+#[test]
+fn waits_idle_to_the_end_of_the_frame() {
+    let mut cpu = vip_cpu(&[
+        0xF0, 0x0A, // 0x200 LD V0, K
+    ]);
+    cpu.set_dt(10);
+    let result = cpu.run_cycles(3668 * 3);
+    assert_eq!(result.reason(), StopReason::WaitingForKey);
+    assert_eq!(cpu.cycles_elapsed(), 3668 * 3);
+    assert_eq!(cpu.get_dt(), 7);
+    // Part of a frame idles just as long as asked
+    cpu.run_cycles(100);
+    assert_eq!(cpu.cycles_elapsed(), 3668 * 3 + 100);
+    assert_eq!(cpu.get_dt(), 7);
+}
+
+#[test]
+fn breakpoints_stop_run_cycles() {
+    let mut cpu = vip_cpu(&COUNTER);
+    cpu.add_breakpoint(0x202);
+    let result = cpu.run_cycles(1000);
+    assert_eq!(result.reason(), StopReason::Breakpoint);
+    assert_eq!(result.cycles(), 1);
+    assert_eq!(cpu.cycles_elapsed(), 50);
+    cpu.clear_breakpoints();
+    // The rest of the frame
+    assert_eq!(cpu.run_frame().cycles(), 35);
+}
+
+#[test]
+fn cycles_are_saved_and_stepped_back() {
+    let mut cpu = vip_cpu(&COUNTER);
+    cpu.enable_history(1024);
+    cpu.tick();
+    cpu.tick();
+    assert_eq!(cpu.cycles_elapsed(), 102);
+    let mut loaded = CPU::new();
+    loaded.load_state(&cpu.save_state()).unwrap();
+    assert_eq!(loaded.get_timing(), Timing::CosmacVip);
+    assert_eq!(loaded.cycles_elapsed(), 102);
+    cpu.step_back();
+    assert_eq!(cpu.cycles_elapsed(), 50);
+    cpu.reset();
+    assert_eq!(cpu.cycles_elapsed(), 0);
+    assert_eq!(cpu.get_timing(), Timing::CosmacVip);
+}
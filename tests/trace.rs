@@ -0,0 +1,95 @@
+//! Tests for execution tracing.
+
+extern crate chip_8_emu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use chip_8_emu::{CPU, RegisterChange};
+
+fn cpu_with(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.set_memory(program);
+    cpu
+}
+
+const PROGRAM: [u8; 8] = [
+    0x6A, 0x2F, // 0x200 LD VA, 0x2F
+    0x7A, 0x01, // 0x202 ADD VA, 0x01
+    0xA3, 0x00, // 0x204 LD I, 0x300
+    0x12, 0x02, // 0x206 JP 0x202
+];
+
+#[test]
+fn buffer() {
+    let mut cpu = cpu_with(&PROGRAM);
+    cpu.tick();
+    assert!(cpu.take_trace().is_empty());
+    cpu.enable_trace_buffer(3);
+    for _ in 0..4 {
+        cpu.tick();
+    }
+    let trace = cpu.take_trace();
+    // Only the last 3 are kept
+    assert_eq!(trace.len(), 3);
+    assert_eq!(trace[0].cycle(), 1);
+    assert_eq!(trace[0].pc(), 0x204);
+    assert_eq!(trace[0].opcode(), 0xA300);
+    assert_eq!(trace[0].text(), "LD I, 0x300");
+    assert_eq!(trace[0].i(), 0x300);
+    assert!(trace[0].changes().is_empty());
+    assert_eq!(trace[2].changes(), [RegisterChange {
+        register: 0xA,
+        old_value: 0x30,
+        new_value: 0x31,
+    }]);
+    assert_eq!(trace[2].changes_js(), [0xA, 0x30, 0x31]);
+    assert_eq!(trace[2].to_string(),
+        "      3 0202: 7A01 ADD VA, 0x01 | VA 30->31 | I=0300 DT=00 ST=00");
+    assert!(cpu.take_trace().is_empty());
+    cpu.disable_trace();
+    cpu.tick();
+    assert!(cpu.take_trace().is_empty());
+}
+
+#[test]
+fn callback() {
+    let mut cpu = cpu_with(&PROGRAM);
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let sink = lines.clone();
+    cpu.trace_with(move |entry| sink.borrow_mut().push(entry.pc()));
+    for _ in 0..5 {
+        cpu.tick();
+    }
+    assert_eq!(*lines.borrow(), [0x200, 0x202, 0x204, 0x206, 0x202]);
+}
+
+#[test]
+fn filters() {
+    let mut cpu = cpu_with(&PROGRAM);
+    cpu.enable_trace_buffer(100);
+    cpu.set_trace_pc_range(0x202, 0x204);
+    for _ in 0..8 {
+        cpu.tick();
+    }
+    let pcs: Vec<u16> = cpu.take_trace().iter().map(|entry| entry.pc())
+        .collect();
+    assert_eq!(pcs, [0x202, 0x204, 0x202, 0x204, 0x202]);
+
+    cpu.clear_trace_pc_range();
+    // Just the jumps and additions
+    cpu.set_trace_opcode_classes(1 << 0x1 | 1 << 0x7);
+    for _ in 0..3 {
+        cpu.tick();
+    }
+    let opcodes: Vec<u16> = cpu.take_trace().iter()
+        .map(|entry| entry.opcode()).collect();
+    assert_eq!(opcodes, [0x1202, 0x7A01]);
+}
+
+#[test]
+fn failed_instructions_arent_traced() {
+    let mut cpu = cpu_with(&[0xFF, 0xFF]);
+    cpu.enable_trace_buffer(10);
+    assert!(cpu.try_tick().is_err());
+    assert!(cpu.take_trace().is_empty());
+}
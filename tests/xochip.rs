@@ -0,0 +1,141 @@
+//! Tests for the XO-CHIP extensions.
+
+extern crate chip_8_emu;
+use chip_8_emu::{CPU, CpuError, Platform};
+
+fn xochip_cpu(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new_with_platform(Platform::XoChip);
+    cpu.set_memory(program);
+    cpu.init_hex_sprites();
+    cpu
+}
+
+#[test]
+fn memory_size() {
+    assert_eq!(CPU::new().get_memory_size(), 0x1000);
+    assert_eq!(CPU::new().get_memory().len(), 0x1000);
+    assert_eq!(xochip_cpu(&[]).get_memory_size(), 0x10000);
+    { // The whole 64K is addressable
+        let mut cpu = xochip_cpu(&[
+            0xF0, 0x00, 0xFF, 0xFD, // LD I, 0xFFFD
+            0xF2, 0x55, // LD [I], V2
+        ]);
+        cpu.set_registers(&[1, 2, 3]);
+        cpu.tick();
+        assert_eq!(cpu.get_i(), 0xFFFD);
+        assert_eq!(cpu.get_pc(), 0x204);
+        cpu.tick();
+        assert_eq!(cpu.get_memory()[0xFFFD..], [1, 2, 3]);
+    }
+    { // But not past it
+        let mut cpu = xochip_cpu(&[0xF3, 0x55]); // LD [I], V3
+        cpu.set_i(0xFFFD);
+        assert_eq!(cpu.try_tick(),
+            Err(CpuError::MemoryOutOfBounds { addr: 0x10000 }));
+    }
+}
+
+#[test]
+fn xochip_opcodes_need_xochip() {
+    let mut cpu = CPU::new_with_platform(Platform::SuperChip);
+    cpu.set_memory(&[0xF0, 0x00, 0x12, 0x34]); // LD I, 0x1234
+    assert_eq!(cpu.try_tick(),
+        Err(CpuError::UnknownOpcode { pc: 0x200, opcode: 0xF000 }));
+}
+
+#[test]
+fn skip_long_load() {
+    let mut cpu = xochip_cpu(&[
+        0x30, 0x00, // SE V0, 0x00
+        0xF0, 0x00, 0x12, 0x34, // LD I, 0x1234
+        0x40, 0x00, // SNE V0, 0x00
+    ]);
+    cpu.tick();
+    assert_eq!(cpu.get_pc(), 0x206);
+    assert_eq!(cpu.get_i(), 0);
+}
+
+#[test]
+fn register_range_store_and_load() {
+    let mut cpu = xochip_cpu(&[
+        0x52, 0x42, // LD [I], V2-V4
+        0x54, 0x22, // LD [I], V4-V2
+        0x57, 0x93, // LD V7-V9, [I]
+        0x5D, 0xB3, // LD VD-VB, [I]
+    ]);
+    cpu.set_registers(&[0, 0, 2, 3, 4]);
+    cpu.set_i(0x300);
+    cpu.tick();
+    assert_eq!(cpu.get_memory()[0x300..0x303], [2, 3, 4]);
+    assert_eq!(cpu.get_i(), 0x300);
+    cpu.set_i(0x310);
+    cpu.tick();
+    assert_eq!(cpu.get_memory()[0x310..0x313], [4, 3, 2]);
+    cpu.set_i(0x300);
+    cpu.tick();
+    assert_eq!(cpu.get_registers()[7..10], [2, 3, 4]);
+    cpu.tick();
+    assert_eq!(cpu.get_registers()[0xB..0xE], [4, 3, 2]);
+    assert_eq!(cpu.get_i(), 0x300);
+}
+
+#[test]
+fn bitplanes() {
+    let mut program = vec![
+        0xF3, 0x01, // PLANE 3
+        0xD0, 0x01, // DRW V0, V0, 1
+        0xF2, 0x01, // PLANE 2
+        0x00, 0xE0, // CLS
+    ];
+    // Plane 1 gets the left pixel, plane 2 both
+    program.resize(0x100, 0);
+    program.extend_from_slice(&[0x80, 0xC0]);
+    let mut cpu = xochip_cpu(&program);
+    cpu.set_i(0x300);
+    cpu.tick();
+    assert_eq!(cpu.get_selected_planes(), 3);
+    cpu.tick();
+    assert_eq!(cpu.get_pixel_planes(0, 0), 0b11);
+    assert_eq!(cpu.get_pixel_planes(1, 0), 0b10);
+    assert_eq!(cpu.get_registers()[0xF], 0);
+    // Each combination of planes gets its own colour
+    assert_ne!(cpu.get_display()[0..3], cpu.get_display()[3..6]);
+    cpu.tick();
+    cpu.tick();
+    assert_eq!(cpu.get_pixel_planes(0, 0), 0b01);
+    assert_eq!(cpu.get_pixel_planes(1, 0), 0b00);
+}
+
+#[test]
+fn scroll_up() {
+    let mut cpu = xochip_cpu(&[
+        0x00, 0xE0, // CLS
+        0xD0, 0x11, // DRW V0, V1, 1
+        0x00, 0xD2, // SCU 2
+    ]);
+    cpu.set_registers(&[0, 5]);
+    cpu.tick();
+    cpu.tick();
+    assert_eq!(cpu.get_pixel_planes(0, 5), 1);
+    cpu.tick();
+    assert_eq!(cpu.get_pixel_planes(0, 5), 0);
+    assert_eq!(cpu.get_pixel_planes(0, 3), 1);
+}
+
+#[test]
+fn audio() {
+    let mut program = vec![
+        0xF0, 0x02, // AUDIO
+        0xF1, 0x3A, // PITCH V1
+    ];
+    program.resize(0x100, 0);
+    program.extend((0..16).map(|n| n * 0x11));
+    let mut cpu = xochip_cpu(&program);
+    cpu.set_registers(&[0, 0x70]);
+    cpu.set_i(0x300);
+    assert_eq!(cpu.get_pitch(), 64);
+    cpu.tick();
+    assert_eq!(cpu.get_audio_pattern()[..3], [0x00, 0x11, 0x22]);
+    cpu.tick();
+    assert_eq!(cpu.get_pitch(), 0x70);
+}
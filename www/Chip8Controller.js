@@ -1,4 +1,4 @@
-import { CPU } from 'chip8/chip_8_emu';
+import { CPU, CpuState, StopReason } from 'chip8/chip_8_emu';
 import DisplayRenderer from './DisplayRenderer';
 import MemoryRenderer from './MemoryRenderer';
 import RegisterRenderer from './RegisterRenderer';
@@ -24,14 +24,22 @@ export default class Chip8Controller {
       0x81, 0x81, 0xff, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xff, 0xff];
 
     this.cpu = CPU.new();
+    // A fresh seed each page load, while keeping save states reproducible
+    this.cpu.set_rng_seed(BigInt(Date.now()));
+    // Keep enough history to step back through a few seconds of play
+    this.cpu.enable_history(16 * 1024 * 1024);
 
     this.displayRenderer = new DisplayRenderer(
       this.cpu.get_display_pointer(),
+      () => ({
+        width: this.cpu.get_display_width(),
+        height: this.cpu.get_display_height(),
+      }),
       this.elements.output.display,
     );
 
     this.memoryRenderer = new MemoryRenderer(
-      this.cpu.get_memory_pointer(),
+      this.cpu,
       this.elements.output.memory,
     );
 
@@ -50,14 +58,16 @@ export default class Chip8Controller {
     this.inputHandler = new InputHandler(this.cpu);
 
     this.ticksPerFrame = 10; // 10 ticks per frame * 60 fps = 600 tps
+    this.turboTicksPerFrame = 100;
     this.running = true;
     this.turbo = true;
+    this.cpu.set_instructions_per_frame(this.turboTicksPerFrame);
 
     this.setupButtons(elements.button, elements.input);
 
     this.renderLoop = () => {
       if (this.running) {
-        this.stepCpu(this.ticksPerFrame * this.turbo ? 100 : 1);
+        this.runFrames();
       }
       requestAnimationFrame(this.renderLoop);
     };
@@ -66,17 +76,73 @@ export default class Chip8Controller {
     this.render();
   }
 
-  stepCpu(ticks = 1) {
-    for (let i = 0; i < ticks; i++) {
-      this.cpu.tick_timers(BigInt(Date.now()));
-      this.cpu.tick();
+  // Run the frames due since the last animation frame
+  runFrames() {
+    const result = this.cpu.run_realtime(BigInt(Date.now()));
+    if (result) {
+      this.handleResult(result);
     }
+  }
+
+  stepCpu() {
+    this.handleResult(this.cpu.run(1));
+  }
+
+  handleResult(result) {
+    switch (result.reason()) {
+      case StopReason.Error:
+        this.handleError(result.error());
+        break;
+      case StopReason.Breakpoint:
+      case StopReason.Condition:
+        this.handleBreakpoint(result.pc());
+        break;
+      case StopReason.Watchpoint:
+        this.handleWatchpoint(result.watchpoint());
+        break;
+      default:
+        break;
+    }
+    result.free();
     this.displayRenderer.setDirtyFlag();
     this.render();
   }
 
+  handleError(error) {
+    if (this.running) {
+      this.toggleRun();
+    }
+    // eslint-disable-next-line no-console
+    console.error(`CPU halted at 0x${error.pc().toString(16)}: ${error.message()}`);
+  }
+
+  handleBreakpoint(pc) {
+    if (this.running) {
+      this.toggleRun();
+    }
+    // eslint-disable-next-line no-console
+    console.info(`Breakpoint hit at 0x${pc.toString(16)}`);
+  }
+
+  handleWatchpoint(hit) {
+    if (this.running) {
+      this.toggleRun();
+    }
+    const hex = (value) => `0x${value.toString(16)}`;
+    // eslint-disable-next-line no-console
+    console.info(`Watchpoint hit at ${hex(hit.addr())} by ${hex(hit.pc())}: `
+      + `${hex(hit.old_value())} -> ${hex(hit.new_value())}`);
+    hit.free();
+  }
+
   render() {
+    // Colour the framebuffer into the buffer the display texture reads
+    this.cpu.render_display();
     this.displayRenderer.render();
+    this.elements.output.display.classList.toggle(
+      'waiting-for-key',
+      this.cpu.get_state() === CpuState.WaitingForKey,
+    );
     this.registerRenderer.render();
     const pc = this.cpu.get_pc();
     this.memoryRenderer.render(pc);
@@ -93,6 +159,9 @@ export default class Chip8Controller {
 
   toggleTurbo() {
     this.turbo = !this.turbo;
+    this.cpu.set_instructions_per_frame(
+      this.turbo ? this.turboTicksPerFrame : this.ticksPerFrame,
+    );
     if (this.turbo) {
       this.elements.button.turbo.classList.add('active');
     } else {
@@ -107,10 +176,38 @@ export default class Chip8Controller {
     this.stepCpu();
   }
 
+  stepBack() {
+    if (this.running) {
+      this.toggleRun();
+    }
+    this.cpu.step_back();
+    this.displayRenderer.setDirtyFlag();
+    this.render();
+  }
+
   reset() {
     this.cpu.reset();
     this.cpu.init_hex_sprites();
     this.cpu.load_program_memory(this.currentRom);
+    this.inputHandler.setRom(this.currentRom);
+    this.render();
+  }
+
+  saveState() {
+    this.quickSave = this.cpu.save_state();
+  }
+
+  restoreState() {
+    if (!this.quickSave) {
+      return;
+    }
+    try {
+      this.cpu.load_state(this.quickSave);
+    } catch (error) {
+      // eslint-disable-next-line no-console
+      console.error(`Couldn't restore state: ${error}`);
+    }
+    this.displayRenderer.setDirtyFlag();
     this.render();
   }
 
@@ -138,9 +235,12 @@ export default class Chip8Controller {
   setupButtons(buttons, inputs) {
     buttons.turbo.addEventListener('click', () => { this.toggleTurbo(); });
     buttons.start.addEventListener('click', () => { this.toggleRun(); });
+    buttons.back.addEventListener('click', () => { this.stepBack(); });
     buttons.step.addEventListener('click', () => { this.stepSim(); });
     buttons.reset.addEventListener('click', () => { this.reset(); });
     buttons.load.addEventListener('click', () => { this.triggerLoad(); });
+    buttons.saveState.addEventListener('click', () => { this.saveState(); });
+    buttons.restoreState.addEventListener('click', () => { this.restoreState(); });
     inputs.romFile.addEventListener('change', () => { this.handleLoad(); });
   }
 }
@@ -4,14 +4,13 @@ import * as THREE from 'three';
 import { memory } from 'chip8/chip_8_emu_bg';
 
 export default class DisplayRenderer {
-  constructor(displayPtr, container) {
+  constructor(displayPtr, getSize, container) {
     this.displayPtr = displayPtr;
+    this.getSize = getSize;
 
     this.fov = 65.3;
     this.near = 0.1;
     this.far = 1000;
-    this.pixelWidth = 64;
-    this.pixelHeight = 32;
 
     // Create Renderer
     this.renderer = new THREE.WebGLRenderer();
@@ -25,13 +24,30 @@ export default class DisplayRenderer {
     );
 
     this.scene = new THREE.Scene();
+    this.camera.position.set(0, 0, 25);
+
+    this.createPlane();
+  }
+
+  // (Re)build the textured plane for the emulator's current resolution. The
+  // plane is always 64x32 units so hires mode just packs in more pixels.
+  createPlane() {
+    if (this.plane) {
+      this.scene.remove(this.plane);
+      this.texture.dispose();
+    }
+
+    const { width, height } = this.getSize();
+    this.pixelWidth = width;
+    this.pixelHeight = height;
+
     const emuDisplayBuffer = new Uint8Array(
       memory.buffer,
-      displayPtr,
+      this.displayPtr,
       this.pixelWidth * this.pixelHeight * 3,
     );
 
-    const geometry = new THREE.PlaneGeometry(this.pixelWidth, this.pixelHeight);
+    const geometry = new THREE.PlaneGeometry(64, 32);
     this.texture = new THREE.DataTexture(
       emuDisplayBuffer,
       this.pixelWidth,
@@ -40,10 +56,8 @@ export default class DisplayRenderer {
     );
     this.texture.flipY = true;
     const material = new THREE.MeshBasicMaterial({ map: this.texture, side: THREE.DoubleSide });
-    const plane = new THREE.Mesh(geometry, material);
-    this.scene.add(plane);
-
-    this.camera.position.set(0, 0, 25);
+    this.plane = new THREE.Mesh(geometry, material);
+    this.scene.add(this.plane);
   }
 
   height() {
@@ -55,6 +69,12 @@ export default class DisplayRenderer {
   }
 
   setDirtyFlag() {
+    const { width, height } = this.getSize();
+    // Growing the wasm memory detaches the buffer the texture was viewing
+    if (width !== this.pixelWidth || height !== this.pixelHeight
+      || this.texture.image.data.buffer !== memory.buffer) {
+      this.createPlane();
+    }
     this.texture.needsUpdate = true;
   }
 
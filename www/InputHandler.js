@@ -1,20 +1,22 @@
-const ALLOWED_KEYS = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A',
-  'B', 'C', 'D', 'E', 'F'];
+import { Keymap } from 'chip8/chip_8_emu';
 
 export default class InputHandler {
   constructor(cpu) {
     this.cpu = cpu;
+    // Each hex digit presses the key it names
+    this.baseKeymap = Keymap.hex();
+    this.keymap = this.baseKeymap.for_rom([]);
     document.addEventListener('keydown', (event) => {
-      const key = event.key.toUpperCase();
-      if (ALLOWED_KEYS.includes(key)) {
-        cpu.set_key_down(parseInt(key, 16));
-      }
+      this.keymap.press(this.cpu, event.key);
     });
     document.addEventListener('keyup', (event) => {
-      const key = event.key.toUpperCase();
-      if (ALLOWED_KEYS.includes(key)) {
-        cpu.set_key_up(parseInt(key, 16));
-      }
+      this.keymap.release(this.cpu, event.key);
     });
   }
+
+  // Switch to the keys for rom, with any bindings the keymap has just for it
+  setRom(rom) {
+    this.keymap.free();
+    this.keymap = this.baseKeymap.for_rom(rom);
+  }
 }
@@ -1,62 +1,45 @@
-import { memory } from 'chip8/chip_8_emu_bg';
+import { Syntax } from 'chip8/chip_8_emu';
 
-const BYTES_PER_ROW = 8;
+// Instructions to show before and after the one at PC
+const INSTRUCTIONS_BEFORE = 8;
+const INSTRUCTIONS_SHOWN = 24;
 
-function getRowLabelDiv(address) {
-  const start = address.toString(16).padStart(4, '0');
-  const end = (address + BYTES_PER_ROW - 1).toString(16).padStart(4, '0');
-  return `
-      <div class="memory-address">
-        ${start}..${end}:
-      </div>
-  `;
+function hex(value, digits) {
+  return value.toString(16).padStart(digits, '0');
 }
 
-function getMemoryRowDiv(rowMemory, startAddress, pc) {
+function getInstructionRowDiv(instruction, pc, syntax) {
+  const address = instruction.address();
   const html = [
     '<div class="row">',
-    getRowLabelDiv(startAddress)];
-
-  // Get the pc value relative to this memory block
-  const offsetPC = pc - startAddress;
-  // For each byte, add it to the html array
-  for (let offset = 0; offset < rowMemory.length; offset += 1) {
-    html.push('<div class="memory-byte');
-    // If this address is at the current PC or PC+1, mark it as active
-    if (offset === offsetPC || offset === offsetPC + 1) {
-      html.push(' memory-byte-active');
-    }
-    html.push('">', rowMemory[offset].toString(16).padStart(2, '0'), '</div>');
+    `<div class="memory-address">${hex(address, 4)}:</div>`,
+    `<div class="memory-byte">${hex(instruction.opcode(), 4)}</div>`,
+    '<div class="memory-instruction'];
+  // Mark the instruction at the current PC as active
+  if (address === pc) {
+    html.push(' memory-byte-active');
   }
-  html.push('</div>');
+  html.push('">', instruction.text(syntax), '</div>', '</div>');
   return html.join('');
 }
 
-function getDisplayBounds(pc) {
-  const rowStart = pc - (pc % BYTES_PER_ROW);
-  return {
-    from: Math.max(rowStart - 56, 0),
-    to: Math.min(rowStart + 64, 4096),
-  };
-}
-
 export default class MemoryRenderer {
-  constructor(memoryPtr, targetDiv, memoryLen = 4096) {
+  constructor(cpu, targetDiv, syntax = Syntax.Cowgod) {
+    this.cpu = cpu;
     this.targetDiv = targetDiv;
-    this.memory = new Uint8Array(
-      memory.buffer,
-      memoryPtr,
-      memoryLen,
-    );
+    this.syntax = syntax;
   }
 
   render(pc) {
-    const bounds = getDisplayBounds(pc);
-    const output = [];
-    // Loop over each row of BYTES_PER_ROW bytes
-    for (let i = bounds.from; i < bounds.to; i += BYTES_PER_ROW) {
-      output.push(getMemoryRowDiv(this.memory.slice(i, i + BYTES_PER_ROW), i, pc));
-    }
+    // Instructions can't be decoded backwards, so start a fixed distance
+    // before PC on the same alignment and hope it lines up
+    const from = Math.max(pc - INSTRUCTIONS_BEFORE * 2, pc % 2);
+    const instructions = this.cpu.disassemble_at(from, INSTRUCTIONS_SHOWN);
+    const output = instructions.map((instruction) => {
+      const row = getInstructionRowDiv(instruction, pc, this.syntax);
+      instruction.free();
+      return row;
+    });
     this.targetDiv.innerHTML = output.join('');
   }
 }
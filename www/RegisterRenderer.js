@@ -22,20 +22,31 @@ function renderLabelledTable(tableData) {
 export default class RegisterRenderer {
   constructor(stackPointer, gpRegisterPointer, getRegisterValues, output) {
     this.output = output;
+    this.stackPointer = stackPointer;
+    this.gpRegisterPointer = gpRegisterPointer;
+    this.getRegisterValues = getRegisterValues;
+    this.createViews();
+  }
+
+  // Growing the wasm memory detaches the old buffer, leaving these empty, so
+  // they're made again whenever that happens
+  createViews() {
     this.gpRegisters = new Uint8Array(
       memory.buffer,
-      gpRegisterPointer,
+      this.gpRegisterPointer,
       16,
     );
-    this.getRegisterValues = getRegisterValues;
     this.stack = new Uint16Array(
       memory.buffer,
-      stackPointer,
+      this.stackPointer,
       16,
     );
   }
 
   render() {
+    if (this.stack.buffer !== memory.buffer) {
+      this.createViews();
+    }
     this.output.stack.innerHTML = renderTable(this.stack, 4);
     this.output.gpRegister.innerHTML = renderTable(this.gpRegisters, 2);
     this.output.sRegister.innerHTML = renderLabelledTable(this.getRegisterValues());
@@ -13,9 +13,12 @@ const elements = {
   button: {
     turbo: document.getElementById('button-turbo'),
     start: document.getElementById('button-start'),
+    back: document.getElementById('button-back'),
     step: document.getElementById('button-step'),
     reset: document.getElementById('button-reset'),
     load: document.getElementById('button-load'),
+    saveState: document.getElementById('button-save-state'),
+    restoreState: document.getElementById('button-restore-state'),
   },
   input: {
     romFile: document.getElementById('input-rom-file'),